@@ -30,3 +30,13 @@ pub fn quote_traits(trait_var: &str, trait_list: &[String]) -> proc_macro2::Toke
     quoted.append_all(quote!(,));
     quoted
 }
+
+/// The `approck::server::cors::RouteCors` for a route's `cors` instruction
+pub fn quote_route_cors(cors: &crate::Cors) -> proc_macro2::TokenStream {
+    match cors {
+        crate::Cors::Off => quote!(approck::server::cors::RouteCors::Off),
+        crate::Cors::Origins(origins) => {
+            quote!(approck::server::cors::RouteCors::Origins(&[#(#origins),*]))
+        }
+    }
+}
@@ -7,6 +7,7 @@ use syn::Ident;
 
 mod path;
 mod post_form;
+mod post_json;
 mod query_string;
 
 use crate::ParamType;
@@ -81,6 +82,9 @@ fn param_token_stream(param: &crate::Param) -> proc_macro2::TokenStream {
         ParamType::QueryStringOption => quote! { #name: Option<QueryString>, },
         ParamType::PostForm => quote! { #name: PostForm, },
         ParamType::PostFormOption => quote! { #name: Option<PostForm>, },
+        ParamType::PostJson => quote! { #name: PostJson, },
+        ParamType::PostJsonOption => quote! { #name: Option<PostJson>, },
+        ParamType::Session => quote! { #name: Session, },
         ParamType::OptionalParam(t) => {
             let t = Ident::new(t, proc_macro2::Span::call_site());
             quote! { #name: Option<#t>, }
@@ -100,6 +104,73 @@ pub fn expand(mod_bundle: crate::HttpModuleInner) -> TokenStream {
         };
     }
 
+    // --------------------------------------------------------------------------------------------
+    // name the route, so metrics are counted per module rather than per url
+
+    codegen_tokens.wrap_fn_items(quote! {
+        req.set_route(module_path!());
+    });
+
+    // --------------------------------------------------------------------------------------------
+    // handle `cors = ...;` (first, so every response from the route carries the headers)
+
+    if let Some(cors) = &mod_bundle.cors {
+        let route_cors = crate::codegen::quote_route_cors(cors);
+        codegen_tokens.wrap_fn_items(quote! {
+            req.set_cors(#route_cors);
+        });
+    }
+
+    // --------------------------------------------------------------------------------------------
+    // handle `rate_limit = ...;` (first, so refused requests cost as little as possible)
+
+    if let Some(rate_limit) = &mod_bundle.rate_limit {
+        let requests = rate_limit.requests;
+        let period_secs = rate_limit.period_secs;
+        let per = match rate_limit.per {
+            crate::RateLimitPer::Ip => quote! { ::approck::server::rate_limit::Per::Ip },
+            crate::RateLimitPer::Session => quote! { ::approck::server::rate_limit::Per::Session },
+        };
+        codegen_tokens.wrap_fn_items(quote! {
+            {
+                const RATE_LIMIT: ::approck::server::rate_limit::RateLimit =
+                    ::approck::server::rate_limit::RateLimit {
+                        route: module_path!(),
+                        requests: #requests,
+                        period: ::std::time::Duration::from_secs(#period_secs),
+                        per: #per,
+                    };
+                if let Some(response) = req.check_rate_limit(&RATE_LIMIT).await? {
+                    return Ok(response);
+                }
+            }
+        });
+    }
+
+    // --------------------------------------------------------------------------------------------
+    // handle `max_body_size = ...;` (must come before anything reads the body)
+
+    if let Some(max_body_size) = mod_bundle.max_body_size {
+        codegen_tokens.wrap_fn_items(quote! {
+            req.set_max_body_size(#max_body_size);
+        });
+    }
+
+    // --------------------------------------------------------------------------------------------
+    // handle `timeout = ...;` (the deadline is counted from the start of the request)
+
+    if let Some(timeout) = &mod_bundle.timeout {
+        let timeout = match timeout {
+            crate::Timeout::Off => quote! { None },
+            crate::Timeout::Millis(millis) => {
+                quote! { Some(::std::time::Duration::from_millis(#millis)) }
+            }
+        };
+        codegen_tokens.wrap_fn_items(quote! {
+            req.set_timeout(#timeout);
+        });
+    }
+
     // --------------------------------------------------------------------------------------------
     // handle `Path`
 
@@ -116,7 +187,10 @@ pub fn expand(mod_bundle: crate::HttpModuleInner) -> TokenStream {
     match &mod_bundle.mod_post_type {
         crate::PostType::None => {}
         crate::PostType::Struct(post_type_struct) => {
-            self::post_form::process(&mut codegen_tokens, post_type_struct);
+            self::post_form::process(&mut codegen_tokens, post_type_struct, mod_bundle.csrf);
+        }
+        crate::PostType::Json(post_type_json) => {
+            self::post_json::process(&mut codegen_tokens, post_type_json);
         }
         crate::PostType::Enum(_) => {
             unimplemented!("enum post types are not yet supported");
@@ -180,6 +254,19 @@ pub fn expand(mod_bundle: crate::HttpModuleInner) -> TokenStream {
                 });
             }
 
+            // the session handle is shared with the server, which saves it after the request
+            crate::ParamType::Session => {
+                codegen_tokens.mod_items(quote! {
+                    use ::approck::server::session::Session;
+                });
+                codegen_tokens.wrap_fn_items(quote! {
+                    let session = req.session();
+                });
+                codegen_tokens.request_fn_params(quote! {
+                    session,
+                });
+            }
+
             // handle QueryString
             crate::ParamType::QueryString => {
                 codegen_tokens.wrap_fn_items(quote! {
@@ -239,6 +326,36 @@ pub fn expand(mod_bundle: crate::HttpModuleInner) -> TokenStream {
                 });
             }
 
+            // handler PostJson
+            crate::ParamType::PostJson => {
+                codegen_tokens.wrap_fn_items(quote! {
+                    let post_json = PostJson::parse(&mut req).await.amend(|e| e
+                        .set_uri(req.uri_string())
+                        .add_context("PostJson::parse()")
+                    )?;
+                });
+                codegen_tokens.request_fn_params(quote! {
+                    post_json,
+                });
+            }
+
+            // handler Option<PostJson>, only parsed when a JSON body was sent
+            crate::ParamType::PostJsonOption => {
+                codegen_tokens.wrap_fn_items(quote! {
+                    let post_json = if req.is_json() {
+                        Some(PostJson::parse(&mut req).await.amend(|e| e
+                            .set_uri(req.uri_string())
+                            .add_context("PostJson::parse()")
+                        )?)
+                    } else {
+                        None
+                    };
+                });
+                codegen_tokens.request_fn_params(quote! {
+                    post_json,
+                });
+            }
+
             // any other type is an Option gets None
             crate::ParamType::OptionalParam(_) => {
                 codegen_tokens.request_fn_params(quote! {
@@ -403,6 +520,18 @@ pub fn expand(mod_bundle: crate::HttpModuleInner) -> TokenStream {
             });
         }
 
+        if mod_bundle.return_types.SSE {
+            mods.push(quote! {
+                use ::approck::server::response::{SseEvent, SSE};
+            });
+            variants.push(quote! {
+                SSE(approck::server::response::SSE),
+            });
+            arms.push(quote! {
+                Response::SSE(v) => approck::server::response::Response::SSE(v),
+            });
+        }
+
         // generate actual code
         codegen_tokens.mod_items(quote! {
             #(#mods)*
@@ -1,23 +1,32 @@
-use crate::QueryStringValue;
+use crate::{QueryStringValue, QueryStringValueType};
 use quote::quote;
 /*
-    Processing a query string involves 3 steps:
+    Processing a post form involves 3 steps:
 
-    1. create Option<Type> local variables for each query string part
-    2. iterate over the query string, assigning each part to the local variable
-    3. create a QueryString struct to hold the local variables
+    1. create Option<Type> local variables for each post form part
+    2. iterate over the body (multipart or urlencoded), assigning each part to the local variable
+    3. create a PostForm struct to hold the local variables
 
+    Unless the route says `csrf = off;`, the CSRF token is checked against the session before
+    anything it protects is read: an `X-CSRF-Token` header before the body, a multipart `_csrf`
+    part as soon as it arrives (and before any file part, so a forged upload is never written
+    to disk), and an urlencoded `_csrf` once the body is parsed.
+
+    Every other local is prefixed with `__approck_`, so no post form part can shadow it.
 
 */
 
 pub fn process(
     codegen_tokens: &mut super::CodeGenTokens,
     post_type_struct: &crate::PostTypeStruct,
+    csrf: bool,
 ) {
     let mut struct_assign = Vec::new();
     let mut var_assign = Vec::new();
     let mut match_arms = Vec::new();
+    let mut multipart_match_arms = Vec::new();
     let mut validate_stmts = Vec::new();
+    let mut has_uploaded_file = false;
 
     let qs_parts = &post_type_struct.query_string_parts;
 
@@ -25,8 +34,29 @@ pub fn process(
         let name_string = syn::LitStr::new(&qs_part.name, proc_macro2::Span::call_site());
         let name_ident = syn::Ident::new(&qs_part.name, proc_macro2::Span::call_site());
 
+        let max_size = post_type_struct
+            .max_sizes
+            .iter()
+            .find(|(name, _)| *name == qs_part.name)
+            .map(|(_, max_size)| *max_size);
+
+        // urlencoded values are already in memory, so the limit is checked after the fact
+        let size_check = match max_size {
+            Some(max_size) => quote! {
+                if __approck_value.len() as u64 > #max_size {
+                    return Err(approck::Error::new(approck::ErrorKind::PayloadTooLarge)
+                        .add_context(format!("post form field '{}' is larger than the maximum of {} bytes", #name_string, #max_size)));
+                }
+            },
+            None => quote! {},
+        };
+        let max_size = match max_size {
+            Some(max_size) => quote! { Some(#max_size) },
+            None => quote! { None },
+        };
+
         // handle validation
-        match &qs_part.value {
+        let (value_type, assign) = match &qs_part.value {
             QueryStringValue::Require(query_string_value_type) => {
                 let value_type = &query_string_value_type.get_type_token_stream();
 
@@ -34,21 +64,21 @@ pub fn process(
                     let mut #name_ident: Option<#value_type> = None;
                 });
 
-                let parsing_code = get_parse_token_stream(query_string_value_type);
-                match_arms.push(quote! {
-                    #name_string => {
-                        #name_ident = Some(#parsing_code);
-                    }
-                });
-
                 validate_stmts.push(quote! {
                     let #name_ident = match #name_ident {
-                        Some(v) => v,
+                        Some(__approck_value) => __approck_value,
                         None => {
-                            panic!("query string part '{}' is required", #name_string);
+                            return Err(approck::Error::new(approck::ErrorKind::Validation)
+                                .add_context(format!("post form field '{}' is required", #name_string)));
                         }
                     };
                 });
+
+                let parsing_code = get_parse_token_stream(query_string_value_type);
+                (
+                    query_string_value_type,
+                    quote! { #name_ident = Some(#parsing_code); },
+                )
             }
             QueryStringValue::Option(query_string_value_type) => {
                 let value_type = &query_string_value_type.get_type_token_stream();
@@ -58,11 +88,10 @@ pub fn process(
                 });
 
                 let parsing_code = get_parse_token_stream(query_string_value_type);
-                match_arms.push(quote! {
-                    #name_string => {
-                        #name_ident = Some(#parsing_code);
-                    }
-                });
+                (
+                    query_string_value_type,
+                    quote! { #name_ident = Some(#parsing_code); },
+                )
             }
             QueryStringValue::Vec(query_string_value_type) => {
                 let value_type = &query_string_value_type.get_type_token_stream();
@@ -72,11 +101,10 @@ pub fn process(
                 });
 
                 let parsing_code = get_parse_token_stream(query_string_value_type);
-                match_arms.push(quote! {
-                    #name_string => {
-                        #name_ident.push(#parsing_code);
-                    }
-                });
+                (
+                    query_string_value_type,
+                    quote! { #name_ident.push(#parsing_code); },
+                )
             }
             QueryStringValue::HashSet(query_string_value_type) => {
                 let value_type = &query_string_value_type.get_type_token_stream();
@@ -86,20 +114,54 @@ pub fn process(
                 });
 
                 let parsing_code = get_parse_token_stream(query_string_value_type);
-                match_arms.push(quote! {
-                    #name_string => {
-                        #name_ident.insert(#parsing_code);
-                    }
-                });
+                (
+                    query_string_value_type,
+                    quote! { #name_ident.insert(#parsing_code); },
+                )
             }
             QueryStringValue::NoValue => {
                 var_assign.push(quote! {
                     let mut #name_ident: bool = false;
                 });
 
+                (
+                    &QueryStringValueType::String,
+                    quote! { #name_ident = true; },
+                )
+            }
+        };
+
+        match value_type {
+            // files can only arrive in a multipart body
+            QueryStringValueType::UploadedFile => {
+                has_uploaded_file = true;
+                let csrf_guard = match csrf {
+                    true => quote! {
+                        if !__approck_csrf_verified {
+                            __approck_req.verify_csrf_token(None).await?;
+                        }
+                    },
+                    false => quote! {},
+                };
+                multipart_match_arms.push(quote! {
+                    Some(#name_string) => {
+                        #csrf_guard
+                        let __approck_value = __approck_field.file(#max_size).await?;
+                        #assign
+                    }
+                });
+            }
+            _ => {
                 match_arms.push(quote! {
                     #name_string => {
-                        #name_ident = true;
+                        #size_check
+                        #assign
+                    }
+                });
+                multipart_match_arms.push(quote! {
+                    Some(#name_string) => {
+                        let __approck_value = __approck_field.text(#max_size).await?;
+                        #assign
                     }
                 });
             }
@@ -110,6 +172,49 @@ pub fn process(
         });
     }
 
+    // the token never lands in the struct
+    let (csrf_header_verify, csrf_verify) = if csrf {
+        var_assign.push(quote! {
+            let mut __approck_csrf_token: Option<String> = None;
+            let mut __approck_csrf_verified = false;
+        });
+        match_arms.push(quote! {
+            "_csrf" => {
+                __approck_csrf_token = Some(__approck_value);
+            }
+        });
+        multipart_match_arms.push(quote! {
+            Some("_csrf") => {
+                if !__approck_csrf_verified {
+                    let __approck_value = __approck_field.text(Some(1024)).await?;
+                    __approck_req.verify_csrf_token(Some(&__approck_value)).await?;
+                    __approck_csrf_verified = true;
+                }
+            }
+        });
+        (
+            quote! {
+                if __approck_req.headers().contains_key(::approck::server::csrf::HEADER_NAME) {
+                    __approck_req.verify_csrf_token(None).await?;
+                    __approck_csrf_verified = true;
+                }
+            },
+            quote! {
+                if !__approck_csrf_verified {
+                    __approck_req.verify_csrf_token(__approck_csrf_token.as_deref()).await?;
+                }
+            },
+        )
+    } else {
+        (quote! {}, quote! {})
+    };
+
+    if has_uploaded_file {
+        codegen_tokens.mod_items(quote! {
+            use ::approck::server::multipart::UploadedFile;
+        });
+    }
+
     let struct_tokens = &post_type_struct.token_stream;
 
     codegen_tokens.mod_items(quote! {
@@ -117,19 +222,38 @@ pub fn process(
         #struct_tokens
 
         impl PostForm {
-            pub async fn parse(req: &mut approck::server::Request<'_>) -> approck::Result<Self> {
-                // create mutable default values for all query string elements
+            pub async fn parse(__approck_req: &mut approck::server::Request<'_>) -> approck::Result<Self> {
+                // create mutable default values for all post form elements
                 #( #var_assign )*
 
-                // iterate over the incomking key=value pairs and match them into the right mutable element
-                for (k,v) in req.read_body_query_pairs().await {
-                    match k.as_str() {
-                        #( #match_arms )*
-                        // key doesn't match any registered query string part
-                        _ => {}
+                // reject forged requests before reading the body, when the token is in a header
+                #csrf_header_verify
+
+                if __approck_req.is_multipart() {
+                    // iterate over the fields as they stream in and match them into the right mutable element
+                    let mut __approck_multipart = __approck_req.multipart()?;
+                    while let Some(__approck_field) = __approck_multipart.next_field().await? {
+                        let __approck_field_name = __approck_field.name().map(str::to_string);
+                        match __approck_field_name.as_deref() {
+                            #( #multipart_match_arms )*
+                            // field doesn't match any registered post form part
+                            _ => {}
+                        }
+                    }
+                } else {
+                    // iterate over the incomking key=value pairs and match them into the right mutable element
+                    for (__approck_key, __approck_value) in __approck_req.read_body_query_pairs().await? {
+                        match __approck_key.as_str() {
+                            #( #match_arms )*
+                            // key doesn't match any registered post form part
+                            _ => {}
+                        }
                     }
                 }
 
+                // reject forged requests before looking at what they submitted
+                #csrf_verify
+
                 // Some require additional validation and transformation, e.g. if required
                 #( #validate_stmts )*
 
@@ -147,12 +271,13 @@ pub fn get_parse_token_stream(
 ) -> proc_macro2::TokenStream {
     use crate::QueryStringValueType;
     match query_string_value_type {
-        QueryStringValueType::String => quote! { v.to_string() },
-        QueryStringValueType::i32 => quote! { v.parse()? },
-        QueryStringValueType::u32 => quote! { v.parse()? },
-        QueryStringValueType::i64 => quote! { v.parse()? },
-        QueryStringValueType::u64 => quote! { v.parse()? },
-        QueryStringValueType::f32 => quote! { v.parse()? },
-        QueryStringValueType::f64 => quote! { v.parse()? },
+        QueryStringValueType::String => quote! { __approck_value.to_string() },
+        QueryStringValueType::i32 => quote! { __approck_value.parse()? },
+        QueryStringValueType::u32 => quote! { __approck_value.parse()? },
+        QueryStringValueType::i64 => quote! { __approck_value.parse()? },
+        QueryStringValueType::u64 => quote! { __approck_value.parse()? },
+        QueryStringValueType::f32 => quote! { __approck_value.parse()? },
+        QueryStringValueType::f64 => quote! { __approck_value.parse()? },
+        QueryStringValueType::UploadedFile => quote! { __approck_value },
     }
 }
@@ -0,0 +1,26 @@
+use quote::quote;
+
+/*
+    A PostJson struct is deserialized as a whole by serde, so unlike PostForm there is nothing to
+    generate per field:
+
+    1. place the original struct with a serde Deserialize derive
+    2. implement PostJson::parse() to read the body as JSON
+*/
+
+pub fn process(codegen_tokens: &mut super::CodeGenTokens, post_type_json: &crate::PostTypeJson) {
+    let struct_tokens = &post_type_json.token_stream;
+
+    codegen_tokens.mod_items(quote! {
+        // Place the original struct verbatim, deserialized through approck's serde re-export
+        #[derive(::approck::server::exports::serde::Deserialize)]
+        #[serde(crate = "::approck::server::exports::serde")]
+        #struct_tokens
+
+        impl PostJson {
+            pub async fn parse(req: &mut approck::server::Request<'_>) -> approck::Result<Self> {
+                req.read_body_json().await
+            }
+        }
+    });
+}
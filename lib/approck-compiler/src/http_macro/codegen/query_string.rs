@@ -171,5 +171,8 @@ pub fn get_parse_token_stream(
         QueryStringValueType::u64 => quote! { v.parse()? },
         QueryStringValueType::f32 => quote! { v.parse()? },
         QueryStringValueType::f64 => quote! { v.parse()? },
+        QueryStringValueType::UploadedFile => {
+            unreachable!("UploadedFile is only allowed in a PostForm")
+        }
     }
 }
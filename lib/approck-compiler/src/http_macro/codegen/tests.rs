@@ -1 +1,156 @@
+use proc_macro2::{Delimiter, TokenStream, TokenTree};
+use quote::quote;
 
+/// Expand a POST route whose `PostForm` has a part called `name`
+fn expand_post_form(name: &str) -> TokenStream {
+    let name = syn::Ident::new(name, proc_macro2::Span::call_site());
+    expand_post_form_fields(quote! { #name: String, })
+}
+
+/// Expand a POST route whose `PostForm` has `fields`
+fn expand_post_form_fields(fields: TokenStream) -> TokenStream {
+    let item_mod: syn::ItemMod = syn::parse2(quote! {
+        pub mod page {
+            pub struct PostForm {
+                #fields
+            }
+
+            pub async fn request(form: PostForm) -> Response {
+                Response::Empty
+            }
+        }
+    })
+    .unwrap();
+
+    let inner = match crate::http_macro::parse_http_module_inner(
+        quote! { POST /; return Empty; },
+        item_mod,
+    ) {
+        Ok(inner) => inner,
+        Err(e) => e.panic(),
+    };
+
+    super::expand(inner)
+}
+
+/// The tokens of `fn parse`, from its parameters to the end of its body
+fn parse_fn_tokens(tokens: TokenStream) -> Option<Vec<TokenTree>> {
+    let tokens: Vec<TokenTree> = tokens.into_iter().collect();
+    for (i, token) in tokens.iter().enumerate() {
+        match token {
+            TokenTree::Ident(ident) if ident == "parse" && i > 0 => {
+                if !matches!(&tokens[i - 1], TokenTree::Ident(fn_ident) if fn_ident == "fn") {
+                    continue;
+                }
+                let is_body = |token: &TokenTree| match token {
+                    TokenTree::Group(group) => group.delimiter() == Delimiter::Brace,
+                    _ => false,
+                };
+                let end = tokens[i..].iter().position(is_body)?;
+                return Some(tokens[i + 1..=i + end].to_vec());
+            }
+            TokenTree::Group(group) => {
+                if let Some(parse_fn_tokens) = parse_fn_tokens(group.stream()) {
+                    return Some(parse_fn_tokens);
+                }
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+/// How many times `name` is used as a variable in `tokens`, which leaves out method calls and
+/// path segments (e.g. `.multipart()` and `multipart::UploadedFile`)
+fn count_ident(tokens: &[TokenTree], name: &str) -> usize {
+    let mut count = 0;
+    for (i, token) in tokens.iter().enumerate() {
+        match token {
+            TokenTree::Ident(ident) if ident == name => {
+                let is_member = match i.checked_sub(1).map(|i| &tokens[i]) {
+                    Some(TokenTree::Punct(punct)) => matches!(punct.as_char(), '.' | ':'),
+                    _ => false,
+                };
+                if !is_member {
+                    count += 1;
+                }
+            }
+            TokenTree::Group(group) => {
+                count += count_ident(&group.stream().into_iter().collect::<Vec<_>>(), name);
+            }
+            _ => {}
+        }
+    }
+    count
+}
+
+// ----------------------------------------------------------------------------
+// test that post form parts do not collide with the locals of `PostForm::parse`
+
+macro_rules! test_post_form_part_name {
+    ($name:ident, $part_name:literal) => {
+        #[test]
+        fn $name() {
+            let control = parse_fn_tokens(expand_post_form("control")).unwrap();
+            let parse_fn = parse_fn_tokens(expand_post_form($part_name)).unwrap();
+
+            // the generated code never uses the name itself...
+            assert_eq!(count_ident(&control, $part_name), 0);
+
+            // ...so the part is used exactly where any other part would be
+            assert_eq!(
+                count_ident(&parse_fn, $part_name),
+                count_ident(&control, "control")
+            );
+        }
+    };
+}
+
+test_post_form_part_name!(test_post_form_part_named_req, "req");
+test_post_form_part_name!(test_post_form_part_named_multipart, "multipart");
+test_post_form_part_name!(test_post_form_part_named_multipart_field, "multipart_field");
+test_post_form_part_name!(
+    test_post_form_part_named_multipart_field_name,
+    "multipart_field_name"
+);
+test_post_form_part_name!(
+    test_post_form_part_named_submitted_csrf_token,
+    "submitted_csrf_token"
+);
+test_post_form_part_name!(test_post_form_part_named_name, "name");
+test_post_form_part_name!(test_post_form_part_named_k, "k");
+test_post_form_part_name!(test_post_form_part_named_v, "v");
+
+// ----------------------------------------------------------------------------
+// test that the CSRF token is checked before what it protects is read
+
+fn to_string(tokens: &[TokenTree]) -> String {
+    tokens.iter().cloned().collect::<TokenStream>().to_string()
+}
+
+/// Where `needle` first appears in the tokens of `fn parse`, as a string
+fn position(parse_fn: &[TokenTree], needle: &str) -> usize {
+    let parse_fn = to_string(parse_fn);
+    parse_fn
+        .find(needle)
+        .unwrap_or_else(|| panic!("`{needle}` not found in {parse_fn}"))
+}
+
+#[test]
+fn test_post_form_csrf_header_before_body() {
+    let parse_fn = parse_fn_tokens(expand_post_form("control")).unwrap();
+    assert!(position(&parse_fn, "verify_csrf_token") < position(&parse_fn, "is_multipart"));
+}
+
+#[test]
+fn test_post_form_csrf_before_file_parts() {
+    let parse_fn = parse_fn_tokens(expand_post_form_fields(quote! {
+        upload: UploadedFile,
+    }))
+    .unwrap();
+
+    // the file arm checks the token before it streams the file to disk
+    let upload_arm = position(&parse_fn, "Some (\"upload\")");
+    let file = position(&parse_fn, ". file (");
+    assert!(to_string(&parse_fn)[upload_arm..file].contains("verify_csrf_token"));
+}
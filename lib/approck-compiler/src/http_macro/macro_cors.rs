@@ -0,0 +1,155 @@
+use super::{Token, TokenError, TokenIter};
+use crate::Cors;
+
+pub const ERROR_EXPECTED_CORS: &str =
+    "expected `off` or a list of origins, e.g. `\"https://app.example.com\"`";
+pub const ERROR_EXPECTED_ORIGIN: &str =
+    "expected an origin like `\"https://app.example.com\"` (no path), or `\"*\"`";
+
+/// Parses `cors = off;` or `cors = "https://app.example.com", "https://admin.example.com";`
+pub(super) fn parse(token_iter: &mut TokenIter) -> Result<Cors, TokenError> {
+    // Should be sitting on the `cors` keyword at the beginning
+    token_iter.get_ident_match("cors")?;
+    token_iter.step();
+
+    token_iter.get_equals()?;
+    token_iter.step();
+
+    let cors = match token_iter.token() {
+        Token::Ident(ident) if ident == "off" => {
+            token_iter.step();
+            Cors::Off
+        }
+        Token::Literal(_) => {
+            let mut origins = Vec::new();
+            loop {
+                origins.push(parse_origin(token_iter)?);
+                token_iter.step();
+
+                if !token_iter.is_comma() {
+                    break;
+                }
+                token_iter.step();
+            }
+            Cors::Origins(origins)
+        }
+        _ => return Err(token_iter.error(ERROR_EXPECTED_CORS)),
+    };
+
+    token_iter.get_semicolon()?;
+    token_iter.step();
+
+    Ok(cors)
+}
+
+fn parse_origin(token_iter: &mut TokenIter) -> Result<String, TokenError> {
+    let origin = match token_iter.token() {
+        Token::Literal(literal) => match syn::parse_str::<syn::LitStr>(&literal.to_string()) {
+            Ok(origin) => origin.value(),
+            Err(_) => return Err(token_iter.error(ERROR_EXPECTED_ORIGIN)),
+        },
+        _ => return Err(token_iter.error(ERROR_EXPECTED_ORIGIN)),
+    };
+
+    if origin == "*" {
+        return Ok(origin);
+    }
+
+    // browsers send the origin as scheme://host[:port], so anything else would never match
+    match origin
+        .strip_prefix("https://")
+        .or_else(|| origin.strip_prefix("http://"))
+    {
+        Some(host) if !host.is_empty() && !host.contains('/') => Ok(origin),
+        _ => Err(token_iter.error(ERROR_EXPECTED_ORIGIN)),
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use crate::Cors;
+    use quote::quote;
+
+    macro_rules! test_panic {
+        ($name:ident, $param_tokens:expr, $panic_message:literal) => {
+            #[test]
+            #[should_panic(expected = $panic_message)]
+            fn $name() {
+                let mut token_iter = crate::tokenator::TokenIter::new($param_tokens);
+                token_iter.step(); // advance it to the cors instruction
+                match super::parse(&mut token_iter) {
+                    Ok(_) => {}
+                    Err(e) => e.panic(),
+                };
+            }
+        };
+    }
+
+    macro_rules! test_cors {
+        ($name:ident, $param_tokens:expr, $rval:expr) => {
+            #[test]
+            fn $name() {
+                let mut token_iter = crate::tokenator::TokenIter::new($param_tokens);
+                token_iter.step(); // advance it to the cors instruction
+                match super::parse(&mut token_iter) {
+                    Ok(v) => {
+                        assert_eq!(v, $rval);
+                    }
+                    Err(e) => e.panic(),
+                };
+            }
+        };
+    }
+
+    test_cors!(test_off, quote! { cors = off; }, Cors::Off);
+    test_cors!(
+        test_one_origin,
+        quote! { cors = "https://app.example.com"; },
+        Cors::Origins(vec!["https://app.example.com".to_string()])
+    );
+    test_cors!(
+        test_many_origins,
+        quote! { cors = "https://app.example.com", "http://localhost:3000"; },
+        Cors::Origins(vec![
+            "https://app.example.com".to_string(),
+            "http://localhost:3000".to_string()
+        ])
+    );
+    test_cors!(
+        test_any_origin,
+        quote! { cors = "*"; },
+        Cors::Origins(vec!["*".to_string()])
+    );
+
+    test_panic!(test_missing_equals, quote! { cors off; }, "expected `=`");
+    test_panic!(
+        test_invalid_ident,
+        quote! { cors = on; },
+        "expected `off` or a list of origins"
+    );
+    test_panic!(
+        test_origin_with_path,
+        quote! { cors = "https://app.example.com/"; },
+        "expected an origin like"
+    );
+    test_panic!(
+        test_origin_without_scheme,
+        quote! { cors = "app.example.com"; },
+        "expected an origin like"
+    );
+    test_panic!(
+        test_origin_not_a_string,
+        quote! { cors = 10; },
+        "expected an origin like"
+    );
+    test_panic!(
+        test_trailing_comma,
+        quote! { cors = "https://app.example.com",; },
+        "expected an origin like"
+    );
+    test_panic!(
+        test_missing_semicolon,
+        quote! { cors = off },
+        "expected `;`"
+    );
+}
@@ -0,0 +1,76 @@
+use super::{Token, TokenError, TokenIter};
+
+pub const ERROR_EXPECTED_ON_OFF: &str = "expected `on` or `off`";
+
+/// Parses `csrf = off;` (or `csrf = on;`, the default), returning whether CSRF tokens are checked
+pub(super) fn parse(token_iter: &mut TokenIter) -> Result<bool, TokenError> {
+    // Should be sitting on the `csrf` keyword at the beginning
+    token_iter.get_ident_match("csrf")?;
+    token_iter.step();
+
+    token_iter.get_equals()?;
+    token_iter.step();
+
+    let csrf = match token_iter.token() {
+        Token::Ident(ident) if ident == "on" => true,
+        Token::Ident(ident) if ident == "off" => false,
+        _ => return Err(token_iter.error(ERROR_EXPECTED_ON_OFF)),
+    };
+    token_iter.step();
+
+    token_iter.get_semicolon()?;
+    token_iter.step();
+
+    Ok(csrf)
+}
+
+#[cfg(test)]
+pub mod tests {
+    use quote::quote;
+
+    macro_rules! test_panic {
+        ($name:ident, $param_tokens:expr, $panic_message:literal) => {
+            #[test]
+            #[should_panic(expected = $panic_message)]
+            fn $name() {
+                let mut token_iter = crate::tokenator::TokenIter::new($param_tokens);
+                token_iter.step(); // advance it to the csrf instruction
+                match super::parse(&mut token_iter) {
+                    Ok(_) => {}
+                    Err(e) => e.panic(),
+                };
+            }
+        };
+    }
+
+    macro_rules! test_csrf {
+        ($name:ident, $param_tokens:expr, $rval:expr) => {
+            #[test]
+            fn $name() {
+                let mut token_iter = crate::tokenator::TokenIter::new($param_tokens);
+                token_iter.step(); // advance it to the csrf instruction
+                match super::parse(&mut token_iter) {
+                    Ok(v) => {
+                        assert_eq!(v, $rval);
+                    }
+                    Err(e) => e.panic(),
+                };
+            }
+        };
+    }
+
+    test_csrf!(test_off, quote! { csrf = off; }, false);
+    test_csrf!(test_on, quote! { csrf = on; }, true);
+
+    test_panic!(test_missing_equals, quote! { csrf off; }, "expected `=`");
+    test_panic!(
+        test_invalid_value,
+        quote! { csrf = false; },
+        "expected `on` or `off`"
+    );
+    test_panic!(
+        test_missing_semicolon,
+        quote! { csrf = off },
+        "expected `;`"
+    );
+}
@@ -0,0 +1,123 @@
+use super::{Token, TokenError, TokenIter};
+
+pub const ERROR_EXPECTED_BYTE_SIZE: &str =
+    "expected a size in bytes, optionally suffixed with one of [B, KB, MB, GB], e.g. `10MB`";
+
+/// Parses `max_body_size = 10MB;`
+pub(super) fn parse(token_iter: &mut TokenIter) -> Result<u64, TokenError> {
+    // Should be sitting on the `max_body_size` keyword at the beginning
+    token_iter.get_ident_match("max_body_size")?;
+    token_iter.step();
+
+    token_iter.get_equals()?;
+    token_iter.step();
+
+    let max_body_size = match token_iter.token() {
+        Token::Literal(literal) => match parse_byte_size(&literal.to_string()) {
+            Some(max_body_size) => max_body_size,
+            None => return Err(token_iter.error(ERROR_EXPECTED_BYTE_SIZE)),
+        },
+        _ => return Err(token_iter.error(ERROR_EXPECTED_BYTE_SIZE)),
+    };
+    token_iter.step();
+
+    token_iter.get_semicolon()?;
+    token_iter.step();
+
+    Ok(max_body_size)
+}
+
+/// Parses a literal like `512`, `64KB`, `10MB`, or `1_000B` into a number of bytes.  The
+/// multiples are binary, so `1KB` is 1024 bytes.
+pub(crate) fn parse_byte_size(literal: &str) -> Option<u64> {
+    let split = literal
+        .find(|c: char| !c.is_ascii_digit() && c != '_')
+        .unwrap_or(literal.len());
+    let (digits, suffix) = literal.split_at(split);
+
+    let multiplier = match suffix {
+        "" | "B" => 1,
+        "KB" => 1024,
+        "MB" => 1024 * 1024,
+        "GB" => 1024 * 1024 * 1024,
+        _ => return None,
+    };
+
+    let digits = digits.replace('_', "");
+    if digits.is_empty() {
+        return None;
+    }
+
+    digits.parse::<u64>().ok()?.checked_mul(multiplier)
+}
+
+#[cfg(test)]
+pub mod tests {
+    use quote::quote;
+
+    macro_rules! test_panic {
+        ($name:ident, $param_tokens:expr, $panic_message:literal) => {
+            #[test]
+            #[should_panic(expected = $panic_message)]
+            fn $name() {
+                let mut token_iter = crate::tokenator::TokenIter::new($param_tokens);
+                token_iter.step(); // advance it to the max_body_size instruction
+                match super::parse(&mut token_iter) {
+                    Ok(_) => {}
+                    Err(e) => e.panic(),
+                };
+            }
+        };
+    }
+
+    macro_rules! test_max_body_size {
+        ($name:ident, $param_tokens:expr, $rval:expr) => {
+            #[test]
+            fn $name() {
+                let mut token_iter = crate::tokenator::TokenIter::new($param_tokens);
+                token_iter.step(); // advance it to the max_body_size instruction
+                match super::parse(&mut token_iter) {
+                    Ok(v) => {
+                        assert_eq!(v, $rval);
+                    }
+                    Err(e) => e.panic(),
+                };
+            }
+        };
+    }
+
+    test_max_body_size!(test_plain_bytes, quote! { max_body_size = 512; }, 512);
+    test_max_body_size!(test_bytes_suffix, quote! { max_body_size = 1_000B; }, 1000);
+    test_max_body_size!(test_kilobytes, quote! { max_body_size = 64KB; }, 65536);
+    test_max_body_size!(
+        test_megabytes,
+        quote! { max_body_size = 10MB; },
+        10 * 1024 * 1024
+    );
+    test_max_body_size!(
+        test_gigabytes,
+        quote! { max_body_size = 2GB; },
+        2 * 1024 * 1024 * 1024
+    );
+
+    test_panic!(
+        test_missing_equals,
+        quote! { max_body_size 10MB; },
+        "expected `=`"
+    );
+    test_panic!(
+        test_invalid_suffix,
+        quote! { max_body_size = 10TB; },
+        "expected a size in bytes"
+    );
+    test_panic!(
+        test_not_a_literal,
+        quote! { max_body_size = big; },
+        "expected a size in bytes"
+    );
+    test_panic!(
+        test_missing_semicolon,
+        quote! { max_body_size = 10MB },
+        "expected `;`"
+    );
+}
@@ -0,0 +1,179 @@
+use super::{Token, TokenError, TokenIter};
+use crate::{RateLimit, RateLimitPer};
+
+pub const ERROR_EXPECTED_REQUEST_COUNT: &str = "expected a number of requests, e.g. `10/min`";
+pub const ERROR_EXPECTED_PERIOD: &str = "expected one of [sec, min, hour, day]";
+pub const ERROR_EXPECTED_PER: &str = "expected `ip` or `session`";
+
+/// Parses `rate_limit = 10/min;` (counted per client IP) or `rate_limit = 10/min per session;`
+pub(super) fn parse(token_iter: &mut TokenIter) -> Result<RateLimit, TokenError> {
+    // Should be sitting on the `rate_limit` keyword at the beginning
+    token_iter.get_ident_match("rate_limit")?;
+    token_iter.step();
+
+    token_iter.get_equals()?;
+    token_iter.step();
+
+    let requests = match token_iter.token() {
+        Token::Literal(literal) => match literal.to_string().replace('_', "").parse::<u64>() {
+            Ok(requests) if requests > 0 => requests,
+            _ => return Err(token_iter.error(ERROR_EXPECTED_REQUEST_COUNT)),
+        },
+        _ => return Err(token_iter.error(ERROR_EXPECTED_REQUEST_COUNT)),
+    };
+    token_iter.step();
+
+    token_iter.get_slash()?;
+    token_iter.step();
+
+    let period_secs = match token_iter.token() {
+        Token::Ident(ident) if ident == "sec" => 1,
+        Token::Ident(ident) if ident == "min" => 60,
+        Token::Ident(ident) if ident == "hour" => 60 * 60,
+        Token::Ident(ident) if ident == "day" => 24 * 60 * 60,
+        _ => return Err(token_iter.error(ERROR_EXPECTED_PERIOD)),
+    };
+    token_iter.step();
+
+    let per = match token_iter.token() {
+        Token::Ident(ident) if ident == "per" => {
+            token_iter.step();
+            let per = match token_iter.token() {
+                Token::Ident(ident) if ident == "ip" => RateLimitPer::Ip,
+                Token::Ident(ident) if ident == "session" => RateLimitPer::Session,
+                _ => return Err(token_iter.error(ERROR_EXPECTED_PER)),
+            };
+            token_iter.step();
+            per
+        }
+        _ => RateLimitPer::Ip,
+    };
+
+    token_iter.get_semicolon()?;
+    token_iter.step();
+
+    Ok(RateLimit {
+        requests,
+        period_secs,
+        per,
+    })
+}
+
+#[cfg(test)]
+pub mod tests {
+    use crate::{RateLimit, RateLimitPer};
+    use quote::quote;
+
+    macro_rules! test_panic {
+        ($name:ident, $param_tokens:expr, $panic_message:literal) => {
+            #[test]
+            #[should_panic(expected = $panic_message)]
+            fn $name() {
+                let mut token_iter = crate::tokenator::TokenIter::new($param_tokens);
+                token_iter.step(); // advance it to the rate_limit instruction
+                match super::parse(&mut token_iter) {
+                    Ok(_) => {}
+                    Err(e) => e.panic(),
+                };
+            }
+        };
+    }
+
+    macro_rules! test_rate_limit {
+        ($name:ident, $param_tokens:expr, $rval:expr) => {
+            #[test]
+            fn $name() {
+                let mut token_iter = crate::tokenator::TokenIter::new($param_tokens);
+                token_iter.step(); // advance it to the rate_limit instruction
+                match super::parse(&mut token_iter) {
+                    Ok(v) => {
+                        assert_eq!(v, $rval);
+                    }
+                    Err(e) => e.panic(),
+                };
+            }
+        };
+    }
+
+    test_rate_limit!(
+        test_per_minute,
+        quote! { rate_limit = 10/min; },
+        RateLimit {
+            requests: 10,
+            period_secs: 60,
+            per: RateLimitPer::Ip,
+        }
+    );
+    test_rate_limit!(
+        test_per_second,
+        quote! { rate_limit = 5/sec; },
+        RateLimit {
+            requests: 5,
+            period_secs: 1,
+            per: RateLimitPer::Ip,
+        }
+    );
+    test_rate_limit!(
+        test_per_day_underscores,
+        quote! { rate_limit = 1_000/day; },
+        RateLimit {
+            requests: 1000,
+            period_secs: 86400,
+            per: RateLimitPer::Ip,
+        }
+    );
+    test_rate_limit!(
+        test_per_ip,
+        quote! { rate_limit = 10/min per ip; },
+        RateLimit {
+            requests: 10,
+            period_secs: 60,
+            per: RateLimitPer::Ip,
+        }
+    );
+    test_rate_limit!(
+        test_per_session,
+        quote! { rate_limit = 3/hour per session; },
+        RateLimit {
+            requests: 3,
+            period_secs: 3600,
+            per: RateLimitPer::Session,
+        }
+    );
+
+    test_panic!(
+        test_missing_equals,
+        quote! { rate_limit 10/min; },
+        "expected `=`"
+    );
+    test_panic!(
+        test_zero_requests,
+        quote! { rate_limit = 0/min; },
+        "expected a number of requests"
+    );
+    test_panic!(
+        test_not_a_number,
+        quote! { rate_limit = ten/min; },
+        "expected a number of requests"
+    );
+    test_panic!(
+        test_missing_slash,
+        quote! { rate_limit = 10 min; },
+        "expected `/`"
+    );
+    test_panic!(
+        test_invalid_period,
+        quote! { rate_limit = 10/week; },
+        "expected one of [sec, min, hour, day]"
+    );
+    test_panic!(
+        test_invalid_per,
+        quote! { rate_limit = 10/min per user; },
+        "expected `ip` or `session`"
+    );
+    test_panic!(
+        test_missing_semicolon,
+        quote! { rate_limit = 10/min },
+        "expected `;`"
+    );
+}
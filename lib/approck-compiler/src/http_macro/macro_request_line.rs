@@ -6,7 +6,8 @@ use super::{RequestLine, Token, TokenError, TokenIter};
 //use proc_macro2_diagnostics::SpanDiagnosticExt;
 
 // create a static message for http methods
-pub const ERROR_EXPECTED_HTTP_METHOD: &str = "expected http method (GET, POST, PUT, DELETE, PATCH)";
+pub const ERROR_EXPECTED_HTTP_METHOD: &str =
+    "expected http method (GET, POST, PUT, DELETE, PATCH, HEAD, OPTIONS)";
 
 pub(super) fn parse(token_iter: &mut TokenIter) -> Result<RequestLine, TokenError> {
     let mut methods = Vec::new();
@@ -27,6 +28,8 @@ pub(super) fn parse(token_iter: &mut TokenIter) -> Result<RequestLine, TokenErro
             "PUT" => Method::PUT,
             "DELETE" => Method::DELETE,
             "PATCH" => Method::PATCH,
+            "HEAD" => Method::HEAD,
+            "OPTIONS" => Method::OPTIONS,
             _ => return Err(token_iter.error(ERROR_EXPECTED_HTTP_METHOD)),
         };
 
@@ -3,7 +3,7 @@ use crate::ReturnTypes;
 
 // create a static message for http methods
 pub const ERROR_EXPECTED_RETURN_TYPE: &str =
-    "expected one of [Bytes, Text, Empty, HTML, JavaScript, CSS, JSON, SVG, NotFound, Redirect, WebSocketUpgrade, Stream]";
+    "expected one of [Bytes, Text, Empty, HTML, JavaScript, CSS, JSON, SVG, NotFound, Redirect, WebSocketUpgrade, Stream, SSE]";
 
 pub(super) fn parse(token_iter: &mut TokenIter) -> Result<ReturnTypes, TokenError> {
     let mut return_types = ReturnTypes::default();
@@ -98,6 +98,13 @@ pub(super) fn parse(token_iter: &mut TokenIter) -> Result<ReturnTypes, TokenErro
                 return_types.Stream = true;
                 token_iter.step();
             }
+            "SSE" => {
+                if return_types.SSE {
+                    return Err(token_iter.error("duplicate `SSE` return type"));
+                }
+                return_types.SSE = true;
+                token_iter.step();
+            }
             v => {
                 return Err(token_iter
                     .error(format!("{}, not `{}`", ERROR_EXPECTED_RETURN_TYPE, v).as_str()));
@@ -276,10 +283,20 @@ pub mod tests {
         }
     );
 
-    // test on multiple return of Bytes, Text, Empty, HTML, JavaScript, CSS, JSON, SVG, NotFound, Redirect, WebSocketUpgrade
+    // test on single return of SSE
+    test_return!(
+        test_return_single_sse,
+        quote! { return SSE; },
+        ReturnTypes {
+            SSE: true,
+            ..Default::default()
+        }
+    );
+
+    // test on multiple return of Bytes, Text, Empty, HTML, JavaScript, CSS, JSON, SVG, NotFound, Redirect, WebSocketUpgrade, Stream, SSE
     test_return!(
         test_return_multiple,
-        quote! { return Bytes | Text | Empty | HTML | JavaScript | CSS | JSON | SVG | NotFound | Redirect | WebSocketUpgrade | Stream; },
+        quote! { return Bytes | Text | Empty | HTML | JavaScript | CSS | JSON | SVG | NotFound | Redirect | WebSocketUpgrade | Stream | SSE; },
         ReturnTypes {
             Bytes: true,
             Text: true,
@@ -293,6 +310,7 @@ pub mod tests {
             Redirect: true,
             WebSocketUpgrade: true,
             Stream: true,
+            SSE: true,
         }
     );
 
@@ -373,6 +391,13 @@ pub mod tests {
         "duplicate `WebSocketUpgrade` return type"
     );
 
+    // panic on duplicate SSE
+    test_panic!(
+        test_return_duplicate_sse,
+        quote! { return SSE | SSE; },
+        "duplicate `SSE` return type"
+    );
+
     // panic on Bytes | Text | Bytes
     test_panic!(
         test_return_duplicate_bytes_text,
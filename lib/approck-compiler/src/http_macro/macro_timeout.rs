@@ -0,0 +1,138 @@
+use super::{Token, TokenError, TokenIter};
+use crate::Timeout;
+
+pub const ERROR_EXPECTED_TIMEOUT: &str =
+    "expected `off` or a duration suffixed with one of [ms, s, min], e.g. `30s`";
+
+/// Parses `timeout = 30s;` or `timeout = off;`
+pub(super) fn parse(token_iter: &mut TokenIter) -> Result<Timeout, TokenError> {
+    // Should be sitting on the `timeout` keyword at the beginning
+    token_iter.get_ident_match("timeout")?;
+    token_iter.step();
+
+    token_iter.get_equals()?;
+    token_iter.step();
+
+    let timeout = match token_iter.token() {
+        Token::Ident(ident) if ident == "off" => Timeout::Off,
+        Token::Literal(literal) => match parse_duration_millis(&literal.to_string()) {
+            Some(millis) => Timeout::Millis(millis),
+            None => return Err(token_iter.error(ERROR_EXPECTED_TIMEOUT)),
+        },
+        _ => return Err(token_iter.error(ERROR_EXPECTED_TIMEOUT)),
+    };
+    token_iter.step();
+
+    token_iter.get_semicolon()?;
+    token_iter.step();
+
+    Ok(timeout)
+}
+
+/// Parses a literal like `500ms`, `30s`, or `2min` into a number of milliseconds.  The unit is
+/// required, and the duration may not be zero.
+fn parse_duration_millis(literal: &str) -> Option<u64> {
+    let split = literal
+        .find(|c: char| !c.is_ascii_digit() && c != '_')
+        .unwrap_or(literal.len());
+    let (digits, suffix) = literal.split_at(split);
+
+    let multiplier = match suffix {
+        "ms" => 1,
+        "s" => 1000,
+        "min" => 60 * 1000,
+        _ => return None,
+    };
+
+    let digits = digits.replace('_', "");
+    match digits.parse::<u64>().ok()?.checked_mul(multiplier)? {
+        0 => None,
+        millis => Some(millis),
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use crate::Timeout;
+    use quote::quote;
+
+    macro_rules! test_panic {
+        ($name:ident, $param_tokens:expr, $panic_message:literal) => {
+            #[test]
+            #[should_panic(expected = $panic_message)]
+            fn $name() {
+                let mut token_iter = crate::tokenator::TokenIter::new($param_tokens);
+                token_iter.step(); // advance it to the timeout instruction
+                match super::parse(&mut token_iter) {
+                    Ok(_) => {}
+                    Err(e) => e.panic(),
+                };
+            }
+        };
+    }
+
+    macro_rules! test_timeout {
+        ($name:ident, $param_tokens:expr, $rval:expr) => {
+            #[test]
+            fn $name() {
+                let mut token_iter = crate::tokenator::TokenIter::new($param_tokens);
+                token_iter.step(); // advance it to the timeout instruction
+                match super::parse(&mut token_iter) {
+                    Ok(v) => {
+                        assert_eq!(v, $rval);
+                    }
+                    Err(e) => e.panic(),
+                };
+            }
+        };
+    }
+
+    test_timeout!(test_off, quote! { timeout = off; }, Timeout::Off);
+    test_timeout!(
+        test_milliseconds,
+        quote! { timeout = 500ms; },
+        Timeout::Millis(500)
+    );
+    test_timeout!(
+        test_seconds,
+        quote! { timeout = 30s; },
+        Timeout::Millis(30_000)
+    );
+    test_timeout!(
+        test_minutes,
+        quote! { timeout = 2min; },
+        Timeout::Millis(120_000)
+    );
+    test_timeout!(
+        test_underscores,
+        quote! { timeout = 1_500ms; },
+        Timeout::Millis(1500)
+    );
+
+    test_panic!(test_missing_equals, quote! { timeout 30s; }, "expected `=`");
+    test_panic!(
+        test_missing_unit,
+        quote! { timeout = 30; },
+        "expected `off` or a duration"
+    );
+    test_panic!(
+        test_invalid_unit,
+        quote! { timeout = 1h; },
+        "expected `off` or a duration"
+    );
+    test_panic!(
+        test_zero,
+        quote! { timeout = 0s; },
+        "expected `off` or a duration"
+    );
+    test_panic!(
+        test_not_a_literal,
+        quote! { timeout = forever; },
+        "expected `off` or a duration"
+    );
+    test_panic!(
+        test_missing_semicolon,
+        quote! { timeout = 30s },
+        "expected `;`"
+    );
+}
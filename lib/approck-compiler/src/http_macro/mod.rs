@@ -1,6 +1,11 @@
 pub mod codegen;
+pub mod macro_cors;
+pub mod macro_csrf;
+pub mod macro_max_body_size;
+pub mod macro_rate_limit;
 pub mod macro_request_line;
 pub mod macro_return_types;
+pub mod macro_timeout;
 pub mod post_form_struct;
 pub mod request_function_return;
 pub mod request_function_signature;
@@ -58,13 +63,30 @@ pub fn parse_http_module_inner(
     let request_line = self::macro_request_line::parse(&mut token_iter)?;
 
     // Set defaults
+    let mut cors = None;
+    let mut csrf = None;
     let mut derive_debug = false;
+    let mut max_body_size = None;
+    let mut rate_limit = None;
     let mut return_types = None;
+    let mut timeout = None;
 
     // parse additional instructions
     loop {
         match token_iter.token() {
             Token::Ident(ident) => match ident.to_string().as_str() {
+                "cors" => {
+                    if cors.is_some() {
+                        return Err(token_iter.error("duplicate `cors` instruction"));
+                    }
+                    cors = Some(self::macro_cors::parse(&mut token_iter)?);
+                }
+                "csrf" => {
+                    if csrf.is_some() {
+                        return Err(token_iter.error("duplicate `csrf` instruction"));
+                    }
+                    csrf = Some(self::macro_csrf::parse(&mut token_iter)?);
+                }
                 "derive_debug" => {
                     if derive_debug {
                         return Err(token_iter.error("duplicate `derive_debug` instruction"));
@@ -75,12 +97,30 @@ pub fn parse_http_module_inner(
                     token_iter.get_semicolon()?;
                     token_iter.step();
                 }
+                "max_body_size" => {
+                    if max_body_size.is_some() {
+                        return Err(token_iter.error("duplicate `max_body_size` instruction"));
+                    }
+                    max_body_size = Some(self::macro_max_body_size::parse(&mut token_iter)?);
+                }
+                "rate_limit" => {
+                    if rate_limit.is_some() {
+                        return Err(token_iter.error("duplicate `rate_limit` instruction"));
+                    }
+                    rate_limit = Some(self::macro_rate_limit::parse(&mut token_iter)?);
+                }
                 "return" => {
                     if return_types.is_some() {
                         return Err(token_iter.error("duplicate `return` instruction"));
                     }
                     return_types = Some(self::macro_return_types::parse(&mut token_iter)?);
                 }
+                "timeout" => {
+                    if timeout.is_some() {
+                        return Err(token_iter.error("duplicate `timeout` instruction"));
+                    }
+                    timeout = Some(self::macro_timeout::parse(&mut token_iter)?);
+                }
                 _ => {
                     return Err(
                         token_iter.error(format!("invalid instruction: `{}`", ident).as_str())
@@ -91,7 +131,9 @@ pub fn parse_http_module_inner(
                 break;
             }
             _ => {
-                return Err(token_iter.error("expected `derive_debug`, or `return`"));
+                return Err(token_iter.error(
+                    "expected `cors`, `csrf`, `derive_debug`, `max_body_size`, `rate_limit`, `return`, or `timeout`",
+                ));
             }
         }
     }
@@ -147,6 +189,17 @@ pub fn parse_http_module_inner(
                 mod_post_type =
                     crate::PostType::Struct(self::post_form_struct::parse(item_struct)?);
             }
+            syn::Item::Struct(item_struct) if item_struct.ident == "PostJson" => {
+                if mod_post_type.is_filled() {
+                    return Err(TokenError::new(
+                        item_struct.span(),
+                        format!("PostType already set to {:?}", mod_post_type).as_str(),
+                    ));
+                }
+                mod_post_type = crate::PostType::Json(crate::PostTypeJson {
+                    token_stream: item_struct.to_token_stream(),
+                });
+            }
             syn::Item::Enum(item_enum) if item_enum.ident == "PostForm" => {
                 if mod_post_type.is_filled() {
                     return Err(TokenError::new(
@@ -198,6 +251,11 @@ pub fn parse_http_module_inner(
         path: request_line.path,
         query_string: request_line.qs,
         derive_debug,
+        max_body_size,
+        rate_limit,
+        cors,
+        csrf: csrf.unwrap_or(true),
+        timeout,
         return_types,
         mod_ident,
         mod_name,
@@ -1,12 +1,47 @@
 use quote::ToTokens;
+use syn::spanned::Spanned;
 
+use super::macro_max_body_size::{parse_byte_size, ERROR_EXPECTED_BYTE_SIZE};
 use super::{TokenError, TokenIter};
 use crate::{PostTypeStruct, QueryStringPart, QueryStringValue, QueryStringValueType};
 
 /// Objective of this function is to parse the contents of the request(...) function signature
 /// It should be presented as a TokenIter, and the response should be a Vec<crate::Param>
 /// An example valid input would be quote! { req: Request, path: Path, qs: QueryString, form: Option<PostForm> }
-pub(super) fn parse(item_struct: syn::ItemStruct) -> Result<PostTypeStruct, TokenError> {
+pub(super) fn parse(mut item_struct: syn::ItemStruct) -> Result<PostTypeStruct, TokenError> {
+    let mut max_sizes = Vec::new();
+
+    // `#[max_size(5MB)]` is ours, so take it off the field before the struct is emitted
+    for field in item_struct.fields.iter_mut() {
+        let mut attrs = Vec::new();
+        for attr in field.attrs.drain(..) {
+            if !attr.path().is_ident("max_size") {
+                attrs.push(attr);
+                continue;
+            }
+
+            let name = match &field.ident {
+                Some(ident) => ident.to_string(),
+                None => return Err(TokenError::new(attr.span(), "expected a named field")),
+            };
+            if max_sizes.iter().any(|(n, _)| *n == name) {
+                return Err(TokenError::new(
+                    attr.span(),
+                    "duplicate `max_size` attribute",
+                ));
+            }
+            let max_size = attr
+                .meta
+                .require_list()
+                .ok()
+                .and_then(|list| parse_byte_size(&list.tokens.to_string()))
+                .ok_or_else(|| TokenError::new(attr.span(), ERROR_EXPECTED_BYTE_SIZE))?;
+
+            max_sizes.push((name, max_size));
+        }
+        field.attrs = attrs;
+    }
+
     let token_stream = item_struct.to_token_stream();
     let mut query_string_parts = Vec::new();
 
@@ -32,7 +67,8 @@ pub(super) fn parse(item_struct: syn::ItemStruct) -> Result<PostTypeStruct, Toke
             "u64" => Ok(QueryStringValueType::u64),
             "f32" => Ok(QueryStringValueType::f32),
             "f64" => Ok(QueryStringValueType::f64),
-            _ => Err("expected String, i32, u32, i64, u64, f32, or f64"),
+            "UploadedFile" => Ok(QueryStringValueType::UploadedFile),
+            _ => Err("expected String, i32, u32, i64, u64, f32, f64, or UploadedFile"),
         };
 
         match token_iter.get_ident_as_string()?.as_str() {
@@ -83,6 +119,9 @@ pub(super) fn parse(item_struct: syn::ItemStruct) -> Result<PostTypeStruct, Toke
 
                 let value_type = extract_value_type(token_iter.get_ident_as_string()?.as_str())
                     .map_err(|e| token_iter.error(e))?;
+                if value_type == QueryStringValueType::UploadedFile {
+                    return Err(token_iter.error("UploadedFile cannot be used in a HashSet"));
+                }
                 token_iter.step();
 
                 token_iter.get_greater_than()?;
@@ -109,6 +148,7 @@ pub(super) fn parse(item_struct: syn::ItemStruct) -> Result<PostTypeStruct, Toke
     Ok(PostTypeStruct {
         token_stream,
         query_string_parts,
+        max_sizes,
     })
 }
 
@@ -166,14 +206,14 @@ mod tests {
     test_panic!(
         test_invalid_data_type,
         quote! { foo: Bar },
-        "expected String, i32, u32, i64, u64, f32, or f64"
+        "expected String, i32, u32, i64, u64, f32, f64, or UploadedFile"
     );
 
     // test invalid data type in Option
     test_panic!(
         test_invalid_data_type_in_option,
         quote! { foo: Option<Bar> },
-        "expected String, i32, u32, i64, u64, f32, or f64"
+        "expected String, i32, u32, i64, u64, f32, f64, or UploadedFile"
     );
 
     // test String
@@ -263,4 +303,72 @@ mod tests {
             },
         ]
     );
+
+    // Test UploadedFile
+    test_return!(
+        test_uploaded_file,
+        quote! { foo: UploadedFile, bar: Option<UploadedFile>, baz: Vec<UploadedFile> },
+        vec![
+            super::QueryStringPart {
+                name: "foo".to_string(),
+                value: QueryStringValue::Require(QueryStringValueType::UploadedFile),
+            },
+            super::QueryStringPart {
+                name: "bar".to_string(),
+                value: QueryStringValue::Option(QueryStringValueType::UploadedFile),
+            },
+            super::QueryStringPart {
+                name: "baz".to_string(),
+                value: QueryStringValue::Vec(QueryStringValueType::UploadedFile),
+            },
+        ]
+    );
+
+    // test UploadedFile in a HashSet
+    test_panic!(
+        test_uploaded_file_in_hashset,
+        quote! { foo: HashSet<UploadedFile> },
+        "UploadedFile cannot be used in a HashSet"
+    );
+
+    macro_rules! test_max_sizes {
+        ($name:ident, $param_tokens:expr, $max_sizes:expr) => {
+            #[test]
+            fn $name() {
+                let param_tokens = $param_tokens;
+                let item_struct: syn::ItemStruct =
+                    syn::parse2(quote! { struct PostForm { #param_tokens } }).unwrap();
+                let rval = match super::parse(item_struct) {
+                    Ok(rval) => rval,
+                    Err(e) => e.panic(),
+                };
+                assert_eq!(rval.max_sizes, $max_sizes);
+                assert!(!rval.token_stream.to_string().contains("max_size"));
+            }
+        };
+    }
+
+    // test max_size on text and file fields
+    test_max_sizes!(
+        test_max_size,
+        quote! { #[max_size(1KB)] name: String, #[max_size(5MB)] file: UploadedFile, other: i32 },
+        vec![
+            ("name".to_string(), 1024),
+            ("file".to_string(), 5 * 1024 * 1024)
+        ]
+    );
+
+    // test an invalid max_size
+    test_panic!(
+        test_max_size_invalid,
+        quote! { #[max_size(lots)] file: UploadedFile },
+        "expected a size in bytes"
+    );
+
+    // test a duplicate max_size
+    test_panic!(
+        test_max_size_duplicate,
+        quote! { #[max_size(1KB)] #[max_size(2KB)] file: UploadedFile },
+        "duplicate `max_size` attribute"
+    );
 }
@@ -25,6 +25,8 @@ pub(super) fn parse(
     let mut has_path = false;
     let mut has_query_string = false;
     let mut has_post_form = false;
+    let mut has_post_json = false;
+    let mut has_session = false;
 
     // Process parameters
     loop {
@@ -125,6 +127,19 @@ pub(super) fn parse(
                     param_type: crate::ParamType::Request,
                 });
             }
+            "Session" => {
+                if has_session {
+                    // must come before .step() so that the error is indicated in the right place
+                    return Err(token_iter.error("Session parameter already exists"));
+                }
+                token_iter.step();
+                has_session = true;
+
+                params.push(crate::Param {
+                    param_name: ident_name,
+                    param_type: crate::ParamType::Session,
+                });
+            }
             "QueryString" => {
                 if has_query_string {
                     // must come before .step() so that the error is indicated in the right place
@@ -151,6 +166,19 @@ pub(super) fn parse(
                     param_type: crate::ParamType::PostForm,
                 });
             }
+            "PostJson" => {
+                if has_post_json {
+                    // must come before .step() so that the error is indicated in the right place
+                    return Err(token_iter.error("PostJson parameter already exists"));
+                }
+                token_iter.step();
+                has_post_json = true;
+
+                params.push(crate::Param {
+                    param_name: ident_name,
+                    param_type: crate::ParamType::PostJson,
+                });
+            }
             "Option" => {
                 token_iter.step();
                 token_iter.get_less_than()?;
@@ -192,6 +220,24 @@ pub(super) fn parse(
                             param_type: crate::ParamType::PostFormOption,
                         });
                     }
+                    "PostJson" => {
+                        if has_post_json {
+                            // must come before .step() so that the error is indicated in the right place
+                            return Err(token_iter.error("PostJson parameter already exists"));
+                        }
+
+                        token_iter.step();
+
+                        token_iter.get_greater_than()?;
+                        token_iter.step();
+
+                        has_post_json = true;
+
+                        params.push(crate::Param {
+                            param_name: ident_name,
+                            param_type: crate::ParamType::PostJsonOption,
+                        });
+                    }
                     ident_name_inner => {
                         token_iter.step();
 
@@ -209,7 +255,7 @@ pub(super) fn parse(
             }
             _ => {
                 return Err(token_iter
-                    .error("expected `App<...>`, `DBCX`, `Document`, `Option<...>`, `Path`, `PostForm`, `QueryString`, `RedisCX`, or `Request`"));
+                    .error("expected `App<...>`, `DBCX`, `Document`, `Option<...>`, `Path`, `PostForm`, `PostJson`, `QueryString`, `RedisCX`, `Request`, or `Session`"));
             }
         }
 
@@ -313,6 +359,16 @@ mod tests {
         }]
     );
 
+    // Test for Session
+    test_return!(
+        test_session,
+        quote! { session: Session },
+        vec![crate::Param {
+            param_name: "session".to_string(),
+            param_type: crate::ParamType::Session,
+        }]
+    );
+
     // Test for Path
     test_return!(
         test_path,
@@ -385,6 +441,26 @@ mod tests {
         ]
     );
 
+    // Test for PostJson
+    test_return!(
+        test_post_json,
+        quote! { body: PostJson },
+        vec![crate::Param {
+            param_name: "body".to_string(),
+            param_type: crate::ParamType::PostJson,
+        }]
+    );
+
+    // Test for Option<PostJson>
+    test_return!(
+        test_option_post_json,
+        quote! { body: Option<PostJson> },
+        vec![crate::Param {
+            param_name: "body".to_string(),
+            param_type: crate::ParamType::PostJsonOption,
+        }]
+    );
+
     // Fail on duplicate QueryString
     test_panic!(
         test_duplicate_query_string,
@@ -399,6 +475,13 @@ mod tests {
         "PostForm parameter already exists"
     );
 
+    // Fail on duplicate PostJson
+    test_panic!(
+        test_duplicate_post_json,
+        quote! { body1: PostJson, body2: Option<PostJson> },
+        "PostJson parameter already exists"
+    );
+
     // Fail on duplicate Request
     test_panic!(
         test_duplicate_request,
@@ -406,6 +489,13 @@ mod tests {
         "Request parameter already exists"
     );
 
+    // Fail on duplicate Session
+    test_panic!(
+        test_duplicate_session,
+        quote! { session1: Session, session2: Session },
+        "Session parameter already exists"
+    );
+
     // Fail on duplicate Path
     test_panic!(
         test_duplicate_path,
@@ -55,35 +55,35 @@ macro_rules! test_method {
 test_panic!(
     test_missing_method,
     quote! { /;  return Empty;},
-    "expected http method (GET, POST, PUT, DELETE, PATCH)"
+    "expected http method (GET, POST, PUT, DELETE, PATCH, HEAD, OPTIONS)"
 );
 
 // fail on invalid type for method
 test_panic!(
     test_invalid_type_for_method,
     quote! { 1 /;  return Empty;},
-    "expected http method (GET, POST, PUT, DELETE, PATCH)"
+    "expected http method (GET, POST, PUT, DELETE, PATCH, HEAD, OPTIONS)"
 );
 
 // fail an invalid method
 test_panic!(
     test_invalid_method,
     quote! { FOO /;  return Empty;},
-    "expected http method (GET, POST, PUT, DELETE, PATCH)"
+    "expected http method (GET, POST, PUT, DELETE, PATCH, HEAD, OPTIONS)"
 );
 
 // fail a method that starts with |
 test_panic!(
     test_method_starting_with_pipe,
     quote! { |GET /;  return Empty;},
-    "expected http method (GET, POST, PUT, DELETE, PATCH)"
+    "expected http method (GET, POST, PUT, DELETE, PATCH, HEAD, OPTIONS)"
 );
 
 // fail a method that ends in |
 test_panic!(
     test_method_ending_with_pipe,
     quote! { GET| /;  return Empty;},
-    "expected http method (GET, POST, PUT, DELETE, PATCH)"
+    "expected http method (GET, POST, PUT, DELETE, PATCH, HEAD, OPTIONS)"
 );
 
 // fail a method that is duplicated
@@ -135,6 +135,20 @@ test_method!(
     vec![Method::PATCH]
 );
 
+// test a simple HEAD method
+test_method!(
+    test_method_head,
+    quote! { HEAD /; return Empty;},
+    vec![Method::HEAD]
+);
+
+// test a simple OPTIONS method
+test_method!(
+    test_method_options,
+    quote! { OPTIONS /; return Empty;},
+    vec![Method::OPTIONS]
+);
+
 // test GET|POST
 test_method!(
     test_method_get_post,
@@ -520,3 +534,197 @@ test_panic!(
     quote! { GET /; derive_debug; derive_debug; return HTML; },
     "duplicate `derive_debug` instruction"
 );
+
+// ----------------------------------------------------------------------------
+// test max_body_size
+
+macro_rules! test_max_body_size {
+    ($name:ident, $request_line:expr, $max_body_size:expr) => {
+        #[test]
+        fn $name() {
+            let http_function_inner =
+                match super::parse_http_module_inner($request_line, get_item_mod!()) {
+                    Ok(http_function_inner) => http_function_inner,
+                    Err(e) => e.panic(),
+                };
+            assert_eq!(http_function_inner.max_body_size, $max_body_size);
+        }
+    };
+}
+
+// pass a missing max_body_size=None
+test_max_body_size!(
+    test_max_body_size_none,
+    quote! { GET /; return HTML; },
+    None
+);
+
+// pass a max_body_size
+test_max_body_size!(
+    test_max_body_size,
+    quote! { POST /; max_body_size = 20MB; return HTML; },
+    Some(20 * 1024 * 1024)
+);
+
+// fail a duplicate max_body_size
+test_panic!(
+    test_max_body_size_duplicate,
+    quote! { POST /; max_body_size = 1MB; max_body_size = 2MB; return HTML; },
+    "duplicate `max_body_size` instruction"
+);
+
+// ----------------------------------------------------------------------------
+// test csrf
+
+macro_rules! test_csrf {
+    ($name:ident, $request_line:expr, $csrf:expr) => {
+        #[test]
+        fn $name() {
+            let http_function_inner =
+                match super::parse_http_module_inner($request_line, get_item_mod!()) {
+                    Ok(http_function_inner) => http_function_inner,
+                    Err(e) => e.panic(),
+                };
+            assert_eq!(http_function_inner.csrf, $csrf);
+        }
+    };
+}
+
+// pass a missing csrf=true
+test_csrf!(test_csrf_default, quote! { POST /; return HTML; }, true);
+
+// pass csrf = off
+test_csrf!(
+    test_csrf_off,
+    quote! { POST /; csrf = off; return HTML; },
+    false
+);
+
+// fail a duplicate csrf
+test_panic!(
+    test_csrf_duplicate,
+    quote! { POST /; csrf = off; csrf = on; return HTML; },
+    "duplicate `csrf` instruction"
+);
+
+// ----------------------------------------------------------------------------
+// test rate_limit
+
+macro_rules! test_rate_limit {
+    ($name:ident, $request_line:expr, $rate_limit:expr) => {
+        #[test]
+        fn $name() {
+            let http_function_inner =
+                match super::parse_http_module_inner($request_line, get_item_mod!()) {
+                    Ok(http_function_inner) => http_function_inner,
+                    Err(e) => e.panic(),
+                };
+            assert_eq!(http_function_inner.rate_limit, $rate_limit);
+        }
+    };
+}
+
+// pass a missing rate_limit=None
+test_rate_limit!(test_rate_limit_none, quote! { GET /; return HTML; }, None);
+
+// pass a rate_limit
+test_rate_limit!(
+    test_rate_limit,
+    quote! { GET|POST /login; rate_limit = 10/min; return HTML; },
+    Some(crate::RateLimit {
+        requests: 10,
+        period_secs: 60,
+        per: crate::RateLimitPer::Ip,
+    })
+);
+
+// fail a duplicate rate_limit
+test_panic!(
+    test_rate_limit_duplicate,
+    quote! { POST /; rate_limit = 10/min; rate_limit = 5/sec; return HTML; },
+    "duplicate `rate_limit` instruction"
+);
+
+// ----------------------------------------------------------------------------
+// test timeout
+
+macro_rules! test_timeout {
+    ($name:ident, $request_line:expr, $timeout:expr) => {
+        #[test]
+        fn $name() {
+            let http_function_inner =
+                match super::parse_http_module_inner($request_line, get_item_mod!()) {
+                    Ok(http_function_inner) => http_function_inner,
+                    Err(e) => e.panic(),
+                };
+            assert_eq!(http_function_inner.timeout, $timeout);
+        }
+    };
+}
+
+// pass a missing timeout=None
+test_timeout!(test_timeout_none, quote! { GET /; return HTML; }, None);
+
+// pass a timeout
+test_timeout!(
+    test_timeout,
+    quote! { GET /report; timeout = 2min; return HTML; },
+    Some(crate::Timeout::Millis(120_000))
+);
+
+// pass timeout = off
+test_timeout!(
+    test_timeout_off,
+    quote! { GET /export; timeout = off; return HTML; },
+    Some(crate::Timeout::Off)
+);
+
+// fail a duplicate timeout
+test_panic!(
+    test_timeout_duplicate,
+    quote! { GET /; timeout = 30s; timeout = 1min; return HTML; },
+    "duplicate `timeout` instruction"
+);
+
+// ----------------------------------------------------------------------------
+// test cors
+
+macro_rules! test_cors {
+    ($name:ident, $request_line:expr, $cors:expr) => {
+        #[test]
+        fn $name() {
+            let http_function_inner =
+                match super::parse_http_module_inner($request_line, get_item_mod!()) {
+                    Ok(http_function_inner) => http_function_inner,
+                    Err(e) => e.panic(),
+                };
+            assert_eq!(http_function_inner.cors, $cors);
+        }
+    };
+}
+
+// pass a missing cors=None
+test_cors!(test_cors_none, quote! { GET /; return JSON; }, None);
+
+// pass cors = off
+test_cors!(
+    test_cors_off,
+    quote! { GET /; cors = off; return JSON; },
+    Some(crate::Cors::Off)
+);
+
+// pass a list of origins
+test_cors!(
+    test_cors_origins,
+    quote! { GET /; cors = "https://app.example.com"; return JSON; },
+    Some(crate::Cors::Origins(vec![
+        "https://app.example.com".to_string()
+    ]))
+);
+
+// fail a duplicate cors
+test_panic!(
+    test_cors_duplicate,
+    quote! { GET /; cors = off; cors = "*"; return JSON; },
+    "duplicate `cors` instruction"
+);
@@ -5,52 +5,68 @@ pub fn quote_router(
     route_tree: &super::route_tree::RouteTree,
     tspaths: &[(String, String)],
 ) -> proc_macro2::TokenStream {
-    let match_arms = get_match_arms(1, route_tree);
+    let route_code = get_route_code(1, route_tree);
     let error_404_code = get_404_code();
 
     let traits = crate::codegen::quote_traits("APP", trait_list);
 
+    // static assets are only ever served to GET (and therefore HEAD) requests
+    let asset_methods = [
+        crate::Method::GET,
+        crate::Method::HEAD,
+        crate::Method::OPTIONS,
+    ];
+    let error_405_asset_code = get_405_code(&asset_methods);
+    let options_asset_code = get_options_code(&asset_methods);
+
     let mut tsarms = Vec::new();
+    let mut manifest_entries = Vec::new();
     for (abs_path, web_path) in tspaths.iter() {
         let ext = match web_path.rsplit_once('.') {
             Some((_, ext)) => ext,
             None => "",
         };
 
-        match ext {
-            "js" => {
-                tsarms.push(quote! {
-                    #web_path => {
-                        let text = include_str!(#abs_path);
-                        return Ok(approck::server::response::Response::JavaScript(text.into()));
-                    }
-                });
-            }
-            "css" => {
-                tsarms.push(quote! {
-                    #web_path => {
-                        let text = include_str!(#abs_path);
-                        return Ok(approck::server::response::Response::CSS(text.into()));
-                    }
-                });
-            }
-            "map" => {
-                tsarms.push(quote! {
-                    #web_path => {
-                        let text = include_str!(#abs_path);
-                        return Ok(approck::server::response::Response::JSON(text.into()));
-                    }
-                });
-            }
+        let content_type = match ext {
+            "js" => "text/javascript",
+            "css" => "text/css",
+            "map" => "application/json",
             _ => {
                 panic!("0x234234234; Unexpected file extension: {}", ext);
             }
-        }
+        };
+
+        let hash = get_asset_hash(abs_path);
+        let hashed_web_path = get_hashed_web_path(web_path, &hash);
+        let variants = get_precompressed_variants(abs_path);
+
+        // served at both paths, but only the hashed one may be cached forever
+        tsarms.push(quote! {
+            #web_path | #hashed_web_path => {
+                match req.method().as_str() {
+                    "GET" | "HEAD" => {}
+                    "OPTIONS" => return #options_asset_code,
+                    _ => return #error_405_asset_code,
+                }
+                static ASSET: approck::server::assets::Asset = approck::server::assets::Asset {
+                    content_type: #content_type,
+                    content: include_bytes!(#abs_path),
+                    hash: #hash,
+                    variants: &[#(#variants),*],
+                };
+                let hashed = req.path() == #hashed_web_path;
+                return Ok(approck::server::assets::serve(&req, &ASSET, hashed));
+            }
+        });
+
+        manifest_entries.push(quote! {
+            (#web_path, #hashed_web_path),
+        });
     }
 
     quote!(
-        #[allow(clippy::match_single_binding,unused_variables)]
-        pub async fn router<'a, #traits>(app: &'static APP, req: approck::server::Request<'a>) -> approck::Result<approck::server::response::Response> {
+        #[allow(clippy::collapsible_if,clippy::get_first,clippy::identity_op,clippy::match_single_binding,clippy::single_match,unused_mut,unused_variables)]
+        pub async fn router<'a, #traits>(app: &'static APP, mut req: approck::server::Request<'a>) -> approck::Result<approck::server::response::Response> {
 
             #[allow(clippy::single_match)]
             match req.path() {
@@ -58,183 +74,323 @@ pub fn quote_router(
                 _ => {}
             }
 
-            let path_vec = req.path_chunks();
-            let mut path_parts = path_vec.into_iter();
-            match &path_parts.next() {
-                #(#match_arms),*
-                _ => {#error_404_code}
+            // the methods accepted by the routes whose path matched, when none of them took this one
+            let mut allowed_methods: Vec<approck::server::Method> = Vec::new();
+
+            // owned, so a preflight can still set its cors on the request while routes are tried
+            let path_vec: Vec<String> = req.path_chunks().into_iter().map(String::from).collect();
+            #(#route_code)*
+
+            if !allowed_methods.is_empty() {
+                if req.method() == approck::server::Method::OPTIONS {
+                    return Ok(approck::server::response::Response::Options(
+                        approck::server::response::Options::new(allowed_methods)
+                    ));
+                }
+                return Ok(approck::server::response::Response::MethodNotAllowed(
+                    approck::server::response::MethodNotAllowed::new(allowed_methods)
+                ));
             }
+            #error_404_code
         }
+
+        /// The hashed path of every asset, by plain path
+        pub static ASSET_MANIFEST: approck::server::assets::Manifest = approck::server::assets::Manifest::new(&[
+            #(#manifest_entries)*
+        ]);
     )
 }
 
-fn get_match_arms(
-    depth: i32,
+/// The first 16 hex digits of the sha256 of the asset, which change whenever its content does
+fn get_asset_hash(abs_path: &str) -> String {
+    use sha2::Digest;
+
+    let content = std::fs::read(abs_path)
+        .unwrap_or_else(|e| panic!("0x234234235; Error reading asset {}: {}", abs_path, e));
+    let digest = sha2::Sha256::digest(&content);
+    digest[..8].iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// `/app.js` becomes `/app.<hash>.js`, and `/app.js.map` becomes `/app.<hash>.js.map`
+fn get_hashed_web_path(web_path: &str, hash: &str) -> String {
+    let (dir, file_name) = web_path.rsplit_once('/').unwrap_or(("", web_path));
+    match file_name.split_once('.') {
+        Some((stem, ext)) => format!("{}/{}.{}.{}", dir, stem, hash, ext),
+        None => format!("{}/{}.{}", dir, file_name, hash),
+    }
+}
+
+/// The compressed copies `acp build` wrote next to an asset, as `(Encoding, bytes)` pairs
+fn get_precompressed_variants(abs_path: &str) -> Vec<proc_macro2::TokenStream> {
+    let encodings = [("br", "Brotli"), ("zst", "Zstd"), ("gz", "Gzip")];
+
+    let mut variants = Vec::new();
+    for (suffix, encoding) in encodings {
+        let variant_path = format!("{}.{}", abs_path, suffix);
+        if std::path::Path::new(&variant_path).exists() {
+            let encoding = syn::Ident::new(encoding, proc_macro2::Span::call_site());
+            variants.push(quote! {
+                (approck::server::compression::Encoding::#encoding, include_bytes!(#variant_path))
+            });
+        }
+    }
+    variants
+}
+
+/// Generates an `if` for each branch of `route_tree`, tried in order.  A branch returns only when
+/// the path and the method both match one of its functions; otherwise it falls through to its
+/// siblings, so `GET /user/{id:i32}` does not stop `POST /user/{name:String}` from being found.
+/// Branches which match the path but not the method add the methods they do accept to
+/// `allowed_methods`, for the `405 Method Not Allowed` sent if nothing else matches.
+fn get_route_code(
+    depth: usize,
     route_tree: &super::route_tree::RouteTree,
 ) -> Vec<proc_macro2::TokenStream> {
-    let mut match_arms = Vec::new();
-    for (path_part, route_tree) in &route_tree.route_tree {
-        let function = &route_tree.function;
+    let index = depth - 1;
 
-        let match_item = match (path_part, function) {
-            // Index with a function (e.g. only a handler)
-            (crate::PathPart::Index, Some(function)) => {
-                let function_call_code = get_function_call_code(function);
-                let error_404_code = get_404_code();
+    let mut route_code = Vec::new();
+    for (path_part, route_tree) in &route_tree.route_tree {
+        let sub_route_code = get_route_code(depth + 1, route_tree);
+        let function_call_code = match route_tree.functions.is_empty() {
+            true => quote!(),
+            false => {
+                let function_call_code = get_function_call_code(route_tree);
                 quote!(
-                    Some("") => {
-                        match &path_parts.next() {
-                            None => {#function_call_code}
-                            _ => {#error_404_code}
-                        }
+                    if path_vec.len() == #index + 1 {
+                        #function_call_code
                     }
                 )
             }
-            // Literal with a function (e.g. either a handler or recurse deeper)
-            (crate::PathPart::Literal(literal), Some(function)) => {
-                let function_call_code = get_function_call_code(function);
-                let sub_match_arms = get_match_arms(depth + 1, route_tree);
-                let error_404_code = get_404_code();
+        };
 
+        let branch = match path_part {
+            // Index (e.g. a trailing slash), which has no deeper routes
+            crate::PathPart::Index => {
                 quote!(
-                    Some(#literal) => match &path_parts.next() {
-                        None => {#function_call_code}
-                        #(#sub_match_arms)*
-                        _ => {#error_404_code}
+                    if path_vec.get(#index).map(String::as_str) == Some("") {
+                        #function_call_code
                     }
                 )
             }
-            // Literal without a function (e.g. just a directory, no handler)
-            (crate::PathPart::Literal(literal), None) => {
-                let sub_match_arms = get_match_arms(depth + 1, route_tree);
-                let error_404_code = get_404_code();
+
+            // Literal (e.g. either a handler or recurse deeper)
+            crate::PathPart::Literal(literal) => {
                 quote!(
-                    Some(#literal) => match &path_parts.next() {
-                        #(#sub_match_arms)*
-                        _ => {#error_404_code}
+                    if path_vec.get(#index).map(String::as_str) == Some(#literal) {
+                        #function_call_code
+                        #(#sub_route_code)*
                     }
                 )
             }
 
-            // Capture with a function (e.g. either a handler or recurse deeper)
-            (crate::PathPart::Capture { name: _, capture }, optional_function) => {
-                let sub_match_arms = get_match_arms(depth + 1, route_tree);
-                let error_404_code = get_404_code();
-
-                // create an ident called capture_{capture_name}
+            // Capture (e.g. either a handler or recurse deeper)
+            crate::PathPart::Capture { name: _, capture } => {
+                // create an ident called capture_{depth}
                 let capture_ident =
                     syn::parse_str::<syn::Ident>(&format!("capture_{}", depth)).unwrap();
 
                 let (guard, guard_conversion) = match capture {
-                    // Strings don't have a guard
+                    // Strings only need to be present
                     crate::PathPartCapture::String { .. } => (
-                        quote! { if !#capture_ident.is_empty() },
+                        quote! { !#capture_ident.is_empty() },
                         quote! { let #capture_ident = #capture_ident.to_string(); },
                     ),
 
                     // Integers need cast into their respective types
                     crate::PathPartCapture::i8 { .. } => (
-                        quote! { if #capture_ident.parse::<i8>().is_ok() },
+                        quote! { #capture_ident.parse::<i8>().is_ok() },
                         quote! { let #capture_ident = #capture_ident.parse::<i8>()?; },
                     ),
                     crate::PathPartCapture::u8 { .. } => (
-                        quote! { if #capture_ident.parse::<u8>().is_ok() },
+                        quote! { #capture_ident.parse::<u8>().is_ok() },
                         quote! { let #capture_ident = #capture_ident.parse::<u8>()?; },
                     ),
                     crate::PathPartCapture::i32 { .. } => (
-                        quote! { if #capture_ident.parse::<i32>().is_ok() },
+                        quote! { #capture_ident.parse::<i32>().is_ok() },
                         quote! { let #capture_ident = #capture_ident.parse::<i32>()?; },
                     ),
                     crate::PathPartCapture::u32 { .. } => (
-                        quote! { if #capture_ident.parse::<u32>().is_ok() },
+                        quote! { #capture_ident.parse::<u32>().is_ok() },
                         quote! { let #capture_ident = #capture_ident.parse::<u32>()?; },
                     ),
                     crate::PathPartCapture::i64 { .. } => (
-                        quote! { if #capture_ident.parse::<i64>().is_ok() },
+                        quote! { #capture_ident.parse::<i64>().is_ok() },
                         quote! { let #capture_ident = #capture_ident.parse::<i64>()?; },
                     ),
                     crate::PathPartCapture::u64 { .. } => (
-                        quote! { if #capture_ident.parse::<u64>().is_ok() },
+                        quote! { #capture_ident.parse::<u64>().is_ok() },
                         quote! { let #capture_ident = #capture_ident.parse::<u64>()?; },
                     ),
                     crate::PathPartCapture::usize { .. } => (
-                        quote! { if #capture_ident.parse::<usize>().is_ok() },
+                        quote! { #capture_ident.parse::<usize>().is_ok() },
                         quote! { let #capture_ident = #capture_ident.parse::<usize>()?; },
                     ),
                 };
 
-                match optional_function {
-                    Some(function) => {
-                        let function_call_code = get_function_call_code(function);
-                        quote! (
-                            Some(#capture_ident) #guard => {
-                                #guard_conversion
-                                match &path_parts.next() {
-                                    #(#sub_match_arms)*
-                                    None => {#function_call_code}
-                                    _ => {#error_404_code}
-                                }
-                            }
-                        )
-                    }
-                    None => {
-                        quote!(
-                            Some(#capture_ident) #guard => {
-                                guard_conversion
-                                match &path_parts.next() {
-                                    #(#sub_match_arms)*
-                                    _ => {#error_404_code}
-                                }
-                            }
-                        )
+                quote!(
+                    if let Some(#capture_ident) = path_vec.get(#index).map(String::as_str) {
+                        if #guard {
+                            #guard_conversion
+                            #function_call_code
+                            #(#sub_route_code)*
+                        }
                     }
+                )
+            }
+        };
+
+        route_code.push(branch);
+    }
+
+    route_code
+}
+
+/// Generates a match on the request method which returns from the wrapper of whichever function
+/// handles it, or adds the methods this path does accept to `allowed_methods` if none of them do
+/// (which is how OPTIONS is answered, unless a function handles it).
+fn get_function_call_code(route_tree: &super::route_tree::RouteTree) -> proc_macro2::TokenStream {
+    let mut method_arms = Vec::new();
+    let implicit_head = !route_tree.methods().contains(&crate::Method::HEAD);
+
+    for function in &route_tree.functions {
+        let wrapper_ident =
+            syn::parse_str::<syn::Path>(&format!("{}::wrap", &function.rust_ident)).unwrap();
+
+        let path_fields = function
+            .inner
+            .iter_path_captures()
+            .map(|(level, _name, _capture)| {
+                let var_ident = syn::Ident::new(
+                    &format!("capture_{}", level),
+                    proc_macro2::Span::call_site(),
+                );
+                quote! {
+                    #var_ident,
                 }
+            });
+
+        let mut methods: Vec<&str> = function
+            .inner
+            .methods
+            .iter()
+            .map(|method| method.as_str())
+            .collect();
+
+        // HEAD runs the GET handler; the server discards the body on the way out
+        if function.inner.methods.contains(&crate::Method::GET) && implicit_head {
+            methods.push(crate::Method::HEAD.as_str());
+        }
+
+        method_arms.push(quote!(
+            #(#methods)|* => {
+                return #wrapper_ident(app, req, #(#path_fields)*).await;
+            }
+        ));
+    }
+
+    // OPTIONS is answered by the router from the methods of every route matching the path,
+    // including CORS preflights, which get the `cors` instruction of the function handling the
+    // method they ask about
+    let cors_code = get_preflight_cors_code(route_tree);
+    let preflight_cors_code = match route_tree.methods().contains(&crate::Method::OPTIONS) {
+        true => quote!(),
+        false if cors_code.is_empty() => quote!(),
+        false => quote!(
+            if req.method() == approck::server::Method::OPTIONS {
+                #cors_code
             }
+        ),
+    };
+
+    let allowed_methods = quote_methods(&route_tree.allowed_methods());
 
-            // No other valid combinations exist
+    quote!(
+        match req.method().as_str() {
+            #(#method_arms)*
             _ => {
-                panic!(
-                    "0x4353453423; Unexpected combination of path_part {:?} function {:?}",
-                    path_part,
-                    match function {
-                        Some(function) => function.rust_ident.clone(),
-                        None => "None".to_string(),
+                #preflight_cors_code
+                for method in [#(#allowed_methods),*] {
+                    if !allowed_methods.contains(&method) {
+                        allowed_methods.push(method);
                     }
-                );
+                }
             }
+        }
+    )
+}
+
+fn get_preflight_cors_code(route_tree: &super::route_tree::RouteTree) -> proc_macro2::TokenStream {
+    let implicit_head = !route_tree.methods().contains(&crate::Method::HEAD);
+
+    let mut cors_arms = Vec::new();
+    for function in &route_tree.functions {
+        let cors = match &function.inner.cors {
+            Some(cors) => crate::codegen::quote_route_cors(cors),
+            None => continue,
         };
 
-        match_arms.push(match_item);
-    }
+        let mut methods: Vec<&str> = function
+            .inner
+            .methods
+            .iter()
+            .map(|method| method.as_str())
+            .collect();
+        if function.inner.methods.contains(&crate::Method::GET) && implicit_head {
+            methods.push(crate::Method::HEAD.as_str());
+        }
 
-    match_arms
-}
+        cors_arms.push(quote!(
+            #(#methods)|* => req.set_cors(#cors),
+        ));
+    }
 
-fn get_function_call_code(function: &crate::HttpModule) -> proc_macro2::TokenStream {
-    let wrapper_ident =
-        syn::parse_str::<syn::Path>(&format!("{}::wrap", &function.rust_ident)).unwrap();
-
-    let path_fields = function
-        .inner
-        .iter_path_captures()
-        .map(|(level, _name, _capture)| {
-            let var_ident = syn::Ident::new(
-                &format!("capture_{}", level),
-                proc_macro2::Span::call_site(),
-            );
-            quote! {
-                #var_ident,
-            }
-        });
+    if cors_arms.is_empty() {
+        return quote!();
+    }
 
     quote!(
-        #wrapper_ident(app, req, #(#path_fields)*).await
+        let requested_method = req
+            .headers()
+            .get("access-control-request-method")
+            .and_then(|value| value.to_str().ok())
+            .unwrap_or_default()
+            .to_string();
+        match requested_method.as_str() {
+            #(#cors_arms)*
+            _ => {}
+        }
     )
 }
 
 fn get_404_code() -> proc_macro2::TokenStream {
     // TODO: instead return an Err(approck::Error)
     quote!(Ok(approck::server::response::Response::NotFound(
-        approck::server::response::NotFound
+        approck::server::response::NotFound::default()
     )))
 }
+
+fn get_405_code(methods: &[crate::Method]) -> proc_macro2::TokenStream {
+    let methods = quote_methods(methods);
+
+    quote!(Ok(approck::server::response::Response::MethodNotAllowed(
+        approck::server::response::MethodNotAllowed::new(vec![#(#methods),*])
+    )))
+}
+
+fn get_options_code(methods: &[crate::Method]) -> proc_macro2::TokenStream {
+    let methods = quote_methods(methods);
+
+    quote!(Ok(approck::server::response::Response::Options(
+        approck::server::response::Options::new(vec![#(#methods),*])
+    )))
+}
+
+fn quote_methods(methods: &[crate::Method]) -> Vec<proc_macro2::TokenStream> {
+    methods
+        .iter()
+        .map(|method| {
+            let method_ident = syn::Ident::new(method.as_str(), proc_macro2::Span::call_site());
+            quote!(approck::server::Method::#method_ident)
+        })
+        .collect()
+}
@@ -2,21 +2,23 @@ use crate::{HttpModule, PathPart};
 use std::collections::HashMap;
 
 pub struct RouteTree {
-    pub function: Option<HttpModule>,
+    /// Every function that terminates at this node.  More than one is allowed as long as their
+    /// http methods do not overlap (e.g. a GET page in one file and a POST api in another)
+    pub functions: Vec<HttpModule>,
     pub route_tree: Vec<(crate::PathPart, RouteTree)>,
 }
 
 impl RouteTree {
     pub fn new() -> Self {
         Self {
-            function: None,
+            functions: Vec::new(),
             route_tree: Vec::new(),
         }
     }
 
     fn sort(self) -> RouteTree {
         let mut rval = RouteTree::new();
-        rval.function = self.function;
+        rval.functions = self.functions;
 
         for (path_part, route_tree_builder) in self.route_tree {
             rval.route_tree.push((path_part, route_tree_builder.sort()));
@@ -24,6 +26,33 @@ impl RouteTree {
 
         rval
     }
+
+    /// The union of all methods handled by the functions at this node, in the order they were
+    /// declared
+    pub fn methods(&self) -> Vec<crate::Method> {
+        let mut rval = Vec::new();
+        for function in &self.functions {
+            for method in &function.inner.methods {
+                if !rval.contains(method) {
+                    rval.push(*method);
+                }
+            }
+        }
+        rval
+    }
+
+    /// The methods the router will answer at this node.  HEAD is implied by GET and OPTIONS is
+    /// always answered, unless a function declares them explicitly.
+    pub fn allowed_methods(&self) -> Vec<crate::Method> {
+        let mut rval = self.methods();
+        if rval.contains(&crate::Method::GET) && !rval.contains(&crate::Method::HEAD) {
+            rval.push(crate::Method::HEAD);
+        }
+        if !rval.contains(&crate::Method::OPTIONS) {
+            rval.push(crate::Method::OPTIONS);
+        }
+        rval
+    }
 }
 
 /// Call this with the full list of functions and 0 as the level
@@ -34,16 +63,27 @@ fn extract_route_tree(function_list: Vec<HttpModule>, index: usize) -> RouteTree
     // prepare fmap to use to recuse with
     for function in function_list {
         if function.inner.path.len() == index {
-            if let Some(existing_function) = rval.function {
-                panic!(
-                    "\n\n0x8302928472; Conflicting functions at level {} path {:?}\nfunction is already in place: {:#?}\n\n",
-                    index,
-                    function.inner.path,
-                    existing_function.inner.mod_name,
-                );
+            for existing_function in &rval.functions {
+                if let Some(method) = function
+                    .inner
+                    .methods
+                    .iter()
+                    .find(|method| existing_function.inner.methods.contains(method))
+                {
+                    panic!(
+                        "\n\n0x8302928472; Conflicting functions for {} at level {} path {:?}\nfunction is already in place: {} in {}\nconflicting function: {} in {}\n\n",
+                        method.as_str(),
+                        index,
+                        function.inner.path,
+                        existing_function.rust_ident,
+                        existing_function.rel_path,
+                        function.rust_ident,
+                        function.rel_path,
+                    );
+                }
             }
 
-            rval.function = Some(function);
+            rval.functions.push(function);
         } else {
             let (_level, path_part) = &function.inner.path[index];
 
@@ -79,3 +119,87 @@ pub fn compile_route_tree(function_list: Vec<HttpModule>) -> RouteTree {
     // create a new copy of it, sorted
     route_tree.sort()
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::{HttpModule, Method, PathPart};
+    use quote::quote;
+
+    fn http_module(rust_ident: &str, attr: proc_macro2::TokenStream) -> HttpModule {
+        let item_mod: syn::ItemMod = syn::parse2(quote! {
+            pub mod page {
+                pub async fn request() -> Response {
+                    Response::Empty
+                }
+            }
+        })
+        .unwrap();
+
+        let inner = match crate::http_macro::parse_http_module_inner(attr, item_mod) {
+            Ok(inner) => inner,
+            Err(e) => e.panic(),
+        };
+
+        HttpModule {
+            rel_path: format!("{}.rs", rust_ident.replace("::", "/")),
+            fn_line: 1,
+            rust_ident: rust_ident.to_string(),
+            inner,
+        }
+    }
+
+    fn find<'a>(route_tree: &'a super::RouteTree, literal: &str) -> &'a super::RouteTree {
+        route_tree
+            .route_tree
+            .iter()
+            .find(|(path_part, _)| *path_part == PathPart::Literal(literal.to_string()))
+            .map(|(_, route_tree)| route_tree)
+            .unwrap()
+    }
+
+    #[test]
+    fn test_shared_path_with_distinct_methods() {
+        let route_tree = super::compile_route_tree(vec![
+            http_module("crate::a::page", quote! { GET /foo; return HTML; }),
+            http_module("crate::b::page", quote! { POST|DELETE /foo; return JSON; }),
+        ]);
+
+        let foo = find(&route_tree, "foo");
+        assert_eq!(foo.functions.len(), 2);
+        assert_eq!(
+            foo.methods(),
+            vec![Method::GET, Method::POST, Method::DELETE]
+        );
+    }
+
+    #[test]
+    fn test_allowed_methods_implies_head_and_options() {
+        let route_tree = super::compile_route_tree(vec![
+            http_module("crate::a::page", quote! { GET|POST /foo; return HTML; }),
+            http_module("crate::b::page", quote! { PUT /bar; return JSON; }),
+            http_module("crate::c::page", quote! { GET|HEAD /baz; return Empty; }),
+        ]);
+
+        assert_eq!(
+            find(&route_tree, "foo").allowed_methods(),
+            vec![Method::GET, Method::POST, Method::HEAD, Method::OPTIONS]
+        );
+        assert_eq!(
+            find(&route_tree, "bar").allowed_methods(),
+            vec![Method::PUT, Method::OPTIONS]
+        );
+        assert_eq!(
+            find(&route_tree, "baz").allowed_methods(),
+            vec![Method::GET, Method::HEAD, Method::OPTIONS]
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Conflicting functions for POST")]
+    fn test_shared_path_with_overlapping_methods() {
+        super::compile_route_tree(vec![
+            http_module("crate::a::page", quote! { GET|POST /foo; return HTML; }),
+            http_module("crate::b::page", quote! { POST /foo; return JSON; }),
+        ]);
+    }
+}
@@ -27,6 +27,16 @@ pub struct HttpModuleInner {
     pub path: Vec<(u8, PathPart)>,
     pub query_string: Option<Vec<QueryStringPart>>,
     pub derive_debug: bool,
+    /// Overrides the server wide maximum request body size (in bytes) for this route
+    pub max_body_size: Option<u64>,
+    /// How many requests a client may make to this route in a period (`rate_limit = 10/min;`)
+    pub rate_limit: Option<RateLimit>,
+    /// Overrides the server wide CORS origins for this route (`cors = off;` or a list of origins)
+    pub cors: Option<Cors>,
+    /// Whether `PostForm` submissions must carry the session's CSRF token (`csrf = off;` to opt out)
+    pub csrf: bool,
+    /// Overrides the server wide request timeout for this route (`timeout = 30s;` or `timeout = off;`)
+    pub timeout: Option<Timeout>,
     pub return_types: ReturnTypes,
     pub mod_name: String,
     pub mod_ident: syn::Ident,
@@ -91,13 +101,30 @@ impl HttpModuleInner {
     }
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
 pub enum Method {
     GET,
     POST,
     PUT,
     DELETE,
     PATCH,
+    HEAD,
+    OPTIONS,
+}
+
+impl Method {
+    /// The method as it appears on the wire, e.g. in an `Allow` header
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Method::GET => "GET",
+            Method::POST => "POST",
+            Method::PUT => "PUT",
+            Method::DELETE => "DELETE",
+            Method::PATCH => "PATCH",
+            Method::HEAD => "HEAD",
+            Method::OPTIONS => "OPTIONS",
+        }
+    }
 }
 
 /// This represnts a chunk of the path
@@ -210,6 +237,8 @@ pub enum QueryStringValueType {
     u64,
     f32,
     f64,
+    /// Only valid in a `PostForm`, for `multipart/form-data` file uploads
+    UploadedFile,
 }
 
 impl QueryStringValueType {
@@ -222,6 +251,9 @@ impl QueryStringValueType {
             QueryStringValueType::u64 => quote! { u64 },
             QueryStringValueType::f32 => quote! { f32 },
             QueryStringValueType::f64 => quote! { f64 },
+            QueryStringValueType::UploadedFile => {
+                quote! { ::approck::server::multipart::UploadedFile }
+            }
         }
     }
 }
@@ -241,6 +273,36 @@ pub struct ReturnTypes {
     pub Redirect: bool,
     pub WebSocketUpgrade: bool,
     pub Stream: bool,
+    pub SSE: bool,
+}
+
+/// Parsed from `rate_limit = 10/min;` or `rate_limit = 10/min per session;`
+#[derive(Debug, PartialEq)]
+pub struct RateLimit {
+    pub requests: u64,
+    pub period_secs: u64,
+    pub per: RateLimitPer,
+}
+
+/// What the requests of a [`RateLimit`] are counted by
+#[derive(Debug, PartialEq)]
+pub enum RateLimitPer {
+    Ip,
+    Session,
+}
+
+/// Parsed from `timeout = 30s;` or `timeout = off;`
+#[derive(Debug, PartialEq)]
+pub enum Timeout {
+    Off,
+    Millis(u64),
+}
+
+/// Parsed from `cors = off;` or `cors = "https://app.example.com", ...;`
+#[derive(Debug, PartialEq)]
+pub enum Cors {
+    Off,
+    Origins(Vec<String>),
 }
 
 #[derive(Debug, PartialEq)]
@@ -253,21 +315,30 @@ pub enum PostType {
     None,
     Struct(PostTypeStruct),
     Enum(PostTypeEnum),
+    Json(PostTypeJson),
 }
 
 pub struct PostTypeStruct {
     token_stream: proc_macro2::TokenStream,
     query_string_parts: Vec<QueryStringPart>,
+    /// Field name and maximum size in bytes, from `#[max_size(5MB)]` on the field
+    max_sizes: Vec<(String, u64)>,
 }
 
 pub struct PostTypeEnum {}
 
+/// A `PostJson` struct, which is deserialized from a JSON request body with serde
+pub struct PostTypeJson {
+    token_stream: proc_macro2::TokenStream,
+}
+
 impl Debug for PostType {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             PostType::None => write!(f, "None"),
             PostType::Struct(_) => write!(f, "Form(...)"),
             PostType::Enum(_) => write!(f, "Enum(...)"),
+            PostType::Json(_) => write!(f, "Json(...)"),
         }
     }
 }
@@ -296,6 +367,9 @@ pub enum ParamType {
     QueryStringOption,     // Option<QueryString>
     PostForm,              // PostForm
     PostFormOption,        // Option<PostForm>
+    PostJson,              // PostJson
+    PostJsonOption,        // Option<PostJson>
+    Session,               // Session
     OptionalParam(String), // Option<v>
 }
 
@@ -1,4 +1,5 @@
 mod approck_generated;
+mod middleware;
 mod ui;
 mod user;
 mod web;
@@ -15,10 +16,19 @@ pub struct AppConfig {
 
 impl AppConfig {
     pub async fn into_system(self) -> granite::Result<AppSystem> {
+        let redis_system = self.redis.into_system().await?;
+        let session_store =
+            approck::server::session::RedisSessionStore::new(redis_system.pool().clone());
+        let rate_limit_store =
+            approck::server::rate_limit::RedisRateLimitStore::new(redis_system.pool().clone());
+
         Ok(AppSystem {
-            redis_system: self.redis.into_system().await?,
+            redis_system,
             postgres_system: self.postgres.into_system().await?,
             webserver_system: self.webserver.into_system(),
+            webserver_middleware: vec![Box::new(middleware::RequestTimer)],
+            session_store,
+            rate_limit_store,
         })
     }
 }
@@ -27,6 +37,9 @@ pub struct AppSystem {
     pub redis_system: granite_redis::RedisSystem,
     pub postgres_system: granite_postgres::PostgresSystem,
     pub webserver_system: approck::server::WebServerSystem,
+    pub webserver_middleware: Vec<Box<dyn approck::server::middleware::Middleware>>,
+    pub session_store: approck::server::session::RedisSessionStore,
+    pub rate_limit_store: approck::server::rate_limit::RedisRateLimitStore,
 }
 
 impl approck::traits::DocumentModule for AppSystem {
@@ -63,8 +76,45 @@ impl approck::server::WebServerModule for AppSystem {
         approck_generated::router(self, req).await
     }
 
+    fn webserver_asset_manifest(&'static self) -> &'static approck::server::assets::Manifest {
+        &approck_generated::ASSET_MANIFEST
+    }
+
+    fn webserver_middleware(
+        &'static self,
+    ) -> &'static [Box<dyn approck::server::middleware::Middleware>] {
+        &self.webserver_middleware
+    }
+
+    fn webserver_session_store(
+        &'static self,
+    ) -> &'static dyn approck::server::session::SessionStore {
+        &self.session_store
+    }
+
+    fn webserver_rate_limit_store(
+        &'static self,
+    ) -> &'static dyn approck::server::rate_limit::RateLimitStore {
+        &self.rate_limit_store
+    }
+
+    async fn webserver_readiness(&'static self) -> Vec<approck::server::health::Component> {
+        let timeout = self.webserver_system.health().timeout();
+        let (postgres, redis) = futures::join!(
+            approck::server::health::postgres(self, timeout, self.postgres_system.pool_status()),
+            approck::server::health::redis(self, timeout, self.redis_system.pool_status()),
+        );
+        vec![postgres, redis]
+    }
+
+    fn webserver_metrics(&'static self, metrics: &mut approck::server::metrics::Metrics) {
+        metrics.pool("postgres", self.postgres_system.pool_status());
+        metrics.pool("redis", self.redis_system.pool_status());
+    }
+
     fn webserver_handle_error(&self, error: granite::Error) -> approck::server::response::Result {
         let mut doc = self.get_document();
+        doc.set_status(approck::server::error_status_code(&error));
 
         doc.add_body(maud::html! {
             div.container.bg-white {
@@ -0,0 +1,22 @@
+use approck::server::middleware::{Middleware, Next};
+use approck::server::Request;
+
+/// Logs how long each request took to route
+pub struct RequestTimer;
+
+#[async_trait::async_trait]
+impl Middleware for RequestTimer {
+    async fn around<'a>(
+        &self,
+        req: Request<'a>,
+        next: Next<'a>,
+    ) -> approck::server::response::Result {
+        let start = std::time::Instant::now();
+
+        let response = next.run(req).await;
+
+        // method, path and request id are already on the request's span
+        tracing::info!(route_ms = start.elapsed().as_secs_f64() * 1000.0, "routed");
+        response
+    }
+}
@@ -1,4 +1,4 @@
-#[approck::http(GET /api/v1/list; return JSON;)]
+#[approck::http(GET /api/v1/list; cors = "*"; return JSON;)]
 pub mod data {
     pub async fn request() -> Response {
         Response::JSON("[a,b,c]".into())
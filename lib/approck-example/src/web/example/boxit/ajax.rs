@@ -1,4 +1,4 @@
-#[approck::http(GET /example/boxit/ajax/random-xy; return JSON;)]
+#[approck::http(GET|POST /example/boxit/ajax/random-xy; return JSON;)]
 pub mod random_xy {
     use rand::Rng;
     use serde_json::json;
@@ -16,10 +16,20 @@ pub mod random_xy {
     static WIDTH: f64 = 2000.0;
     static HEIGHT: f64 = 800.0;
 
-    pub async fn request() -> Response {
+    /// POST `{"max_size": 50.0}` to limit how big the box can be
+    pub struct PostJson {
+        pub max_size: f64,
+    }
+
+    pub async fn request(post_json: Option<PostJson>) -> Response {
+        let max_size = match post_json {
+            Some(post_json) => post_json.max_size.clamp(11.0, 100.0),
+            None => 100.0,
+        };
+
         let mut rng = rand::thread_rng();
-        let width: f64 = rng.gen_range(10.0..100.0);
-        let height: f64 = rng.gen_range(10.0..100.0);
+        let width: f64 = rng.gen_range(10.0..max_size);
+        let height: f64 = rng.gen_range(10.0..max_size);
         let x: f64 = rng.gen_range(0.0..(WIDTH - width));
         let y: f64 = rng.gen_range(45.0..(HEIGHT - height));
         let color: &str = COLORS[rng.gen_range(0..50)];
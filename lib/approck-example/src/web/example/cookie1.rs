@@ -0,0 +1,59 @@
+#[approck::http(GET|POST /example/cookie1; return HTML;)]
+pub mod page {
+    pub struct PostForm {
+        color: String,
+    }
+
+    pub async fn request(req: Request, doc: Document, form: Option<PostForm>) -> Result<Response> {
+        // plain cookies can be read and changed by the client
+        let color = match &form {
+            Some(form) => form.color.clone(),
+            None => req
+                .cookie("color")
+                .map(|cookie| cookie.value().to_string())
+                .unwrap_or_default(),
+        };
+
+        // signed cookies can be read but not changed, so the count can be trusted
+        let visits = req
+            .signed_cookie("visits")
+            .and_then(|cookie| cookie.value().parse::<u32>().ok())
+            .unwrap_or(0)
+            + 1;
+
+        let csrf_token = req.csrf_token().await?;
+
+        #[rustfmt::skip]
+        doc.add_body(maud::html! {
+            div.container.bg-white {
+                a href="/example/" { "◂ Back to Example List" }
+                h1 { code {  (req.path()) } }
+
+                hr;
+
+                p { "Favorite color (plain cookie): " b { (color) } }
+                p { "Visits (signed cookie): " b { (visits) } }
+
+                form method="post" action=(req.path()) class="g-3" {
+                    (csrf_token)
+                    div class="mb-3" {
+                        label for="color" class="form-label" { "Favorite Color" }
+                        input.form-control type="text" id="color" name="color" value=(color) {};
+                    }
+                    button.btn.btn-primary type="submit" { "Save" }
+                }
+            }
+        });
+
+        let mut response: approck::server::response::HTML = doc.into();
+        if form.is_some() {
+            response.add_cookie(req.new_cookie("color", color).build());
+        }
+        response.add_signed_cookie(
+            req.cookie_key(),
+            req.new_cookie("visits", visits.to_string()).build(),
+        );
+
+        Ok(Response::HTML(response))
+    }
+}
@@ -21,6 +21,10 @@ pub mod page {
                             li {a href="/example/userlist1" { "User List 1" } }
                             li {a href="/example/websocket1" { "Websocket Example 1" } }
                             li {a href="/example/stream" { "Stream Response Example (4gb download)" } }
+                            li {a href="/example/sse1" { "Server-Sent Events 1 with reconnect" } }
+                            li {a href="/example/upload1" { "File Upload 1 with multipart/form-data" } }
+                            li {a href="/example/session1" { "Session 1 with login and logout" } }
+                            li {a href="/example/cookie1" { "Cookie 1 with plain and signed cookies" } }
                         }
                     }
                     ."col-md-6" {
@@ -1,4 +1,5 @@
 pub mod boxit;
+pub mod cookie1;
 pub mod index;
 pub mod name;
 pub mod pathcap1;
@@ -9,6 +10,9 @@ pub mod querystring2;
 pub mod querystring3;
 pub mod querystring4;
 pub mod redis1;
+pub mod session1;
+pub mod sse1;
 pub mod stream;
+pub mod upload1;
 pub mod userlist1;
 pub mod websocket1;
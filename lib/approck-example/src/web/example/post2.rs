@@ -7,7 +7,7 @@ pub mod page {
         message: String,
     }
 
-    pub async fn request(req: Request, ui: Document, form: Option<PostForm>) -> Response {
+    pub async fn request(req: Request, ui: Document, form: Option<PostForm>) -> Result<Response> {
         let form = match form {
             Some(form) => form,
             None => PostForm {
@@ -18,9 +18,11 @@ pub mod page {
         };
 
         if form.message == "redirect" {
-            return Response::Redirect("/example/".into());
+            return Ok(Response::Redirect("/example/".into()));
         }
 
+        let csrf_token = req.csrf_token().await?;
+
         #[rustfmt::skip]
         ui.add_body(maud::html! {
             div.container.bg-white {
@@ -46,6 +48,7 @@ pub mod page {
                     "In the following form, if you type the word `redirect` into the message field and submit the form, you will be redirected to a different page."
                 }
                 form method="post" action=(req.path()) class="g-3" {
+                    (csrf_token)
                     div class="mb-3" {
                         labe.form-label for="name" { "Name" }
                         input.form-control type="text" name="name" placeholder="Name" required="true" value=(form.name) {};
@@ -66,6 +69,6 @@ pub mod page {
             }
         });
 
-        Response::HTML(ui.into())
+        Ok(Response::HTML(ui.into()))
     }
 }
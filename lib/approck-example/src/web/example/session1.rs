@@ -0,0 +1,62 @@
+#[approck::http(GET|POST /example/session1; rate_limit = 20/min; return HTML;)]
+pub mod page {
+    pub struct PostForm {
+        name: Option<String>,
+        logout: Option<String>,
+    }
+
+    pub async fn request(
+        req: Request,
+        doc: Document,
+        session: Session,
+        form: Option<PostForm>,
+    ) -> Result<Response> {
+        if let Some(form) = form {
+            if form.logout.is_some() {
+                session.clear().await?;
+            } else if let Some(name) = form.name {
+                // new privileges, new token
+                session.rotate().await?;
+                session.set("name", &name).await?;
+            }
+        }
+
+        let visits = session.get::<u32>("visits").await?.unwrap_or(0) + 1;
+        session.set("visits", &visits).await?;
+        let name: Option<String> = session.get("name").await?;
+        let csrf_token = req.csrf_token().await?;
+
+        #[rustfmt::skip]
+        doc.add_body(maud::html! {
+            div.container.bg-white {
+                a href="/example/" { "◂ Back to Example List" }
+                h1 { code {  (req.path()) } }
+
+                hr;
+
+                p { "You have visited this page " b { (visits) } " times in this session." }
+
+                @if let Some(name) = &name {
+                    p { "Logged in as " b { (name) } }
+                    form method="post" action=(req.path()) class="g-3" {
+                        (csrf_token)
+                        input type="hidden" name="logout" value="1";
+                        button.btn.btn-primary type="submit" { "Log Out" }
+                    }
+                }
+                @else {
+                    form method="post" action=(req.path()) class="g-3" {
+                        (csrf_token)
+                        div class="mb-3" {
+                            label for="name" class="form-label" { "Name" }
+                            input.form-control type="text" id="name" name="name" placeholder="Name" required="true" {};
+                        }
+                        button.btn.btn-primary type="submit" { "Log In" }
+                    }
+                }
+            }
+        });
+
+        Ok(Response::HTML(doc.into()))
+    }
+}
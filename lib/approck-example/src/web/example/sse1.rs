@@ -0,0 +1,44 @@
+#[approck::http(GET /example/sse1; return HTML|SSE;)]
+pub mod page {
+    pub async fn request(req: Request, ui: Document) -> Response {
+        // The page opens an EventSource to its own path, which asks for `text/event-stream`.
+        let wants_events = req
+            .headers()
+            .get("accept")
+            .and_then(|value| value.to_str().ok())
+            .is_some_and(|accept| accept.contains("text/event-stream"));
+
+        if wants_events {
+            // After a reconnect, carry on counting from the last event the client received.
+            let start = req
+                .last_event_id()
+                .and_then(|id| id.parse::<u64>().ok())
+                .map_or(0, |id| id + 1);
+
+            let events = futures::stream::unfold(start, |count| async move {
+                tokio::time::sleep(std::time::Duration::from_secs(1)).await;
+                let event = SseEvent::new(format!("Tick {count} from the server"))
+                    .id(count.to_string())
+                    .event("tick");
+                Some((granite::Result::Ok(event), count + 1))
+            });
+
+            return Response::SSE(SSE::new(events));
+        }
+
+        ui.add_js("./sse1.js");
+
+        ui.add_body(maud::html! {
+            div.container.bg-white {
+                a href="/example/" { "◂ Back to Example List" }
+                h1 { "Server-Sent Events Example" }
+                hr;
+                p { "The server sends an event every second.  Stop the server and start it again to see the browser reconnect and resume from the last event." }
+                hr;
+                div #output {}
+            }
+        });
+
+        Response::HTML(ui.into())
+    }
+}
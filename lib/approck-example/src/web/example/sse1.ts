@@ -0,0 +1,14 @@
+// listen for events from /example/sse1
+const events = new EventSource(window.location.href);
+const $output = document.getElementById("output") as HTMLDivElement;
+
+// print any tick events
+events.addEventListener("tick", function(event) {
+    let p = document.createElement("p");
+    p.textContent = `${(event as MessageEvent).lastEventId}: ${(event as MessageEvent).data}`;
+    $output.appendChild(p);
+});
+
+events.onerror = function(event) {
+    console.error(event);
+};
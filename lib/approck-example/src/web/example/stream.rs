@@ -1,19 +1,26 @@
-//! The text of this source file is streamed in the response. Check the terminal for the messages
-//! for each chunk that was streamed.
+//! A 4 GiB download of zeros, streamed in chunks. Check the terminal for the messages for each
+//! chunk that was streamed. The stream is seekable, so the download can be paused and resumed.
 
 #[approck::http(GET /example/stream; return Stream;)]
 pub mod page {
     pub async fn request(req: Request) -> Response {
-        let chunk_size = 65536;
-        let chunk_count = 65536;
+        let chunk_size: u64 = 65536;
+        let chunk_count: u64 = 65536;
         let total_length = chunk_size * chunk_count;
 
-        let mut response: Stream = futures::stream::iter((0..chunk_count).map(move |i| {
-            println!("chunk {i}");
-            vec![0u8; chunk_size]
-        }))
-        .map(granite::Result::Ok)
-        .into();
+        // called with the part of the file the client asked for, all of it unless resuming
+        let mut response = Stream::seekable(total_length, move |range| {
+            let mut offset = range.start;
+            futures::stream::iter(std::iter::from_fn(move || {
+                if offset >= range.end {
+                    return None;
+                }
+                let length = chunk_size.min(range.end - offset);
+                println!("chunk at {offset}");
+                offset += length;
+                Some(granite::Result::Ok(vec![0u8; length as usize]))
+            }))
+        });
         response
             .headers
             .append("content-type", "application/octet-stream".parse().unwrap());
@@ -21,9 +28,6 @@ pub mod page {
             "content-disposition",
             "attachment; filename=\"example.bin\"".parse().unwrap(),
         );
-        response
-            .headers
-            .append("content-length", total_length.to_string().parse().unwrap());
         Response::Stream(response)
     }
 }
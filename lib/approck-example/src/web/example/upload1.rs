@@ -0,0 +1,58 @@
+#[approck::http(GET|POST /example/upload1; max_body_size = 20MB; return HTML;)]
+pub mod page {
+    pub struct PostForm {
+        description: String,
+        #[max_size(10MB)]
+        file: Option<UploadedFile>,
+    }
+
+    pub async fn request(req: Request, ui: Document, form: Option<PostForm>) -> Result<Response> {
+        let csrf_token = req.csrf_token().await?;
+
+        #[rustfmt::skip]
+        ui.add_body(maud::html! {
+            div.container.bg-white {
+                a href="/example/" { "◂ Back to Example List" } 
+                h1 { code {  (req.path()) } }
+
+                hr;
+
+                @if let Some(form) = &form {
+                    h2 { "uploaded" }
+                    ul {
+                        li { "Description: " (form.description) }
+                        @if let Some(file) = &form.file {
+                            li { "File Name: " (file.file_name().unwrap_or("")) }
+                            li { "Content Type: " (file.content_type().unwrap_or("")) }
+                            li { "Size: " (file.size()) " bytes" }
+                        }
+                        @else {
+                            li { "No file" }
+                        }
+                    }
+                }
+
+                hr;
+                p {
+                    "Files up to 10MB are accepted, larger ones are rejected with a 413."
+                }
+                form method="post" action=(req.path()) enctype="multipart/form-data" class="g-3" {
+                    (csrf_token)
+                    div class="mb-3" {
+                        label for="description" class="form-label" { "Description" }
+                        input.form-control type="text" id="description" name="description" placeholder="Description" required="true" {};
+                    }
+                    div class="mb-3" {
+                        label for="file" class="form-label" { "File" }
+                        input.form-control type="file" id="file" name="file" {};
+                    }
+                    div class="mb-3" {
+                        input type="submit" class="btn btn-primary" value="Upload";
+                    }
+                }
+            }
+        });
+
+        Ok(Response::HTML(ui.into()))
+    }
+}
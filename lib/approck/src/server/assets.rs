@@ -0,0 +1,147 @@
+//! Assets built by esbuild and compiled into the binary by the generated router.
+//!
+//! Every asset is served at its plain path (e.g. `/app.js`) and at a path with a hash of its
+//! content (e.g. `/app.3f9a1c2b7d4e5f60.js`).  The hashed path never changes meaning, so browsers
+//! may cache it forever.  The plain path must be revalidated, which is cheap thanks to ETags.
+//!
+//! `Document::add_js` and `Document::add_css` implementations pass their paths through
+//! [`resolve`] so pages link to the hashed paths.  Relative paths (e.g. `./index.js`) cannot be
+//! looked up and are left as they are.
+
+use super::compression::{self, Encoding};
+use super::response::{Bytes, Empty, Response};
+
+/// Maps the plain path of each asset to its hashed path.  The generated router provides one as
+/// `approck_generated::ASSET_MANIFEST`; hand it to the server with
+/// [`WebServerModule::webserver_asset_manifest`].
+///
+/// [`WebServerModule::webserver_asset_manifest`]: super::WebServerModule::webserver_asset_manifest
+pub struct Manifest {
+    entries: &'static [(&'static str, &'static str)],
+}
+
+impl Manifest {
+    pub const fn new(entries: &'static [(&'static str, &'static str)]) -> Self {
+        Self { entries }
+    }
+
+    pub fn empty() -> &'static Self {
+        static EMPTY: Manifest = Manifest::new(&[]);
+        &EMPTY
+    }
+
+    /// The hashed path of the asset at `path`, if there is one
+    pub fn get(&self, path: &str) -> Option<&'static str> {
+        self.entries
+            .iter()
+            .find(|(plain, _)| *plain == path)
+            .map(|(_, hashed)| *hashed)
+    }
+}
+
+static MANIFEST: std::sync::OnceLock<&'static Manifest> = std::sync::OnceLock::new();
+
+/// Called once by [`serve`](super::serve)
+pub(crate) fn set_manifest(manifest: &'static Manifest) {
+    let _ = MANIFEST.set(manifest);
+}
+
+/// The hashed path of the asset at `path`, or `path` itself if it is not a known asset
+pub fn resolve(path: &str) -> String {
+    MANIFEST
+        .get()
+        .and_then(|manifest| manifest.get(path))
+        .unwrap_or(path)
+        .to_string()
+}
+
+/// An asset as compiled in by the generated router
+pub struct Asset {
+    pub content_type: &'static str,
+    pub content: &'static [u8],
+    /// Hash of `content`, which is also in the hashed path
+    pub hash: &'static str,
+    /// Copies compressed by `acp build`
+    pub variants: &'static [(Encoding, &'static [u8])],
+}
+
+/// Answer a request for `asset`, with a 304 if the client already has it.  `hashed` says whether
+/// it was requested at its hashed path, which can be cached forever.
+pub fn serve(req: &super::Request, asset: &Asset, hashed: bool) -> Response {
+    let available: Vec<Encoding> = asset
+        .variants
+        .iter()
+        .map(|(encoding, _)| *encoding)
+        .collect();
+    let encoding = compression::negotiate(req.headers(), &available);
+
+    // each encoding is a different representation, so it needs its own tag
+    let etag = match encoding {
+        Some(encoding) => format!("\"{}-{}\"", asset.hash, encoding.as_str()),
+        None => format!("\"{}\"", asset.hash),
+    };
+
+    let mut headers = http::HeaderMap::new();
+    headers.insert(
+        http::header::CACHE_CONTROL,
+        http::HeaderValue::from_static(match hashed {
+            true => "public, max-age=31536000, immutable",
+            false => "no-cache",
+        }),
+    );
+    headers.insert(
+        http::header::VARY,
+        http::HeaderValue::from_static("accept-encoding"),
+    );
+    if let Ok(value) = http::HeaderValue::from_str(&etag) {
+        headers.insert(http::header::ETAG, value);
+    }
+
+    if if_none_match(req.headers(), asset.hash) {
+        return Response::Empty(Empty {
+            status: http::StatusCode::NOT_MODIFIED,
+            headers,
+        });
+    }
+
+    headers.insert(
+        http::header::CONTENT_TYPE,
+        http::HeaderValue::from_static(asset.content_type),
+    );
+
+    let content = match encoding {
+        Some(encoding) => {
+            headers.insert(
+                http::header::CONTENT_ENCODING,
+                http::HeaderValue::from_static(encoding.as_str()),
+            );
+            asset
+                .variants
+                .iter()
+                .find(|(variant, _)| *variant == encoding)
+                .map(|(_, content)| *content)
+                .unwrap_or(asset.content)
+        }
+        None => asset.content,
+    };
+
+    Response::Bytes(Bytes {
+        content: bytes::Bytes::from_static(content),
+        status: http::StatusCode::OK,
+        headers,
+    })
+}
+
+/// True if `If-None-Match` lists any representation of the content with `hash`
+fn if_none_match(headers: &http::HeaderMap, hash: &str) -> bool {
+    headers
+        .get_all(http::header::IF_NONE_MATCH)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(|tag| tag.trim())
+        .any(|tag| {
+            let tag = tag.strip_prefix("W/").unwrap_or(tag).trim_matches('"');
+            tag == "*" || tag == hash || tag.split_once('-').map(|(tag, _)| tag) == Some(hash)
+        })
+}
@@ -0,0 +1,335 @@
+//! Response compression, negotiated with the client's `Accept-Encoding`.
+//!
+//! Text-like responses (HTML, JSON, JavaScript, CSS, SVG and plain text) are compressed by the
+//! server after they are rendered, if they are at least `webserver.compression.min_size` bytes.
+//! Streams of those types are compressed as they are sent.  Assets built by esbuild are compressed
+//! once by `acp build` and compiled in, so serving them costs nothing; see [`assets`].
+//!
+//! [`assets`]: super::assets
+
+use futures::TryStreamExt;
+use salvo_core::http::body::BytesFrame;
+use salvo_core::http::ResBody;
+use tokio::io::AsyncReadExt;
+
+/// The `[webserver.compression]` section of the config
+#[derive(Debug, serde::Deserialize)]
+#[serde(default)]
+pub struct CompressionConfig {
+    /// Set to false if a proxy in front of the server compresses
+    pub enabled: bool,
+
+    /// Bodies smaller than this many bytes are sent as they are
+    pub min_size: u64,
+}
+
+impl Default for CompressionConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            min_size: 1024,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Encoding {
+    Brotli,
+    Zstd,
+    Gzip,
+}
+
+impl Encoding {
+    /// Preferred first when the client likes several equally
+    const ALL: [Encoding; 3] = [Encoding::Brotli, Encoding::Zstd, Encoding::Gzip];
+
+    /// The name used in `Accept-Encoding` and `Content-Encoding`
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Encoding::Brotli => "br",
+            Encoding::Zstd => "zstd",
+            Encoding::Gzip => "gzip",
+        }
+    }
+}
+
+/// The best encoding out of `available` that the client accepts, if any
+pub fn negotiate(headers: &http::HeaderMap, available: &[Encoding]) -> Option<Encoding> {
+    let mut best: Option<(Encoding, f32)> = None;
+
+    for encoding in Encoding::ALL {
+        if !available.contains(&encoding) {
+            continue;
+        }
+        let quality = accept_quality(headers, encoding.as_str());
+        if quality > 0.0 && best.is_none_or(|(_, best_quality)| quality > best_quality) {
+            best = Some((encoding, quality));
+        }
+    }
+
+    best.map(|(encoding, _)| encoding)
+}
+
+/// The `q` the client gave `name` (or `*`), or 0 if it did not list it
+fn accept_quality(headers: &http::HeaderMap, name: &str) -> f32 {
+    let mut wildcard = 0.0;
+
+    for value in headers.get_all(http::header::ACCEPT_ENCODING) {
+        let Ok(value) = value.to_str() else {
+            continue;
+        };
+        for item in value.split(',') {
+            let mut parts = item.split(';');
+            let coding = parts.next().unwrap_or("").trim();
+            let quality = parts
+                .filter_map(|param| param.trim().strip_prefix("q="))
+                .find_map(|q| q.trim().parse::<f32>().ok())
+                .unwrap_or(1.0);
+
+            if coding.eq_ignore_ascii_case(name) {
+                return quality;
+            }
+            if coding == "*" {
+                wildcard = quality;
+            }
+        }
+    }
+
+    wildcard
+}
+
+/// Only text compresses well; images, archives and the like already are compressed.  Event
+/// streams are left alone because the compressor would hold events back until its buffer fills.
+fn is_compressible(headers: &http::HeaderMap) -> bool {
+    let content_type = match headers
+        .get(http::header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+    {
+        Some(content_type) => content_type,
+        None => return false,
+    };
+    let essence = content_type
+        .split(';')
+        .next()
+        .unwrap_or("")
+        .trim()
+        .to_ascii_lowercase();
+
+    match essence.as_str() {
+        "text/event-stream" => false,
+        "application/json" | "application/javascript" | "image/svg+xml" => true,
+        _ => essence.starts_with("text/"),
+    }
+}
+
+/// Compress the rendered response in place if the client accepts it and it is worth it
+pub(crate) async fn compress_response(
+    request_headers: &http::HeaderMap,
+    response: &mut salvo_core::Response,
+    config: &CompressionConfig,
+) {
+    if !config.enabled
+        || response
+            .headers
+            .contains_key(http::header::CONTENT_ENCODING)
+        || !is_compressible(&response.headers)
+    {
+        return;
+    }
+
+    let status = response.status_code.unwrap_or(http::StatusCode::OK);
+    if status == http::StatusCode::NO_CONTENT
+        || status == http::StatusCode::NOT_MODIFIED
+        || status == http::StatusCode::PARTIAL_CONTENT
+    {
+        return;
+    }
+
+    // the body depends on this header from here on, even if it is not compressed this time
+    add_vary(&mut response.headers);
+
+    let encoding = match negotiate(request_headers, &Encoding::ALL) {
+        Some(encoding) => encoding,
+        None => return,
+    };
+
+    let body = match response.replace_body(ResBody::None) {
+        ResBody::Once(bytes) if (bytes.len() as u64) < config.min_size => {
+            response.replace_body(ResBody::Once(bytes));
+            return;
+        }
+        ResBody::Once(bytes) => match compress_bytes(encoding, &bytes).await {
+            Ok(compressed) => ResBody::Once(compressed.into()),
+            Err(error) => {
+                tracing::error!("compressing response with {}: {error}", encoding.as_str());
+                response.replace_body(ResBody::Once(bytes));
+                return;
+            }
+        },
+        ResBody::Stream(stream) => ResBody::Stream(compress_stream(encoding, stream)),
+        body => {
+            response.replace_body(body);
+            return;
+        }
+    };
+
+    response.replace_body(body);
+    response.headers.remove(http::header::CONTENT_LENGTH);
+    // ranges would refer to the uncompressed bytes
+    response.headers.remove(http::header::ACCEPT_RANGES);
+    response.headers.insert(
+        http::header::CONTENT_ENCODING,
+        http::HeaderValue::from_static(encoding.as_str()),
+    );
+}
+
+fn add_vary(headers: &mut http::HeaderMap) {
+    let present = headers
+        .get_all(http::header::VARY)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .any(|name| name.trim().eq_ignore_ascii_case("accept-encoding") || name.trim() == "*");
+    if !present {
+        headers.append(
+            http::header::VARY,
+            http::HeaderValue::from_static("accept-encoding"),
+        );
+    }
+}
+
+async fn compress_bytes(encoding: Encoding, bytes: &[u8]) -> std::io::Result<Vec<u8>> {
+    let mut compressed = Vec::new();
+    encoder(encoding, bytes)
+        .read_to_end(&mut compressed)
+        .await?;
+    Ok(compressed)
+}
+
+type BodyStream = sync_wrapper::SyncWrapper<
+    futures::stream::BoxStream<'static, Result<BytesFrame, salvo_core::BoxedError>>,
+>;
+
+/// Compresses the data frames as they arrive.  Trailers cannot follow compressed data through
+/// the encoder, so they are dropped.
+fn compress_stream(encoding: Encoding, stream: BodyStream) -> BodyStream {
+    let data = stream
+        .into_inner()
+        .try_filter_map(
+            |frame| async move { Ok::<_, salvo_core::BoxedError>(frame.0.into_data().ok()) },
+        )
+        .map_err(std::io::Error::other);
+    let reader = tokio_util::io::StreamReader::new(data);
+
+    let compressed: futures::stream::BoxStream<'static, _> = Box::pin(
+        tokio_util::io::ReaderStream::new(encoder(encoding, reader))
+            .map_ok(BytesFrame::from)
+            .map_err(|error| -> salvo_core::BoxedError { Box::new(error) }),
+    );
+
+    sync_wrapper::SyncWrapper::new(compressed)
+}
+
+/// Favors speed over size, since this runs for every response
+fn encoder<'a, R>(
+    encoding: Encoding,
+    reader: R,
+) -> std::pin::Pin<Box<dyn tokio::io::AsyncRead + Send + 'a>>
+where
+    R: tokio::io::AsyncBufRead + Send + 'a,
+{
+    use async_compression::tokio::bufread::{BrotliEncoder, GzipEncoder, ZstdEncoder};
+    use async_compression::Level;
+
+    match encoding {
+        Encoding::Brotli => Box::pin(BrotliEncoder::with_quality(reader, Level::Precise(4))),
+        Encoding::Zstd => Box::pin(ZstdEncoder::with_quality(reader, Level::Default)),
+        Encoding::Gzip => Box::pin(GzipEncoder::with_quality(reader, Level::Default)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Encoding;
+
+    fn negotiate(accept_encoding: &[&'static str]) -> Option<Encoding> {
+        let mut headers = http::HeaderMap::new();
+        for value in accept_encoding {
+            headers.append(
+                http::header::ACCEPT_ENCODING,
+                http::HeaderValue::from_static(value),
+            );
+        }
+        super::negotiate(&headers, &Encoding::ALL)
+    }
+
+    #[test]
+    fn test_equal_quality_prefers_brotli() {
+        assert_eq!(
+            negotiate(&["gzip, deflate, br, zstd"]),
+            Some(Encoding::Brotli)
+        );
+        assert_eq!(negotiate(&["gzip, zstd"]), Some(Encoding::Zstd));
+        assert_eq!(negotiate(&["*"]), Some(Encoding::Brotli));
+    }
+
+    #[test]
+    fn test_quality_values() {
+        assert_eq!(negotiate(&["br;q=0.5, gzip;q=1.0"]), Some(Encoding::Gzip));
+        assert_eq!(
+            negotiate(&["br;q=0.2, zstd;q=0.8, gzip"]),
+            Some(Encoding::Gzip)
+        );
+        assert_eq!(
+            negotiate(&["br ; q=0.9, gzip; q=0.3"]),
+            Some(Encoding::Brotli)
+        );
+
+        // a malformed q is taken as 1
+        assert_eq!(negotiate(&["br;q=0.5, gzip;q=high"]), Some(Encoding::Gzip));
+    }
+
+    #[test]
+    fn test_q_zero_refuses() {
+        assert_eq!(negotiate(&["br;q=0, gzip"]), Some(Encoding::Gzip));
+        assert_eq!(negotiate(&["br;q=0, zstd;q=0, gzip;q=0"]), None);
+        assert_eq!(negotiate(&["*;q=0"]), None);
+    }
+
+    #[test]
+    fn test_wildcard() {
+        // listed encodings take their own q over the wildcard's
+        assert_eq!(negotiate(&["*;q=0.5, gzip"]), Some(Encoding::Gzip));
+        assert_eq!(negotiate(&["br;q=0, *"]), Some(Encoding::Zstd));
+        assert_eq!(negotiate(&["*;q=0, gzip;q=0.1"]), Some(Encoding::Gzip));
+    }
+
+    #[test]
+    fn test_nothing_acceptable() {
+        assert_eq!(negotiate(&[]), None);
+        assert_eq!(negotiate(&["identity"]), None);
+        assert_eq!(negotiate(&["deflate, compress"]), None);
+    }
+
+    #[test]
+    fn test_several_headers() {
+        assert_eq!(
+            negotiate(&["gzip;q=0.5", "zstd;q=0.7"]),
+            Some(Encoding::Zstd)
+        );
+    }
+
+    #[test]
+    fn test_only_available_encodings() {
+        let mut headers = http::HeaderMap::new();
+        headers.insert(
+            http::header::ACCEPT_ENCODING,
+            http::HeaderValue::from_static("br, gzip;q=0.5"),
+        );
+        assert_eq!(
+            super::negotiate(&headers, &[Encoding::Gzip]),
+            Some(Encoding::Gzip)
+        );
+        assert_eq!(super::negotiate(&headers, &[Encoding::Zstd]), None);
+    }
+}
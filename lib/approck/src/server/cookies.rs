@@ -0,0 +1,329 @@
+//! Cookie policy and helpers for signed (tamper-proof) and private (encrypted) cookies.
+//!
+//! Signed and private cookies are keyed by `webserver.cookie.secret`, which must be at least 32
+//! bytes.  Without it a random key is generated at startup, so such cookies do not survive a
+//! restart and are not shared between instances.
+
+use cookie::{Cookie, CookieBuilder, CookieJar, Key};
+
+/// The `[webserver.cookie]` section of the config
+#[derive(Default, serde::Deserialize)]
+#[serde(default)]
+pub struct CookieConfig {
+    /// Only send cookies over HTTPS.  Defaults to `webserver.tls`.
+    pub secure: Option<bool>,
+
+    /// `strict`, `lax` (the default) or `none`.  Browsers reject `none` without `secure`, so it
+    /// turns `secure` on, and setting `secure = false` with it is an error.
+    pub same_site: SameSite,
+
+    /// Share the cookies with subdomains of this domain
+    pub domain: Option<String>,
+
+    /// Seconds until the cookies expire.  Defaults to `webserver.session_ttl`.
+    pub max_age: Option<u64>,
+
+    /// Key material for signed and private cookies
+    pub secret: Option<String>,
+}
+
+// keep the secret out of logs
+impl std::fmt::Debug for CookieConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("CookieConfig")
+            .field("secure", &self.secure)
+            .field("same_site", &self.same_site)
+            .field("domain", &self.domain)
+            .field("max_age", &self.max_age)
+            .field("secret", &self.secret.as_ref().map(|_| "..."))
+            .finish()
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SameSite {
+    Strict,
+    #[default]
+    Lax,
+    None,
+}
+
+/// The cookie settings in effect, built from [`CookieConfig`]
+pub struct CookiePolicy {
+    secure: bool,
+    same_site: SameSite,
+    domain: Option<String>,
+    max_age: std::time::Duration,
+    key: Key,
+}
+
+// keep the key out of logs
+impl std::fmt::Debug for CookiePolicy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("CookiePolicy")
+            .field("secure", &self.secure)
+            .field("same_site", &self.same_site)
+            .field("domain", &self.domain)
+            .field("max_age", &self.max_age)
+            .finish_non_exhaustive()
+    }
+}
+
+impl CookieConfig {
+    pub(crate) fn into_policy(self, tls: bool, session_ttl: std::time::Duration) -> CookiePolicy {
+        let key = match self.secret {
+            Some(secret) if secret.len() >= 32 => Key::derive_from(secret.as_bytes()),
+            Some(_) => panic!("webserver.cookie.secret must be at least 32 bytes"),
+            None => Key::generate(),
+        };
+
+        let secure = match (self.same_site, self.secure) {
+            (SameSite::None, Some(false)) => {
+                panic!("webserver.cookie.same_site = \"none\" requires webserver.cookie.secure")
+            }
+            (SameSite::None, _) => true,
+            (_, secure) => secure.unwrap_or(tls),
+        };
+
+        CookiePolicy {
+            secure,
+            same_site: self.same_site,
+            domain: self.domain,
+            max_age: self
+                .max_age
+                .map(std::time::Duration::from_secs)
+                .unwrap_or(session_ttl),
+            key,
+        }
+    }
+}
+
+impl CookiePolicy {
+    /// A cookie with the configured attributes, visible to the whole site and not to scripts.
+    /// Override any of them on the returned builder.
+    pub fn cookie(
+        &self,
+        name: impl Into<String>,
+        value: impl Into<String>,
+    ) -> CookieBuilder<'static> {
+        let max_age = cookie::time::Duration::seconds(self.max_age.as_secs() as i64);
+        let same_site = match self.same_site {
+            SameSite::Strict => cookie::SameSite::Strict,
+            SameSite::Lax => cookie::SameSite::Lax,
+            SameSite::None => cookie::SameSite::None,
+        };
+
+        let builder = Cookie::build((name.into(), value.into()))
+            .path("/")
+            .http_only(true)
+            .secure(self.secure)
+            .same_site(same_site)
+            .max_age(max_age);
+
+        match &self.domain {
+            Some(domain) => builder.domain(domain.clone()),
+            None => builder,
+        }
+    }
+
+    /// The key for signed and private cookies
+    pub fn key(&self) -> &Key {
+        &self.key
+    }
+}
+
+/// Used by [`Request`](super::Request)s which were not set up by the server
+pub(crate) fn default_policy() -> &'static CookiePolicy {
+    static POLICY: std::sync::OnceLock<CookiePolicy> = std::sync::OnceLock::new();
+    POLICY.get_or_init(|| {
+        CookieConfig::default().into_policy(
+            true,
+            std::time::Duration::from_secs(super::DEFAULT_SESSION_TTL),
+        )
+    })
+}
+
+/// Sign the value of `cookie` so the client can read but not change it
+pub fn sign(key: &Key, cookie: Cookie<'static>) -> Cookie<'static> {
+    let name = cookie.name().to_string();
+    let mut jar = CookieJar::new();
+    jar.signed_mut(key).add(cookie);
+    jar.get(&name).cloned().expect("cookie was just added")
+}
+
+/// Encrypt the value of `cookie` so the client can neither read nor change it
+pub fn encrypt(key: &Key, cookie: Cookie<'static>) -> Cookie<'static> {
+    let name = cookie.name().to_string();
+    let mut jar = CookieJar::new();
+    jar.private_mut(key).add(cookie);
+    jar.get(&name).cloned().expect("cookie was just added")
+}
+
+/// The original cookie if the signature of `cookie` is valid
+pub fn verify(key: &Key, cookie: Cookie<'static>) -> Option<Cookie<'static>> {
+    let name = cookie.name().to_string();
+    let mut jar = CookieJar::new();
+    jar.add_original(cookie);
+    jar.signed(key).get(&name)
+}
+
+/// The original cookie if `cookie` decrypts with `key`
+pub fn decrypt(key: &Key, cookie: Cookie<'static>) -> Option<Cookie<'static>> {
+    let name = cookie.name().to_string();
+    let mut jar = CookieJar::new();
+    jar.add_original(cookie);
+    jar.private(key).get(&name)
+}
+
+/// Append a `Set-Cookie` header for `cookie`, marking it `Secure` if it is `SameSite=None`
+pub(crate) fn append(headers: &mut http::HeaderMap, cookie: &Cookie<'_>) {
+    let mut cookie = cookie.clone();
+    if cookie.same_site() == Some(cookie::SameSite::None) {
+        cookie.set_secure(true);
+    }
+    if let Ok(value) = http::HeaderValue::from_str(&cookie.encoded().to_string()) {
+        headers.append(http::header::SET_COOKIE, value);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{CookieConfig, SameSite};
+    use cookie::{Cookie, Key};
+    use std::time::Duration;
+
+    const SECRET: &str = "0123456789abcdef0123456789abcdef";
+
+    fn key() -> Key {
+        Key::derive_from(SECRET.as_bytes())
+    }
+
+    fn config(same_site: SameSite, secure: Option<bool>) -> CookieConfig {
+        CookieConfig {
+            same_site,
+            secure,
+            secret: Some(SECRET.to_string()),
+            ..Default::default()
+        }
+    }
+
+    /// `cookie` with its value changed in one place
+    fn tamper(cookie: &Cookie<'static>) -> Cookie<'static> {
+        let mut value = cookie.value().to_string();
+        let last = match value.pop() {
+            Some('A') => 'B',
+            _ => 'A',
+        };
+        value.push(last);
+        Cookie::new(cookie.name().to_string(), value)
+    }
+
+    #[test]
+    fn test_sign_verify() {
+        let signed = super::sign(&key(), Cookie::new("user", "42"));
+        assert_ne!(signed.value(), "42");
+
+        let verified = super::verify(&key(), signed.clone()).unwrap();
+        assert_eq!(verified.value(), "42");
+
+        assert!(super::verify(&key(), tamper(&signed)).is_none());
+        assert!(super::verify(&key(), Cookie::new("user", "42")).is_none());
+        assert!(super::verify(&Key::generate(), signed).is_none());
+    }
+
+    #[test]
+    fn test_encrypt_decrypt() {
+        let encrypted = super::encrypt(&key(), Cookie::new("user", "42"));
+        assert!(!encrypted.value().contains("42"));
+
+        let decrypted = super::decrypt(&key(), encrypted.clone()).unwrap();
+        assert_eq!(decrypted.value(), "42");
+
+        assert!(super::decrypt(&key(), tamper(&encrypted)).is_none());
+        assert!(super::decrypt(&key(), Cookie::new("user", "42")).is_none());
+        assert!(super::decrypt(&Key::generate(), encrypted).is_none());
+
+        // a signed cookie is not a private one
+        let signed = super::sign(&key(), Cookie::new("user", "42"));
+        assert!(super::decrypt(&key(), signed).is_none());
+    }
+
+    #[test]
+    fn test_secret_is_the_key() {
+        let policy = config(SameSite::Lax, None).into_policy(true, Duration::from_secs(60));
+        let signed = super::sign(policy.key(), Cookie::new("user", "42"));
+        assert_eq!(super::verify(&key(), signed).unwrap().value(), "42");
+    }
+
+    #[test]
+    #[should_panic(expected = "at least 32 bytes")]
+    fn test_short_secret() {
+        let config = CookieConfig {
+            secret: Some(SECRET[..31].to_string()),
+            ..Default::default()
+        };
+        config.into_policy(true, Duration::from_secs(60));
+    }
+
+    #[test]
+    fn test_secure_follows_tls() {
+        let policy = config(SameSite::Lax, None).into_policy(false, Duration::from_secs(60));
+        assert_eq!(policy.cookie("a", "b").build().secure(), Some(false));
+
+        let policy = config(SameSite::Lax, None).into_policy(true, Duration::from_secs(60));
+        assert_eq!(policy.cookie("a", "b").build().secure(), Some(true));
+
+        let policy = config(SameSite::Lax, Some(false)).into_policy(true, Duration::from_secs(60));
+        assert_eq!(policy.cookie("a", "b").build().secure(), Some(false));
+    }
+
+    #[test]
+    fn test_same_site_none_is_secure() {
+        let policy = config(SameSite::None, None).into_policy(false, Duration::from_secs(60));
+        let cookie = policy.cookie("a", "b").build();
+        assert_eq!(cookie.same_site(), Some(cookie::SameSite::None));
+        assert_eq!(cookie.secure(), Some(true));
+
+        // also when the cookie was built by hand
+        let mut headers = http::HeaderMap::new();
+        let cookie = Cookie::build(("a", "b"))
+            .same_site(cookie::SameSite::None)
+            .secure(false)
+            .build();
+        super::append(&mut headers, &cookie);
+        let set_cookie = headers[http::header::SET_COOKIE].to_str().unwrap();
+        assert!(set_cookie.contains("Secure"), "{set_cookie}");
+
+        let cookie = Cookie::build(("a", "b"))
+            .same_site(cookie::SameSite::Lax)
+            .build();
+        let mut headers = http::HeaderMap::new();
+        super::append(&mut headers, &cookie);
+        let set_cookie = headers[http::header::SET_COOKIE].to_str().unwrap();
+        assert!(!set_cookie.contains("Secure"), "{set_cookie}");
+    }
+
+    #[test]
+    #[should_panic(expected = "requires webserver.cookie.secure")]
+    fn test_same_site_none_without_secure() {
+        config(SameSite::None, Some(false)).into_policy(true, Duration::from_secs(60));
+    }
+
+    #[test]
+    fn test_max_age() {
+        let policy = config(SameSite::Lax, None).into_policy(true, Duration::from_secs(60));
+        let cookie = policy.cookie("a", "b").build();
+        assert_eq!(cookie.max_age(), Some(cookie::time::Duration::seconds(60)));
+
+        let config = CookieConfig {
+            max_age: Some(5),
+            ..config(SameSite::Lax, None)
+        };
+        let cookie = config
+            .into_policy(true, Duration::from_secs(60))
+            .cookie("a", "b")
+            .build();
+        assert_eq!(cookie.max_age(), Some(cookie::time::Duration::seconds(5)));
+    }
+}
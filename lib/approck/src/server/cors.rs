@@ -0,0 +1,414 @@
+//! Cross-origin resource sharing, so front-end apps served from other origins can call the
+//! server's endpoints.
+//!
+//! `[webserver.cors]` sets the policy for every route.  A route can replace the allowed origins
+//! with `cors = "https://app.example.com";` or opt out with `cors = off;`.  Preflight `OPTIONS`
+//! requests are answered by the router without running any handler.  Requests from origins which
+//! are not allowed get no CORS headers at all, so the browser keeps the response from the page.
+
+use super::response::{HeadersMut, Response};
+
+/// The `[webserver.cors]` section of the config
+#[derive(Debug, Default, serde::Deserialize)]
+#[serde(default)]
+pub struct CorsConfig {
+    /// Origins allowed to call the server, e.g. `https://app.example.com`, or `*` for any.  Empty
+    /// (the default) leaves CORS off, except on routes which list their own origins.
+    pub allow_origins: Vec<String>,
+
+    /// Methods preflights may ask for.  Defaults to the methods of the route.
+    pub allow_methods: Vec<String>,
+
+    /// Request headers preflights may ask for.  Defaults to whichever they ask for.
+    pub allow_headers: Vec<String>,
+
+    /// Response headers scripts may read, besides the few every response exposes
+    pub expose_headers: Vec<String>,
+
+    /// Let requests carry cookies.  The origin is then echoed back instead of `*`.
+    pub allow_credentials: bool,
+
+    /// Seconds browsers may cache the answer to a preflight
+    pub max_age: Option<u64>,
+}
+
+/// A route's `cors` instruction, which the generated code hands to [`Request::set_cors`]
+///
+/// [`Request::set_cors`]: super::Request::set_cors
+#[derive(Debug, Clone, Copy)]
+pub enum RouteCors {
+    Off,
+    Origins(&'static [&'static str]),
+}
+
+/// Add the CORS headers for a request with `method` and `request_headers` to `response`, if its
+/// origin is allowed
+pub(crate) fn apply(
+    config: &CorsConfig,
+    route: Option<RouteCors>,
+    method: &http::Method,
+    request_headers: &http::HeaderMap,
+    response: &mut Response,
+) {
+    let allow_origins: Vec<&str> = match route {
+        Some(RouteCors::Off) => return,
+        Some(RouteCors::Origins(origins)) => origins.to_vec(),
+        None => config.allow_origins.iter().map(String::as_str).collect(),
+    };
+    if allow_origins.is_empty() {
+        return;
+    }
+
+    // The answer depends on the origin unless every origin gets the same `*`, including whether
+    // there are CORS headers at all, so caches must not hand one origin's response to another
+    let any_origin = allow_origins.contains(&"*");
+    if !any_origin || config.allow_credentials {
+        response
+            .headers_mut()
+            .append(http::header::VARY, http::HeaderValue::from_static("origin"));
+    }
+
+    let origin = match request_headers
+        .get(http::header::ORIGIN)
+        .and_then(|value| value.to_str().ok())
+    {
+        Some(origin) => origin,
+        None => return,
+    };
+
+    if !any_origin && !allow_origins.contains(&origin) {
+        return;
+    }
+
+    let allow_origin = match any_origin && !config.allow_credentials {
+        true => http::HeaderValue::from_static("*"),
+        false => match http::HeaderValue::from_str(origin) {
+            Ok(origin) => origin,
+            Err(_) => return,
+        },
+    };
+
+    let headers = response.headers_mut();
+    headers.insert(http::header::ACCESS_CONTROL_ALLOW_ORIGIN, allow_origin);
+
+    if config.allow_credentials {
+        headers.insert(
+            http::header::ACCESS_CONTROL_ALLOW_CREDENTIALS,
+            http::HeaderValue::from_static("true"),
+        );
+    }
+
+    let is_preflight = method == http::Method::OPTIONS
+        && request_headers.contains_key(http::header::ACCESS_CONTROL_REQUEST_METHOD);
+
+    match response {
+        Response::Options(options) if is_preflight => {
+            let allow_methods = match config.allow_methods.is_empty() {
+                true => options
+                    .allow
+                    .iter()
+                    .map(|method| method.as_str())
+                    .collect::<Vec<_>>()
+                    .join(", "),
+                false => config.allow_methods.join(", "),
+            };
+            insert_header(
+                &mut options.headers,
+                http::header::ACCESS_CONTROL_ALLOW_METHODS,
+                &allow_methods,
+            );
+
+            if config.allow_headers.is_empty() {
+                options.headers.append(
+                    http::header::VARY,
+                    http::HeaderValue::from_static("access-control-request-headers"),
+                );
+                if let Some(requested) =
+                    request_headers.get(http::header::ACCESS_CONTROL_REQUEST_HEADERS)
+                {
+                    options.headers.insert(
+                        http::header::ACCESS_CONTROL_ALLOW_HEADERS,
+                        requested.clone(),
+                    );
+                }
+            } else {
+                insert_header(
+                    &mut options.headers,
+                    http::header::ACCESS_CONTROL_ALLOW_HEADERS,
+                    &config.allow_headers.join(", "),
+                );
+            }
+
+            if let Some(max_age) = config.max_age {
+                options
+                    .headers
+                    .insert(http::header::ACCESS_CONTROL_MAX_AGE, max_age.into());
+            }
+        }
+        response => {
+            if !config.expose_headers.is_empty() {
+                insert_header(
+                    response.headers_mut(),
+                    http::header::ACCESS_CONTROL_EXPOSE_HEADERS,
+                    &config.expose_headers.join(", "),
+                );
+            }
+        }
+    }
+}
+
+fn insert_header(headers: &mut http::HeaderMap, name: http::header::HeaderName, value: &str) {
+    if let Ok(value) = http::HeaderValue::from_str(value) {
+        headers.insert(name, value);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{CorsConfig, RouteCors};
+    use crate::server::response::{Empty, Options, Response};
+
+    const APP: &str = "https://app.example.com";
+    const OTHER: &str = "https://other.example.com";
+
+    fn config(allow_origins: &[&str]) -> CorsConfig {
+        CorsConfig {
+            allow_origins: allow_origins
+                .iter()
+                .map(|origin| origin.to_string())
+                .collect(),
+            ..Default::default()
+        }
+    }
+
+    /// The headers `apply` adds to an `OPTIONS` response allowing `GET` and `POST`, or to an
+    /// empty one for any other method
+    fn apply(
+        config: &CorsConfig,
+        route: Option<RouteCors>,
+        method: http::Method,
+        request_headers: &[(http::header::HeaderName, &'static str)],
+    ) -> http::HeaderMap {
+        let request_headers = request_headers
+            .iter()
+            .map(|(name, value)| (name.clone(), http::HeaderValue::from_static(value)))
+            .collect();
+        let mut response = match method {
+            http::Method::OPTIONS => {
+                Response::Options(Options::new(vec![http::Method::GET, http::Method::POST]))
+            }
+            _ => Response::Empty(Empty {
+                status: http::StatusCode::OK,
+                headers: http::HeaderMap::new(),
+            }),
+        };
+        super::apply(config, route, &method, &request_headers, &mut response);
+        match response {
+            Response::Options(options) => options.headers,
+            Response::Empty(empty) => empty.headers,
+            _ => unreachable!(),
+        }
+    }
+
+    fn get(config: &CorsConfig, route: Option<RouteCors>, origin: &'static str) -> http::HeaderMap {
+        apply(
+            config,
+            route,
+            http::Method::GET,
+            &[(http::header::ORIGIN, origin)],
+        )
+    }
+
+    fn header(headers: &http::HeaderMap, name: http::header::HeaderName) -> Option<&str> {
+        headers.get(name).map(|value| value.to_str().unwrap())
+    }
+
+    fn vary(headers: &http::HeaderMap) -> Vec<&str> {
+        headers
+            .get_all(http::header::VARY)
+            .iter()
+            .map(|value| value.to_str().unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_off_without_origins() {
+        assert!(get(&config(&[]), None, APP).is_empty());
+    }
+
+    #[test]
+    fn test_origin_matching() {
+        let config = config(&[APP]);
+
+        let headers = get(&config, None, APP);
+        assert_eq!(
+            header(&headers, http::header::ACCESS_CONTROL_ALLOW_ORIGIN),
+            Some(APP)
+        );
+        assert_eq!(vary(&headers), vec!["origin"]);
+        assert!(!headers.contains_key(http::header::ACCESS_CONTROL_ALLOW_CREDENTIALS));
+
+        // others get no CORS headers, but the response still varies by origin
+        let headers = get(&config, None, OTHER);
+        assert!(!headers.contains_key(http::header::ACCESS_CONTROL_ALLOW_ORIGIN));
+        assert_eq!(vary(&headers), vec!["origin"]);
+
+        let headers = get(&config, None, "https://app.example.com.evil.com");
+        assert!(!headers.contains_key(http::header::ACCESS_CONTROL_ALLOW_ORIGIN));
+
+        let headers = apply(&config, None, http::Method::GET, &[]);
+        assert!(!headers.contains_key(http::header::ACCESS_CONTROL_ALLOW_ORIGIN));
+        assert_eq!(vary(&headers), vec!["origin"]);
+    }
+
+    #[test]
+    fn test_any_origin() {
+        let headers = get(&config(&["*"]), None, APP);
+        assert_eq!(
+            header(&headers, http::header::ACCESS_CONTROL_ALLOW_ORIGIN),
+            Some("*")
+        );
+        assert_eq!(vary(&headers), Vec::<&str>::new());
+
+        let headers = apply(&config(&["*"]), None, http::Method::GET, &[]);
+        assert!(headers.is_empty());
+    }
+
+    #[test]
+    fn test_any_origin_with_credentials() {
+        // `*` is not allowed with credentials, so the origin is echoed instead
+        let config = CorsConfig {
+            allow_credentials: true,
+            ..config(&["*"])
+        };
+
+        let headers = get(&config, None, APP);
+        assert_eq!(
+            header(&headers, http::header::ACCESS_CONTROL_ALLOW_ORIGIN),
+            Some(APP)
+        );
+        assert_eq!(
+            header(&headers, http::header::ACCESS_CONTROL_ALLOW_CREDENTIALS),
+            Some("true")
+        );
+        assert_eq!(vary(&headers), vec!["origin"]);
+    }
+
+    #[test]
+    fn test_route_overrides() {
+        let headers = get(&config(&["*"]), Some(RouteCors::Off), APP);
+        assert!(headers.is_empty());
+
+        // the route's origins replace the configured ones
+        let route = Some(RouteCors::Origins(&[OTHER]));
+        let headers = get(&config(&[APP]), route, APP);
+        assert!(!headers.contains_key(http::header::ACCESS_CONTROL_ALLOW_ORIGIN));
+        assert_eq!(vary(&headers), vec!["origin"]);
+
+        let headers = get(&config(&[APP]), route, OTHER);
+        assert_eq!(
+            header(&headers, http::header::ACCESS_CONTROL_ALLOW_ORIGIN),
+            Some(OTHER)
+        );
+
+        // and turn CORS on where the config leaves it off
+        let headers = get(&config(&[]), route, OTHER);
+        assert_eq!(
+            header(&headers, http::header::ACCESS_CONTROL_ALLOW_ORIGIN),
+            Some(OTHER)
+        );
+    }
+
+    #[test]
+    fn test_preflight() {
+        let config = CorsConfig {
+            max_age: Some(600),
+            ..config(&[APP])
+        };
+        let headers = apply(
+            &config,
+            None,
+            http::Method::OPTIONS,
+            &[
+                (http::header::ORIGIN, APP),
+                (http::header::ACCESS_CONTROL_REQUEST_METHOD, "POST"),
+                (http::header::ACCESS_CONTROL_REQUEST_HEADERS, "x-custom"),
+            ],
+        );
+
+        // the route's methods, and whichever headers were asked for
+        assert_eq!(
+            header(&headers, http::header::ACCESS_CONTROL_ALLOW_METHODS),
+            Some("GET, POST")
+        );
+        assert_eq!(
+            header(&headers, http::header::ACCESS_CONTROL_ALLOW_HEADERS),
+            Some("x-custom")
+        );
+        assert_eq!(
+            header(&headers, http::header::ACCESS_CONTROL_MAX_AGE),
+            Some("600")
+        );
+        assert_eq!(
+            vary(&headers),
+            vec!["origin", "access-control-request-headers"]
+        );
+    }
+
+    #[test]
+    fn test_preflight_configured() {
+        let config = CorsConfig {
+            allow_methods: vec!["GET".to_string()],
+            allow_headers: vec!["x-one".to_string(), "x-two".to_string()],
+            ..config(&[APP])
+        };
+        let headers = apply(
+            &config,
+            None,
+            http::Method::OPTIONS,
+            &[
+                (http::header::ORIGIN, APP),
+                (http::header::ACCESS_CONTROL_REQUEST_METHOD, "POST"),
+                (http::header::ACCESS_CONTROL_REQUEST_HEADERS, "x-custom"),
+            ],
+        );
+
+        assert_eq!(
+            header(&headers, http::header::ACCESS_CONTROL_ALLOW_METHODS),
+            Some("GET")
+        );
+        assert_eq!(
+            header(&headers, http::header::ACCESS_CONTROL_ALLOW_HEADERS),
+            Some("x-one, x-two")
+        );
+        assert!(!headers.contains_key(http::header::ACCESS_CONTROL_MAX_AGE));
+        assert_eq!(vary(&headers), vec!["origin"]);
+    }
+
+    #[test]
+    fn test_expose_headers() {
+        let config = CorsConfig {
+            expose_headers: vec!["x-request-id".to_string()],
+            ..config(&[APP])
+        };
+
+        let headers = get(&config, None, APP);
+        assert_eq!(
+            header(&headers, http::header::ACCESS_CONTROL_EXPOSE_HEADERS),
+            Some("x-request-id")
+        );
+
+        // an `OPTIONS` request without `Access-Control-Request-Method` is not a preflight
+        let headers = apply(
+            &config,
+            None,
+            http::Method::OPTIONS,
+            &[(http::header::ORIGIN, APP)],
+        );
+        assert!(!headers.contains_key(http::header::ACCESS_CONTROL_ALLOW_METHODS));
+        assert_eq!(
+            header(&headers, http::header::ACCESS_CONTROL_EXPOSE_HEADERS),
+            Some("x-request-id")
+        );
+    }
+}
@@ -0,0 +1,73 @@
+//! Protection against cross-site request forgery for `PostForm` handlers.
+//!
+//! Each session gets a random token, which forms embed as a hidden `_csrf` field (render a
+//! [`CsrfToken`] from [`Request::csrf_token`] inside the `form`).  The generated `PostForm::parse`
+//! rejects submissions whose token does not match with [`ErrorKind::Authorization`].  Scripts may
+//! send the token in the `X-CSRF-Token` header instead.
+//!
+//! `PostJson` bodies are not checked: a cross-site page cannot send `application/json` without a
+//! CORS preflight.  Routes authenticated by other means (e.g. API keys) opt out with `csrf = off;`.
+//!
+//! [`Request::csrf_token`]: super::Request::csrf_token
+//! [`ErrorKind::Authorization`]: granite::ErrorKind::Authorization
+
+use super::session::Session;
+
+/// The form field the token is submitted in
+pub const FIELD_NAME: &str = "_csrf";
+
+/// The header the token may be submitted in instead
+pub const HEADER_NAME: &str = "x-csrf-token";
+
+/// Where the token lives in the session, so it survives `rotate` but not `clear`
+const SESSION_KEY: &str = "_csrf_token";
+
+/// The session's CSRF token.  Renders as the hidden input to place inside a form.
+#[derive(Debug, Clone)]
+pub struct CsrfToken(String);
+
+impl CsrfToken {
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl maud::Render for CsrfToken {
+    fn render(&self) -> maud::Markup {
+        maud::html! {
+            input type="hidden" name=(FIELD_NAME) value=(self.0);
+        }
+    }
+}
+
+/// The session's token, creating one if it has none yet
+pub(crate) async fn token(session: &Session) -> granite::Result<CsrfToken> {
+    if let Some(token) = session.get::<String>(SESSION_KEY).await? {
+        return Ok(CsrfToken(token));
+    }
+    let token = granite::ts_random_hex(64);
+    session.set(SESSION_KEY, &token).await?;
+    Ok(CsrfToken(token))
+}
+
+/// Fails with `Authorization` unless `submitted` matches the session's token
+pub(crate) async fn verify(session: &Session, submitted: Option<&str>) -> granite::Result<()> {
+    let expected = session.get::<String>(SESSION_KEY).await?;
+
+    match (expected, submitted) {
+        (Some(expected), Some(submitted)) if constant_time_eq(&expected, submitted) => Ok(()),
+        (_, None) => Err(granite::Error::new(granite::ErrorKind::Authorization)
+            .add_context("CSRF token is missing")),
+        _ => Err(granite::Error::new(granite::ErrorKind::Authorization)
+            .add_context("CSRF token is invalid")),
+    }
+}
+
+// don't leak how much of the token was right through the timing
+fn constant_time_eq(a: &str, b: &str) -> bool {
+    a.len() == b.len()
+        && a.bytes()
+            .zip(b.bytes())
+            .fold(0, |acc, (x, y)| acc | (x ^ y))
+            == 0
+}
@@ -0,0 +1,209 @@
+//! What the proxies in front of the server say about the client, in `Forwarded` (RFC 7239) or
+//! in `X-Forwarded-For`, `X-Forwarded-Proto` and `X-Forwarded-Host`.
+//!
+//! Anyone can send these headers, so they are only believed when the connection comes from one
+//! of `webserver.trusted_proxies`.  Each proxy appends the address it received the request from,
+//! so the client is found by walking back from the server to the first address which is not a
+//! trusted proxy.
+
+use ipnetwork::IpNetwork;
+use std::net::IpAddr;
+
+/// The client, as far back as the trusted proxies can vouch for it
+pub(crate) struct Client<'h> {
+    pub(crate) ip: IpAddr,
+    pub(crate) proto: Option<&'h str>,
+    pub(crate) host: Option<&'h str>,
+}
+
+pub(crate) fn client<'h>(
+    headers: &'h http::HeaderMap,
+    peer: IpAddr,
+    trusted_proxies: &[IpNetwork],
+) -> Client<'h> {
+    let mut client = Client {
+        ip: peer,
+        proto: None,
+        host: None,
+    };
+    if !is_trusted(trusted_proxies, peer) {
+        return client;
+    }
+
+    if headers.contains_key(http::header::FORWARDED) {
+        for element in elements(headers, http::header::FORWARDED.as_str()).rev() {
+            // a proxy which did not know where the request came from can not be seen past
+            let ip = match param(element, "for").and_then(parse_node) {
+                Some(ip) => ip,
+                None => break,
+            };
+            client = Client {
+                ip,
+                proto: param(element, "proto"),
+                host: param(element, "host"),
+            };
+            if !is_trusted(trusted_proxies, ip) {
+                break;
+            }
+        }
+    } else {
+        for node in elements(headers, "x-forwarded-for").rev() {
+            let ip = match parse_node(node) {
+                Some(ip) => ip,
+                None => break,
+            };
+            client.ip = ip;
+            if !is_trusted(trusted_proxies, ip) {
+                break;
+            }
+        }
+        // set by the proxy nearest to the server, which is trusted
+        client.proto = elements(headers, "x-forwarded-proto").next_back();
+        client.host = elements(headers, "x-forwarded-host").next_back();
+    }
+
+    client
+}
+
+fn is_trusted(trusted_proxies: &[IpNetwork], ip: IpAddr) -> bool {
+    trusted_proxies.iter().any(|network| network.contains(ip))
+}
+
+/// The comma separated elements of every `name` header, in the order they were added
+fn elements<'h>(
+    headers: &'h http::HeaderMap,
+    name: &str,
+) -> impl DoubleEndedIterator<Item = &'h str> {
+    headers
+        .get_all(name)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(str::trim)
+        .filter(|element| !element.is_empty())
+}
+
+/// The value of `name` in a `Forwarded` element such as `for=192.0.2.60;proto=https`
+fn param<'h>(element: &'h str, name: &str) -> Option<&'h str> {
+    element
+        .split(';')
+        .filter_map(|pair| pair.split_once('='))
+        .find(|(key, _)| key.trim().eq_ignore_ascii_case(name))
+        .map(|(_, value)| value.trim().trim_matches('"'))
+}
+
+/// The address in `192.0.2.60`, `192.0.2.60:4711`, `2001:db8::17` or `[2001:db8::17]:4711`.
+/// `unknown` and obfuscated identifiers have none.
+fn parse_node(node: &str) -> Option<IpAddr> {
+    let node = node.trim().trim_matches('"');
+    if let Some(bracketed) = node.strip_prefix('[') {
+        return bracketed.split(']').next()?.parse().ok();
+    }
+    node.parse()
+        .ok()
+        .or_else(|| node.rsplit_once(':')?.0.parse().ok())
+}
+
+#[cfg(test)]
+mod tests {
+    use ipnetwork::IpNetwork;
+    use std::net::IpAddr;
+
+    fn header_map(headers: &[(&'static str, &'static str)]) -> http::HeaderMap {
+        let mut header_map = http::HeaderMap::new();
+        for (name, value) in headers {
+            header_map.append(*name, http::HeaderValue::from_static(value));
+        }
+        header_map
+    }
+
+    fn ip(ip: &str) -> IpAddr {
+        ip.parse().unwrap()
+    }
+
+    fn trusted() -> Vec<IpNetwork> {
+        vec![
+            "10.0.0.0/8".parse().unwrap(),
+            "2001:db8::/32".parse().unwrap(),
+        ]
+    }
+
+    #[test]
+    fn test_untrusted_peer_is_the_client() {
+        let headers = header_map(&[
+            ("x-forwarded-for", "192.0.2.60"),
+            ("x-forwarded-proto", "https"),
+        ]);
+        let client = super::client(&headers, ip("198.51.100.1"), &trusted());
+        assert_eq!(client.ip, ip("198.51.100.1"));
+        assert_eq!(client.proto, None);
+    }
+
+    #[test]
+    fn test_x_forwarded_for() {
+        let headers = header_map(&[
+            ("x-forwarded-for", "203.0.113.9, 192.0.2.60"),
+            ("x-forwarded-for", "10.0.0.2"),
+            ("x-forwarded-proto", "http, https"),
+            ("x-forwarded-host", "example.com"),
+        ]);
+        let client = super::client(&headers, ip("10.0.0.1"), &trusted());
+
+        // 10.0.0.2 is a trusted proxy, 192.0.2.60 is not, so nothing before it is believed
+        assert_eq!(client.ip, ip("192.0.2.60"));
+        assert_eq!(client.proto, Some("https"));
+        assert_eq!(client.host, Some("example.com"));
+    }
+
+    #[test]
+    fn test_x_forwarded_for_ports_and_ipv6() {
+        let headers = header_map(&[("x-forwarded-for", "192.0.2.60:80")]);
+        let client = super::client(&headers, ip("10.0.0.1"), &trusted());
+        assert_eq!(client.ip, ip("192.0.2.60"));
+
+        let headers = header_map(&[("x-forwarded-for", "[2001:db9::17]:4711, 2001:db8::1")]);
+        let client = super::client(&headers, ip("10.0.0.1"), &trusted());
+        assert_eq!(client.ip, ip("2001:db9::17"));
+    }
+
+    #[test]
+    fn test_x_forwarded_for_garbage_stops_the_walk() {
+        let headers = header_map(&[("x-forwarded-for", "192.0.2.60, not-an-ip, 10.0.0.2")]);
+        let client = super::client(&headers, ip("10.0.0.1"), &trusted());
+        assert_eq!(client.ip, ip("10.0.0.2"));
+    }
+
+    #[test]
+    fn test_forwarded() {
+        let headers = header_map(&[(
+            "forwarded",
+            r#"for=192.0.2.43;proto=http, for="[2001:db8:cafe::17]:4711";proto=https;host=example.com, For=10.0.0.2"#,
+        )]);
+        let client = super::client(&headers, ip("10.0.0.1"), &trusted());
+
+        // 2001:db8::/32 is trusted, so the walk goes on to 192.0.2.43
+        assert_eq!(client.ip, ip("192.0.2.43"));
+        assert_eq!(client.proto, Some("http"));
+        assert_eq!(client.host, None);
+    }
+
+    #[test]
+    fn test_forwarded_takes_precedence() {
+        let headers = header_map(&[
+            ("forwarded", "for=192.0.2.43;proto=https;host=example.com"),
+            ("x-forwarded-for", "198.51.100.7"),
+            ("x-forwarded-proto", "http"),
+        ]);
+        let client = super::client(&headers, ip("10.0.0.1"), &trusted());
+        assert_eq!(client.ip, ip("192.0.2.43"));
+        assert_eq!(client.proto, Some("https"));
+        assert_eq!(client.host, Some("example.com"));
+    }
+
+    #[test]
+    fn test_forwarded_unknown_stops_the_walk() {
+        let headers = header_map(&[("forwarded", "for=192.0.2.43, for=unknown, for=10.0.0.2")]);
+        let client = super::client(&headers, ip("10.0.0.1"), &trusted());
+        assert_eq!(client.ip, ip("10.0.0.2"));
+    }
+}
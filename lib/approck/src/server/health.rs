@@ -0,0 +1,188 @@
+//! `GET /health` and `GET /ready`, for load balancers and orchestrators, turned on with
+//! `[webserver.health] enabled = true`.
+//!
+//! `/health` answers `200` as long as the process is serving requests.  `/ready` runs the checks
+//! from [`WebServerModule::webserver_readiness`], each within `timeout`, and answers `200` if they
+//! all passed or `503` if any failed, with a JSON report of every component:
+//!
+//! ```json
+//! {"status":"fail","components":{
+//!   "postgres":{"status":"ok","latency_ms":1.4,"pool":{"connections":4,"idle_connections":3}},
+//!   "redis":{"status":"fail","latency_ms":2000.1,"error":"timed out","pool":{"connections":10,"idle_connections":0}}
+//! }}
+//! ```
+//!
+//! Neither runs any middleware or touches the session, though both get the security headers and
+//! `X-Request-Id` like any other response.
+//!
+//! [`WebServerModule::webserver_readiness`]: super::WebServerModule::webserver_readiness
+
+use super::response::{Response, JSON};
+use std::time::Duration;
+
+pub const HEALTH_PATH: &str = "/health";
+pub const READY_PATH: &str = "/ready";
+
+/// The `[webserver.health]` section of the config
+#[derive(Debug, Default, serde::Deserialize)]
+#[serde(default)]
+pub struct HealthConfig {
+    /// Serve `/health` and `/ready`.  Off by default, so they do not shadow routes of the app.
+    pub enabled: bool,
+
+    /// Seconds each readiness check may take before it counts as failed.  Defaults to 2.
+    pub timeout: Option<u64>,
+}
+
+impl HealthConfig {
+    pub fn timeout(&self) -> Duration {
+        Duration::from_secs(self.timeout.unwrap_or(2))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Status {
+    Ok,
+    Fail,
+}
+
+/// Connections held by a pool, and how many of them are waiting to be used.  When
+/// `idle_connections` stays at 0, requests are queueing for a connection.
+#[derive(Debug, Clone, Copy, serde::Serialize)]
+pub struct PoolStats {
+    pub connections: u32,
+    pub idle_connections: u32,
+}
+
+impl From<granite::PoolStatus> for PoolStats {
+    fn from(status: granite::PoolStatus) -> Self {
+        Self {
+            connections: status.connections,
+            idle_connections: status.idle_connections,
+        }
+    }
+}
+
+/// The result of one readiness check
+#[derive(Debug, serde::Serialize)]
+pub struct Component {
+    #[serde(skip)]
+    pub name: &'static str,
+    pub status: Status,
+    pub latency_ms: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pool: Option<PoolStats>,
+}
+
+impl Component {
+    /// Run `probe` as the check for `name`, failing it if it errors or takes over `timeout`
+    pub async fn check<F, E>(
+        name: &'static str,
+        timeout: Duration,
+        pool: Option<PoolStats>,
+        probe: F,
+    ) -> Self
+    where
+        F: std::future::Future<Output = Result<(), E>>,
+        E: std::fmt::Display,
+    {
+        let start = std::time::Instant::now();
+        let error = match tokio::time::timeout(timeout, probe).await {
+            Ok(Ok(())) => None,
+            Ok(Err(error)) => Some(error.to_string()),
+            Err(_) => Some("timed out".to_string()),
+        };
+
+        Self {
+            name,
+            status: match error {
+                None => Status::Ok,
+                Some(_) => Status::Fail,
+            },
+            latency_ms: start.elapsed().as_secs_f64() * 1000.0,
+            error,
+            pool,
+        }
+    }
+}
+
+/// Get a connection through [`PostgresModule::postgres_dbcx`] and run `SELECT 1` on it
+///
+/// [`PostgresModule::postgres_dbcx`]: granite_postgres::PostgresModule::postgres_dbcx
+pub async fn postgres<APP>(app: &APP, timeout: Duration, pool: granite::PoolStatus) -> Component
+where
+    APP: granite_postgres::PostgresModule + Sync,
+{
+    use granite_postgres::DB;
+
+    Component::check("postgres", timeout, Some(pool.into()), async {
+        let dbcx = app.postgres_dbcx().await.map_err(|e| e.to_string())?;
+        dbcx.query_one("SELECT 1", &[])
+            .await
+            .map_err(|e| e.to_string())?;
+        Ok::<_, String>(())
+    })
+    .await
+}
+
+/// Get a connection through [`RedisModule::redis_dbcx`] and `PING` it
+///
+/// [`RedisModule::redis_dbcx`]: granite_redis::RedisModule::redis_dbcx
+pub async fn redis<APP>(app: &APP, timeout: Duration, pool: granite::PoolStatus) -> Component
+where
+    APP: granite_redis::RedisModule + Sync,
+{
+    Component::check("redis", timeout, Some(pool.into()), async {
+        let mut redis_cx = app.redis_dbcx().await?;
+        redis_cx.ping().await
+    })
+    .await
+}
+
+/// `/health`
+pub(crate) fn health() -> Response {
+    report(Status::Ok, serde_json::Map::new())
+}
+
+/// `/ready`, given the results of the app's checks
+pub(crate) fn ready(components: Vec<Component>) -> Response {
+    let status = match components.iter().all(|c| c.status == Status::Ok) {
+        true => Status::Ok,
+        false => Status::Fail,
+    };
+    for component in components.iter().filter(|c| c.status == Status::Fail) {
+        tracing::warn!(
+            "not ready, {} failed: {}",
+            component.name,
+            component.error.as_deref().unwrap_or_default()
+        );
+    }
+
+    let components = components
+        .into_iter()
+        .map(|c| (c.name.to_string(), serde_json::to_value(&c).unwrap()))
+        .collect();
+    report(status, components)
+}
+
+fn report(status: Status, components: serde_json::Map<String, serde_json::Value>) -> Response {
+    let mut json = JSON::new(
+        serde_json::json!({
+            "status": status,
+            "components": components,
+        })
+        .to_string(),
+    );
+    json.status = match status {
+        Status::Ok => http::StatusCode::OK,
+        Status::Fail => http::StatusCode::SERVICE_UNAVAILABLE,
+    };
+    json.headers.insert(
+        http::header::CACHE_CONTROL,
+        http::HeaderValue::from_static("no-store"),
+    );
+    Response::JSON(json)
+}
@@ -0,0 +1,76 @@
+//! Access logging with `tracing`.  Every request runs in a `request` span carrying its id, method
+//! and path, and ends with a `request completed` event with the status, latency and size.
+
+use tracing_subscriber::EnvFilter;
+
+/// Sent back on every response, and accepted from the client (e.g. a load balancer) if it looks
+/// like an id.
+pub const REQUEST_ID_HEADER: &str = "x-request-id";
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    /// Human readable lines
+    #[default]
+    Pretty,
+    /// One JSON object per line, for log aggregators
+    Json,
+}
+
+/// Install the global subscriber, filtered by `RUST_LOG` (default `info`).  Does nothing if the
+/// application already installed one.
+pub(crate) fn init(log_format: LogFormat) {
+    let filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info"));
+    let builder = tracing_subscriber::fmt().with_env_filter(filter);
+
+    let _ = match log_format {
+        LogFormat::Pretty => builder.try_init(),
+        LogFormat::Json => builder
+            .json()
+            .with_current_span(true)
+            .with_span_list(false)
+            .try_init(),
+    };
+}
+
+/// The client's request id if it sent a sane one, otherwise a new one
+pub(crate) fn request_id(headers: &http::HeaderMap) -> String {
+    let incoming = headers
+        .get(REQUEST_ID_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .filter(|value| {
+            !value.is_empty()
+                && value.len() <= 128
+                && value
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'))
+        });
+
+    match incoming {
+        Some(request_id) => request_id.to_string(),
+        None => granite::ts_random_hex(32),
+    }
+}
+
+/// Size of the rendered body, if it is known up front
+pub(crate) fn body_size(response: &mut salvo_core::Response) -> Option<u64> {
+    use salvo_core::http::ResBody;
+
+    let content_length = response
+        .headers
+        .get(http::header::CONTENT_LENGTH)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse().ok());
+    if content_length.is_some() {
+        return content_length;
+    }
+
+    let body = response.replace_body(ResBody::None);
+    let size = match &body {
+        ResBody::None => Some(0),
+        ResBody::Once(bytes) => Some(bytes.len() as u64),
+        _ => None,
+    };
+    response.replace_body(body);
+    size
+}
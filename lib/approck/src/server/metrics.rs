@@ -0,0 +1,336 @@
+//! `GET /metrics` in the Prometheus text format, turned on with
+//! `[webserver.metrics] enabled = true`.
+//!
+//! The server reports, for every `#[approck::http(...)]` route (labelled by its module path, so
+//! `/user/1` and `/user/2` count together):
+//!
+//! - `approck_http_requests_total{route, method, status}`
+//! - `approck_http_request_duration_seconds{route}`, a histogram
+//!
+//! as well as `approck_http_requests_in_flight` and `approck_websocket_connections`.  Requests
+//! which matched no route are counted under `route=""`.
+//!
+//! Everything else comes from [`WebServerModule::webserver_metrics`], which is called on each
+//! scrape.  It reports the state of the application's pools with [`Metrics::pool`], and its own
+//! [`Counter`]s and [`Gauge`]s with [`Metrics::counter`] and [`Metrics::gauge`].
+//!
+//! The endpoint has no access control, so only enable it where the scraper is the only client
+//! that can reach it.
+//!
+//! [`WebServerModule::webserver_metrics`]: super::WebServerModule::webserver_metrics
+
+use super::health::PoolStats;
+use super::response::{Response, Text};
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::atomic::{AtomicI64, AtomicU64, Ordering};
+use std::time::Duration;
+
+pub const METRICS_PATH: &str = "/metrics";
+
+/// The `[webserver.metrics]` section of the config
+#[derive(Debug, Default, serde::Deserialize)]
+#[serde(default)]
+pub struct MetricsConfig {
+    /// Serve `/metrics`.  Off by default, so it does not shadow a route of the app.
+    pub enabled: bool,
+}
+
+/// Upper bounds, in seconds, of the request duration histogram buckets
+const BUCKETS: [f64; 11] = [
+    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+
+/// A count which only goes up, e.g. `static SIGNUPS: Counter = Counter::new();`
+#[derive(Debug, Default)]
+pub struct Counter(AtomicU64);
+
+impl Counter {
+    pub const fn new() -> Self {
+        Self(AtomicU64::new(0))
+    }
+    pub fn inc(&self) {
+        self.add(1);
+    }
+    pub fn add(&self, n: u64) {
+        self.0.fetch_add(n, Ordering::Relaxed);
+    }
+    pub fn get(&self) -> u64 {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// A value which goes up and down, e.g. the length of a queue
+#[derive(Debug, Default)]
+pub struct Gauge(AtomicI64);
+
+impl Gauge {
+    pub const fn new() -> Self {
+        Self(AtomicI64::new(0))
+    }
+    pub fn set(&self, value: i64) {
+        self.0.store(value, Ordering::Relaxed);
+    }
+    pub fn inc(&self) {
+        self.0.fetch_add(1, Ordering::Relaxed);
+    }
+    pub fn dec(&self) {
+        self.0.fetch_sub(1, Ordering::Relaxed);
+    }
+    pub fn get(&self) -> i64 {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// The route a request was handled by, set by the generated code with [`Request::set_route`]
+///
+/// [`Request::set_route`]: super::Request::set_route
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Route(pub(crate) &'static str);
+
+/// The route which handled `request`, if one did
+pub(crate) fn route(request: &salvo_core::Request) -> Option<&'static str> {
+    request.extensions().get::<Route>().map(|route| route.0)
+}
+
+#[derive(Default)]
+struct RouteStats {
+    /// Keyed by method and status
+    responses: BTreeMap<(&'static str, u16), u64>,
+    buckets: [u64; BUCKETS.len()],
+    sum: f64,
+    count: u64,
+}
+
+static ROUTES: std::sync::Mutex<BTreeMap<&'static str, RouteStats>> =
+    std::sync::Mutex::new(BTreeMap::new());
+
+static IN_FLIGHT: Gauge = Gauge::new();
+
+/// Counts a request as in flight until dropped
+pub(crate) struct InFlight;
+
+impl InFlight {
+    pub(crate) fn new() -> Self {
+        IN_FLIGHT.inc();
+        Self
+    }
+}
+
+impl Drop for InFlight {
+    fn drop(&mut self) {
+        IN_FLIGHT.dec();
+    }
+}
+
+/// Count a finished request
+pub(crate) fn record(
+    route: Option<&'static str>,
+    method: &http::Method,
+    status: http::StatusCode,
+    duration: Duration,
+) {
+    let method = match *method {
+        http::Method::GET => "GET",
+        http::Method::HEAD => "HEAD",
+        http::Method::POST => "POST",
+        http::Method::PUT => "PUT",
+        http::Method::DELETE => "DELETE",
+        http::Method::PATCH => "PATCH",
+        http::Method::OPTIONS => "OPTIONS",
+        _ => "OTHER",
+    };
+    let seconds = duration.as_secs_f64();
+
+    let mut routes = ROUTES.lock().unwrap();
+    let stats = routes.entry(route.unwrap_or_default()).or_default();
+    *stats
+        .responses
+        .entry((method, status.as_u16()))
+        .or_default() += 1;
+    for (count, le) in stats.buckets.iter_mut().zip(BUCKETS) {
+        if seconds <= le {
+            *count += 1;
+        }
+    }
+    stats.sum += seconds;
+    stats.count += 1;
+}
+
+/// The metrics of one scrape, in families of samples which share a name
+#[derive(Default)]
+pub struct Metrics {
+    families: Vec<Family>,
+}
+
+struct Family {
+    name: String,
+    help: String,
+    kind: &'static str,
+    samples: String,
+}
+
+impl Metrics {
+    /// Add a sample of a counter, e.g. `metrics.counter("app_signups_total", "Users who signed
+    /// up", &[], SIGNUPS.get())`
+    pub fn counter(&mut self, name: &str, help: &str, labels: &[(&str, &str)], value: u64) {
+        self.sample(name, help, "counter", name, labels, value as f64);
+    }
+
+    /// Add a sample of a gauge
+    pub fn gauge(&mut self, name: &str, help: &str, labels: &[(&str, &str)], value: f64) {
+        self.sample(name, help, "gauge", name, labels, value);
+    }
+
+    /// Add the state of a connection pool, e.g.
+    /// `metrics.pool("postgres", self.postgres_system.pool_status())`
+    pub fn pool(&mut self, pool: &str, stats: impl Into<PoolStats>) {
+        let stats = stats.into();
+        self.gauge(
+            "approck_pool_connections",
+            "Connections held by the pool",
+            &[("pool", pool)],
+            stats.connections.into(),
+        );
+        self.gauge(
+            "approck_pool_idle_connections",
+            "Connections in the pool waiting to be used",
+            &[("pool", pool)],
+            stats.idle_connections.into(),
+        );
+    }
+
+    fn sample(
+        &mut self,
+        family: &str,
+        help: &str,
+        kind: &'static str,
+        name: &str,
+        labels: &[(&str, &str)],
+        value: f64,
+    ) {
+        let index = match self.families.iter().position(|f| f.name == family) {
+            Some(index) => index,
+            None => {
+                self.families.push(Family {
+                    name: family.to_string(),
+                    help: help.to_string(),
+                    kind,
+                    samples: String::new(),
+                });
+                self.families.len() - 1
+            }
+        };
+        let samples = &mut self.families[index].samples;
+
+        samples.push_str(name);
+        if !labels.is_empty() {
+            samples.push('{');
+            for (i, (label, value)) in labels.iter().enumerate() {
+                if i > 0 {
+                    samples.push(',');
+                }
+                let value = value
+                    .replace('\\', r"\\")
+                    .replace('"', r#"\""#)
+                    .replace('\n', r"\n");
+                let _ = write!(samples, r#"{label}="{value}""#);
+            }
+            samples.push('}');
+        }
+        let _ = writeln!(samples, " {value}");
+    }
+
+    /// The samples of the server's own metrics
+    fn server() -> Self {
+        let mut metrics = Self::default();
+
+        for (&route, stats) in ROUTES.lock().unwrap().iter() {
+            for (&(method, status), count) in &stats.responses {
+                metrics.counter(
+                    "approck_http_requests_total",
+                    "Requests answered, by route, method and status",
+                    &[
+                        ("route", route),
+                        ("method", method),
+                        ("status", &status.to_string()),
+                    ],
+                    *count,
+                );
+            }
+
+            let family = "approck_http_request_duration_seconds";
+            let help = "Time taken to answer requests, by route";
+            for (count, le) in stats.buckets.iter().zip(BUCKETS) {
+                metrics.sample(
+                    family,
+                    help,
+                    "histogram",
+                    &format!("{family}_bucket"),
+                    &[("route", route), ("le", &le.to_string())],
+                    *count as f64,
+                );
+            }
+            for (suffix, labels, value) in [
+                (
+                    "_bucket",
+                    &[("route", route), ("le", "+Inf")][..],
+                    stats.count as f64,
+                ),
+                ("_sum", &[("route", route)][..], stats.sum),
+                ("_count", &[("route", route)][..], stats.count as f64),
+            ] {
+                metrics.sample(
+                    family,
+                    help,
+                    "histogram",
+                    &format!("{family}{suffix}"),
+                    labels,
+                    value,
+                );
+            }
+        }
+
+        metrics.gauge(
+            "approck_http_requests_in_flight",
+            "Requests being answered",
+            &[],
+            IN_FLIGHT.get() as f64,
+        );
+        metrics.gauge(
+            "approck_websocket_connections",
+            "Websocket sessions whose handler is running",
+            &[],
+            super::websocket::open_websockets() as f64,
+        );
+
+        metrics
+    }
+
+    fn encode(&self) -> String {
+        let mut output = String::new();
+        for family in &self.families {
+            let _ = writeln!(output, "# HELP {} {}", family.name, family.help);
+            let _ = writeln!(output, "# TYPE {} {}", family.name, family.kind);
+            output.push_str(&family.samples);
+        }
+        output
+    }
+}
+
+/// `/metrics`, given what the app added to `metrics`
+pub(crate) fn response(metrics: Metrics) -> Response {
+    let mut output = Metrics::server().encode();
+    output.push_str(&metrics.encode());
+
+    let mut text = Text::new(output);
+    text.headers.insert(
+        http::header::CONTENT_TYPE,
+        http::HeaderValue::from_static("text/plain; version=0.0.4; charset=utf-8"),
+    );
+    text.headers.insert(
+        http::header::CACHE_CONTROL,
+        http::HeaderValue::from_static("no-store"),
+    );
+    Response::Text(text)
+}
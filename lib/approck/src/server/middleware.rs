@@ -0,0 +1,99 @@
+//! Application defined middleware, registered with [`WebServerModule::webserver_middleware`].
+//!
+//! For every request the middleware run in order around the route, like layers of an onion:
+//!
+//! 1. [`Middleware::around`] is called on the first middleware with the request and a [`Next`]
+//!    which continues with the second middleware, and so on, until the last one calls the route.
+//!    The default implementation calls [`Middleware::before`] and then continues the chain.
+//! 2. The response is rendered, and the server adds its own headers.
+//! 3. [`Middleware::after`] is called on every middleware, in reverse order, with the rendered
+//!    response.  This includes error responses and responses from short-circuits.
+//!
+//! [`WebServerModule::webserver_middleware`]: super::WebServerModule::webserver_middleware
+
+use super::response::{Response, Result};
+use super::Request;
+
+/// The rest of the chain, as seen from a middleware
+pub struct Next<'a> {
+    remaining: &'static [Box<dyn Middleware>],
+    route: Route<'a>,
+}
+
+type Route<'a> = Box<dyn FnOnce(Request<'a>) -> futures::future::BoxFuture<'a, Result> + Send + 'a>;
+
+/// The status and headers of a rendered response
+pub struct ResponseHead<'a> {
+    inner: &'a mut salvo_core::Response,
+}
+
+#[async_trait::async_trait]
+pub trait Middleware: Send + Sync {
+    /// Called before the route.  The request can be modified, or a response returned to
+    /// short-circuit the rest of the chain and the route.
+    async fn before(&self, _req: &mut Request<'_>) -> granite::Result<Option<Response>> {
+        Ok(None)
+    }
+
+    /// Wraps the rest of the chain and the route.  Override this to run code around the route
+    /// future, e.g. to time it or to replace its response.
+    async fn around<'a>(&self, mut req: Request<'a>, next: Next<'a>) -> Result {
+        if let Some(response) = self.before(&mut req).await? {
+            return Ok(response);
+        }
+        next.run(req).await
+    }
+
+    /// Called after the response is rendered.  The request body has already been read.
+    async fn after(&self, _req: &Request<'_>, _res: &mut ResponseHead<'_>) {}
+}
+
+impl<'a> Next<'a> {
+    pub(crate) fn new<F>(chain: &'static [Box<dyn Middleware>], route: F) -> Self
+    where
+        F: FnOnce(Request<'a>) -> futures::future::BoxFuture<'a, Result> + Send + 'a,
+    {
+        Self {
+            remaining: chain,
+            route: Box::new(route),
+        }
+    }
+
+    /// Continue with the next middleware, or the route if this was the last one
+    pub async fn run(self, req: Request<'a>) -> Result {
+        match self.remaining.split_first() {
+            Some((middleware, remaining)) => {
+                let next = Next {
+                    remaining,
+                    route: self.route,
+                };
+                middleware.around(req, next).await
+            }
+            None => (self.route)(req).await,
+        }
+    }
+}
+
+impl<'a> From<&'a mut salvo_core::Response> for ResponseHead<'a> {
+    fn from(response: &'a mut salvo_core::Response) -> Self {
+        Self { inner: response }
+    }
+}
+
+impl ResponseHead<'_> {
+    pub fn status(&self) -> http::StatusCode {
+        self.inner.status_code.unwrap_or(http::StatusCode::OK)
+    }
+
+    pub fn set_status(&mut self, status: http::StatusCode) {
+        self.inner.status_code = Some(status);
+    }
+
+    pub fn headers(&self) -> &http::HeaderMap {
+        &self.inner.headers
+    }
+
+    pub fn headers_mut(&mut self) -> &mut http::HeaderMap {
+        &mut self.inner.headers
+    }
+}
@@ -1,9 +1,27 @@
 use granite::ErrorKind;
 use headers::HeaderMapExt;
-pub use http::StatusCode;
+pub use http::{Method, StatusCode};
 
+pub mod assets;
+pub mod compression;
+pub mod cookies;
+pub mod cors;
+pub mod csrf;
 pub mod exports;
+mod forwarded;
+pub mod health;
+pub mod logging;
+pub mod metrics;
+pub mod middleware;
+pub mod multipart;
+mod range;
+pub mod rate_limit;
 pub mod response;
+pub mod security_headers;
+pub mod session;
+pub mod test_client;
+pub mod timeout;
+mod tls;
 pub mod websocket;
 
 use http::{header::UPGRADE, HeaderMap};
@@ -16,17 +34,83 @@ pub struct WebServerConfig {
     pub host: IpAddr,
 
     pub port: u16,
+
+    /// Set to false to serve plain HTTP (e.g. behind a TLS terminating proxy).  QUIC is only
+    /// available over TLS so it is turned off as well.
+    #[serde(default = "default_tls")]
+    pub tls: bool,
+
+    /// If set (and `tls` is on), listen on this port too and 308 redirect everything to HTTPS
+    pub redirect_http_port: Option<u16>,
+
     #[serde(default = "default_tls_cert_pem")]
     pub tls_cert_pem: String,
 
     #[serde(default = "default_tls_key_pem")]
     pub tls_key_pem: String,
+
+    /// Read the certificate chain and key from these files instead of `tls_cert_pem` and
+    /// `tls_key_pem`.  They are reloaded when they change on disk or on SIGHUP.
+    pub tls_cert_path: Option<std::path::PathBuf>,
+    pub tls_key_path: Option<std::path::PathBuf>,
+
+    /// Largest request body, in bytes, any route will read.  Routes can override it with a
+    /// `max_body_size` instruction.
+    pub max_body_size: Option<u64>,
+
+    /// Seconds a request may take to be answered before it is cancelled and fails with
+    /// `ErrorKind::Timeout`.  Routes can override it with a `timeout` instruction.  Unset means no
+    /// limit.  See [`timeout`].
+    pub timeout: Option<u64>,
+
+    /// Seconds to wait for in-flight requests and websocket sessions to finish on shutdown
+    pub shutdown_timeout: Option<u64>,
+
+    /// Seconds a session is kept after the last request that used it
+    pub session_ttl: Option<u64>,
+
+    /// Attributes of the `SessionToken` cookie and of cookies made with [`Request::new_cookie`]
+    #[serde(default)]
+    pub cookie: cookies::CookieConfig,
+
+    /// `pretty` (the default) or `json` lines
+    #[serde(default)]
+    pub log_format: logging::LogFormat,
+
+    /// Which responses are compressed for clients that accept it
+    #[serde(default)]
+    pub compression: compression::CompressionConfig,
+
+    /// Which other origins may call the server from a browser
+    #[serde(default)]
+    pub cors: cors::CorsConfig,
+
+    /// Proxies (e.g. `10.0.0.0/8`) whose `Forwarded` and `X-Forwarded-*` headers are believed
+    /// when working out the client's address and the url it requested
+    #[serde(default)]
+    pub trusted_proxies: Vec<ipnetwork::IpNetwork>,
+
+    /// Values of the security headers sent with every response
+    #[serde(default)]
+    pub security_headers: security_headers::SecurityHeadersConfig,
+
+    /// The `/health` and `/ready` endpoints
+    #[serde(default)]
+    pub health: health::HealthConfig,
+
+    /// The `/metrics` endpoint
+    #[serde(default)]
+    pub metrics: metrics::MetricsConfig,
 }
 
 fn default_host() -> IpAddr {
     "127.0.0.1".parse().unwrap()
 }
 
+fn default_tls() -> bool {
+    true
+}
+
 fn default_tls_cert_pem() -> String {
     include_str!("../../../../meta/localhost.crt.pem").to_string()
 }
@@ -36,13 +120,21 @@ fn default_tls_key_pem() -> String {
 }
 
 impl WebServerConfig {
-    pub fn into_system(self) -> WebServerSystem {
-        WebServerSystem { config: self }
+    pub fn into_system(mut self) -> WebServerSystem {
+        let session_ttl =
+            std::time::Duration::from_secs(self.session_ttl.unwrap_or(DEFAULT_SESSION_TTL));
+        let cookie_policy = std::mem::take(&mut self.cookie).into_policy(self.tls, session_ttl);
+
+        WebServerSystem {
+            config: self,
+            cookie_policy,
+        }
     }
 }
 
 pub struct WebServerSystem {
     config: WebServerConfig,
+    cookie_policy: cookies::CookiePolicy,
 }
 
 impl WebServerSystem {
@@ -52,12 +144,63 @@ impl WebServerSystem {
     pub fn port(&self) -> u16 {
         self.config.port
     }
+    pub fn tls(&self) -> bool {
+        self.config.tls
+    }
+    pub fn redirect_http_port(&self) -> Option<u16> {
+        match self.config.tls {
+            true => self.config.redirect_http_port,
+            false => None,
+        }
+    }
     pub fn tls_cert_pem(&self) -> &str {
         &self.config.tls_cert_pem
     }
     pub fn tls_key_pem(&self) -> &str {
         &self.config.tls_key_pem
     }
+    pub fn tls_cert_path(&self) -> Option<&std::path::Path> {
+        self.config.tls_cert_path.as_deref()
+    }
+    pub fn tls_key_path(&self) -> Option<&std::path::Path> {
+        self.config.tls_key_path.as_deref()
+    }
+    pub fn max_body_size(&self) -> u64 {
+        self.config.max_body_size.unwrap_or(DEFAULT_MAX_BODY_SIZE)
+    }
+    pub fn timeout(&self) -> Option<std::time::Duration> {
+        self.config.timeout.map(std::time::Duration::from_secs)
+    }
+    pub fn shutdown_timeout(&self) -> std::time::Duration {
+        std::time::Duration::from_secs(self.config.shutdown_timeout.unwrap_or(30))
+    }
+    pub fn session_ttl(&self) -> std::time::Duration {
+        std::time::Duration::from_secs(self.config.session_ttl.unwrap_or(DEFAULT_SESSION_TTL))
+    }
+    pub fn cookie_policy(&self) -> &cookies::CookiePolicy {
+        &self.cookie_policy
+    }
+    pub fn log_format(&self) -> logging::LogFormat {
+        self.config.log_format
+    }
+    pub fn compression(&self) -> &compression::CompressionConfig {
+        &self.config.compression
+    }
+    pub fn cors(&self) -> &cors::CorsConfig {
+        &self.config.cors
+    }
+    pub fn trusted_proxies(&self) -> &[ipnetwork::IpNetwork] {
+        &self.config.trusted_proxies
+    }
+    pub fn security_headers(&self) -> &security_headers::SecurityHeadersConfig {
+        &self.config.security_headers
+    }
+    pub fn health(&self) -> &health::HealthConfig {
+        &self.config.health
+    }
+    pub fn metrics(&self) -> &metrics::MetricsConfig {
+        &self.config.metrics
+    }
 }
 
 pub trait WebServerModule {
@@ -69,18 +212,96 @@ pub trait WebServerModule {
         req: Request<'a>,
     ) -> impl std::future::Future<Output = crate::server::response::Result> + Send;
 
+    /// Where [`session::Session`]s are kept.  Defaults to the memory of this process, which is
+    /// only suitable for development; use a [`session::RedisSessionStore`] in production.
+    fn webserver_session_store(&'static self) -> &'static dyn session::SessionStore {
+        session::default_store()
+    }
+
+    /// Where the counters for `rate_limit` instructions are kept.  Defaults to the memory of this
+    /// process; use a [`rate_limit::RedisRateLimitStore`] to share them between instances.
+    fn webserver_rate_limit_store(&'static self) -> &'static dyn rate_limit::RateLimitStore {
+        rate_limit::default_store()
+    }
+
+    /// The hashed paths of the compiled-in assets, which documents link to.  Return
+    /// `&approck_generated::ASSET_MANIFEST` here.  See [`assets`].
+    fn webserver_asset_manifest(&'static self) -> &'static assets::Manifest {
+        assets::Manifest::empty()
+    }
+
+    /// Middleware run around every route, in order.  See [`middleware`].
+    fn webserver_middleware(&'static self) -> &'static [Box<dyn middleware::Middleware>] {
+        &[]
+    }
+
     /// Provide the user with some nice looking response.
     fn webserver_handle_error(&self, error: granite::Error) -> crate::server::response::Result {
         Ok(standard_handle_error(error))
     }
+
+    /// The checks `/ready` runs, e.g. [`health::postgres`] and [`health::redis`] for each pool.
+    /// Run them concurrently, since each may take up to `webserver.health.timeout`.  See
+    /// [`health`].
+    fn webserver_readiness(
+        &'static self,
+    ) -> impl std::future::Future<Output = Vec<health::Component>> + Send {
+        async { Vec::new() }
+    }
+
+    /// Called on each scrape of `/metrics` to add the application's own metrics, e.g. the state
+    /// of its pools with [`metrics::Metrics::pool`].  See [`metrics`].
+    fn webserver_metrics(&'static self, _metrics: &mut metrics::Metrics) {}
+
+    /// Called once by [`serve`] after it has stopped accepting connections and drained the
+    /// in-flight requests and websocket sessions.  Close pools and flush anything buffered here.
+    fn webserver_shutdown(&'static self) -> impl std::future::Future<Output = ()> + Send {
+        async {}
+    }
 }
 
+/// Used when neither the config nor the route sets `max_body_size`
+pub const DEFAULT_MAX_BODY_SIZE: u64 = 10 * 1024 * 1024;
+
+/// Seconds a session is kept when `webserver.session_ttl` is not set
+const DEFAULT_SESSION_TTL: u64 = 7 * 24 * 60 * 60;
+
 #[derive(Debug)]
-pub struct Request<'a>(&'a mut salvo_core::Request);
+pub struct Request<'a> {
+    inner: &'a mut salvo_core::Request,
+    max_body_size: u64,
+    request_id: String,
+    session_token: String,
+    session: session::Session,
+    cookie_policy: &'static cookies::CookiePolicy,
+    trusted_proxies: &'static [ipnetwork::IpNetwork],
+    deadline: std::sync::Arc<timeout::Deadline>,
+}
 
 impl<'a> From<&'a mut salvo_core::Request> for Request<'a> {
     fn from(request: &'a mut salvo_core::Request) -> Self {
-        Self(request)
+        let session_token = match request.cookie("SessionToken") {
+            Some(cookie) if cookie.value_trimmed().len() == 64 => cookie.value_trimmed().to_owned(),
+            _ => granite::ts_random_hex(64),
+        };
+
+        // the server swaps in the application's store and ttl
+        let session = session::Session::new(
+            session::default_store(),
+            session_token.clone(),
+            std::time::Duration::from_secs(DEFAULT_SESSION_TTL),
+        );
+
+        Self {
+            inner: request,
+            max_body_size: DEFAULT_MAX_BODY_SIZE,
+            request_id: String::new(),
+            session_token,
+            session,
+            cookie_policy: cookies::default_policy(),
+            trusted_proxies: &[],
+            deadline: std::sync::Arc::new(timeout::Deadline::new()),
+        }
     }
 }
 
@@ -130,7 +351,7 @@ impl<'a> Request<'a> {
             return Ok(None);
         }
 
-        websocket::upgrade(self.0, websocket_handler)
+        websocket::upgrade(self.inner, websocket_handler)
             .await
             .map(Some)
     }
@@ -138,80 +359,393 @@ impl<'a> Request<'a> {
     /// Get the chunks of the path as a vec.  For example:  
     ///   `/a/b/c/d` -> `["a", "b", "c", "d"]`
     pub fn path_chunks(&self) -> Vec<&str> {
-        self.0.uri().path().split('/').skip(1).collect()
+        self.inner.uri().path().split('/').skip(1).collect()
     }
 
     pub fn path(&self) -> &str {
-        self.0.uri().path()
+        self.inner.uri().path()
+    }
+
+    pub fn method(&self) -> &http::Method {
+        self.inner.method()
     }
 
     pub fn uri_string(&self) -> String {
-        self.0.uri().to_string()
+        self.inner.uri().to_string()
     }
 
     /// Get the http::HeaderMap from the request
     pub fn headers(&self) -> &http::HeaderMap {
-        self.0.headers()
+        self.inner.headers()
     }
 
-    pub fn session_token(&self) -> String {
-        if let Some(cookie) = self.0.cookie("SessionToken") {
-            let cookie = cookie.value_trimmed();
-            if cookie.len() == 64 {
-                return cookie.to_owned();
-            }
+    /// The IP address of the client.  When the request comes through one of
+    /// `webserver.trusted_proxies`, this is the address they forwarded it for.
+    pub fn remote_addr(&self) -> Option<IpAddr> {
+        self.forwarded().map(|client| client.ip)
+    }
+
+    /// The IP address of the connection, which is a proxy's if there is one in front
+    pub fn peer_addr(&self) -> Option<IpAddr> {
+        self.inner
+            .remote_addr()
+            .clone()
+            .into_std()
+            .map(|addr| addr.ip())
+    }
+
+    /// `https` or `http`, as the client sent the request.  Behind a trusted proxy which
+    /// terminates TLS, this comes from the proxy's headers.
+    pub fn scheme(&self) -> &str {
+        match self.forwarded().and_then(|client| client.proto) {
+            Some(proto) if proto.eq_ignore_ascii_case("https") => "https",
+            Some(proto) if proto.eq_ignore_ascii_case("http") => "http",
+            _ => self.inner.scheme().as_str(),
         }
+    }
+
+    /// The host, and port if it is not the default, which the client sent the request to, e.g.
+    /// `example.com` or `localhost:3014`
+    pub fn host(&self) -> &str {
+        let is_valid = |host: &&str| http::uri::Authority::try_from(*host).is_ok();
+        self.forwarded()
+            .and_then(|client| client.host)
+            .filter(is_valid)
+            .or_else(|| {
+                self.inner
+                    .headers()
+                    .get(http::header::HOST)
+                    .and_then(|host| host.to_str().ok())
+                    .filter(is_valid)
+            })
+            .or_else(|| {
+                self.inner
+                    .uri()
+                    .authority()
+                    .map(|authority| authority.as_str())
+            })
+            .unwrap_or("localhost")
+    }
+
+    /// The url the client requested, as it would appear in its address bar, e.g.
+    /// `https://example.com/user/1?tab=2`.  Join paths onto it for absolute links in emails.
+    pub fn absolute_url(&self) -> String {
+        let path_and_query = self
+            .inner
+            .uri()
+            .path_and_query()
+            .map_or("/", |path_and_query| path_and_query.as_str());
+        format!("{}://{}{}", self.scheme(), self.host(), path_and_query)
+    }
+
+    fn forwarded(&self) -> Option<forwarded::Client<'_>> {
+        let peer = self.peer_addr()?;
+        Some(forwarded::client(
+            self.inner.headers(),
+            peer,
+            self.trusted_proxies,
+        ))
+    }
+
+    /// Count this request against `rate_limit`, returning the `429 Too Many Requests` to send
+    /// instead of running the route if it is over.  Called by routes with `rate_limit = ...;`.
+    /// See [`rate_limit`].
+    pub async fn check_rate_limit(
+        &self,
+        rate_limit: &rate_limit::RateLimit,
+    ) -> granite::Result<Option<response::Response>> {
+        let client = match rate_limit.per {
+            rate_limit::Per::Ip => match self.remote_addr() {
+                Some(ip) => ip.to_string(),
+                None => "unknown".to_string(),
+            },
+            rate_limit::Per::Session => self.session_token.clone(),
+        };
+        rate_limit::check(rate_limit, &client).await
+    }
+
+    /// The `Last-Event-ID` header an `EventSource` sends when it reconnects, which is the `id` of
+    /// the last [`SseEvent`](response::SseEvent) it received.  Resume the stream after it.
+    pub fn last_event_id(&self) -> Option<&str> {
+        self.inner
+            .headers()
+            .get("last-event-id")
+            .and_then(|value| value.to_str().ok())
+    }
+
+    /// A cookie the client sent
+    pub fn cookie(&self, name: &str) -> Option<&cookie::Cookie<'static>> {
+        self.inner.cookie(name)
+    }
+
+    /// A cookie the client sent, if it was added with `add_signed_cookie` and not tampered with
+    pub fn signed_cookie(&self, name: &str) -> Option<cookie::Cookie<'static>> {
+        cookies::verify(self.cookie_policy.key(), self.cookie(name)?.clone())
+    }
 
-        granite::ts_random_hex(64)
+    /// A cookie the client sent, decrypted, if it was added with `add_private_cookie`
+    pub fn private_cookie(&self, name: &str) -> Option<cookie::Cookie<'static>> {
+        cookies::decrypt(self.cookie_policy.key(), self.cookie(name)?.clone())
+    }
+
+    /// A cookie following the configured policy (secure, same site, domain, max age), ready to be
+    /// added to a response
+    pub fn new_cookie(
+        &self,
+        name: impl Into<String>,
+        value: impl Into<String>,
+    ) -> cookie::CookieBuilder<'static> {
+        self.cookie_policy.cookie(name, value)
+    }
+
+    /// The key to pass to `add_signed_cookie` and `add_private_cookie` on responses
+    pub fn cookie_key(&self) -> &'static cookie::Key {
+        self.cookie_policy.key()
+    }
+
+    /// The client's `SessionToken` cookie if it is well formed, otherwise a new token.  Note
+    /// that the session may be rotated to a new token before the response is sent.
+    pub fn session_token(&self) -> String {
+        self.session_token.clone()
+    }
+
+    /// The server-side session for this request.  See [`session`].
+    pub fn session(&self) -> session::Session {
+        self.session.clone()
+    }
+
+    /// This session's CSRF token.  Render it inside every form posted to a `PostForm` handler.
+    /// See [`csrf`].
+    pub async fn csrf_token(&self) -> granite::Result<csrf::CsrfToken> {
+        csrf::token(&self.session).await
+    }
+
+    /// Fails with [`ErrorKind::Authorization`] unless `submitted`, or failing that the
+    /// `X-CSRF-Token` header, matches this session's CSRF token.  Called by `PostForm::parse`.
+    pub async fn verify_csrf_token(&self, submitted: Option<&str>) -> granite::Result<()> {
+        let submitted = submitted.or_else(|| {
+            self.inner
+                .headers()
+                .get(csrf::HEADER_NAME)
+                .and_then(|value| value.to_str().ok())
+        });
+        csrf::verify(&self.session, submitted).await
     }
 
     pub fn iter_query_pairs(&self) -> url::form_urlencoded::Parse<'_> {
-        url::form_urlencoded::parse(self.0.uri().query().unwrap_or("").as_bytes())
+        url::form_urlencoded::parse(self.inner.uri().query().unwrap_or("").as_bytes())
     }
 
-    pub async fn read_body_as_bytes(&mut self) -> Result<Vec<u8>, granite::Error> {
-        match self.0.body() {
-            salvo_core::http::ReqBody::Once(bytes) => Ok(bytes.to_vec()),
-            salvo_core::http::ReqBody::None => Ok(Vec::new()),
-            _ => Err(granite::Error::new(ErrorKind::Unexpected)
-                .add_context("Unable to read request body as bytes")),
-        }
+    /// Identifies this request in the logs and the `X-Request-Id` response header
+    pub fn request_id(&self) -> &str {
+        &self.request_id
+    }
+
+    /// The largest request body, in bytes, that will be read before failing with
+    /// [`ErrorKind::PayloadTooLarge`]
+    pub fn max_body_size(&self) -> u64 {
+        self.max_body_size
+    }
+
+    /// Set by the server from `webserver.max_body_size` and by routes with a `max_body_size`
+    /// instruction.  Must be called before the body is read.
+    pub fn set_max_body_size(&mut self, max_body_size: u64) {
+        self.max_body_size = max_body_size;
+    }
+
+    /// How long this request may take to be answered, counted from its start
+    pub fn timeout(&self) -> Option<std::time::Duration> {
+        self.deadline.timeout()
+    }
+
+    /// Set by the server from `webserver.timeout` and by routes with a `timeout` instruction.
+    /// `None` lets the request take as long as it needs.  See [`timeout`].
+    pub fn set_timeout(&mut self, timeout: Option<std::time::Duration>) {
+        self.deadline.set_timeout(timeout);
+    }
+
+    /// Set by routes with a `cors` instruction, in place of `webserver.cors.allow_origins`.  See
+    /// [`cors`].
+    pub fn set_cors(&mut self, cors: cors::RouteCors) {
+        self.inner.extensions_mut().insert(cors);
+    }
+
+    /// The nonce which lets an inline `<script>` or `<style>` run under this response's
+    /// `Content-Security-Policy`.  See [`security_headers`].
+    pub fn csp_nonce(&self) -> Option<String> {
+        security_headers::csp_nonce()
+    }
+
+    /// Set by the generated code of every route to its module path, which labels its metrics
+    pub fn set_route(&mut self, route: &'static str) {
+        self.inner.extensions_mut().insert(metrics::Route(route));
+    }
+
+    /// The module path of the route handling this request, once the router has found it
+    pub fn route(&self) -> Option<&'static str> {
+        metrics::route(self.inner)
+    }
+
+    /// Take the request body as a stream of chunks, as they arrive from the client.  The stream
+    /// yields an [`ErrorKind::PayloadTooLarge`] error (and then ends) as soon as more than
+    /// `max_body_size` bytes have been received.  The body can only be taken once.
+    pub fn body_stream(
+        &mut self,
+    ) -> impl futures::Stream<Item = granite::Result<bytes::Bytes>> + Send + 'static {
+        let max_body_size = self.max_body_size;
+
+        // No need to wait for the body if the client already told us it is too big
+        let content_length = self
+            .inner
+            .headers()
+            .typed_get::<headers::ContentLength>()
+            .map(|content_length| content_length.0)
+            .unwrap_or(0);
+
+        let body = Box::pin(self.inner.take_body());
+        let received = match content_length > max_body_size {
+            true => content_length,
+            false => 0,
+        };
+
+        // the state is the body and how much of it was received so far, or None once finished
+        futures::stream::unfold(
+            (body, Some(received)),
+            move |(mut body, received)| async move {
+                use http_body_util::BodyExt;
+
+                let mut received = received?;
+
+                if received > max_body_size {
+                    return Some((Err(payload_too_large(max_body_size)), (body, None)));
+                }
+
+                loop {
+                    match body.frame().await? {
+                        Ok(frame) => {
+                            // trailers are not part of the body
+                            if let Ok(data) = frame.into_data() {
+                                received += data.len() as u64;
+                                if received > max_body_size {
+                                    return Some((
+                                        Err(payload_too_large(max_body_size)),
+                                        (body, None),
+                                    ));
+                                }
+                                return Some((Ok(data), (body, Some(received))));
+                            }
+                        }
+                        Err(error) => {
+                            let error = granite::Error::new(ErrorKind::Unexpected)
+                                .add_context(format!("Unable to read request body: {error}"));
+                            return Some((Err(error), (body, None)));
+                        }
+                    }
+                }
+            },
+        )
+    }
+
+    /// Read the whole request body into memory, subject to `max_body_size`
+    pub async fn read_body_as_bytes(&mut self) -> granite::Result<Vec<u8>> {
+        use futures::TryStreamExt;
+
+        self.body_stream()
+            .try_fold(Vec::new(), |mut bytes, chunk| async move {
+                bytes.extend_from_slice(&chunk);
+                Ok(bytes)
+            })
+            .await
     }
 
     pub fn is_upgrade(&self) -> bool {
-        let connection = match self.0.headers().typed_get::<headers::Connection>() {
+        let connection = match self.inner.headers().typed_get::<headers::Connection>() {
             Some(connection) => connection,
             None => return false,
         };
-        let upgrade = match self.0.headers().typed_get::<headers::Upgrade>() {
+        let upgrade = match self.inner.headers().typed_get::<headers::Upgrade>() {
             Some(upgrade) => upgrade,
             None => return false,
         };
         connection.contains(UPGRADE) && upgrade == headers::Upgrade::websocket()
     }
 
-    // write a function to iter_body_query_pairs
-    pub async fn read_body_query_pairs(&mut self) -> Vec<(String, String)> {
-        let bytes = self.read_body_as_bytes().await.unwrap();
+    /// True if the request body is `multipart/form-data`
+    pub fn is_multipart(&self) -> bool {
+        self.multipart_boundary().is_some()
+    }
+
+    fn multipart_boundary(&self) -> Option<String> {
+        let content_type = self.inner.headers().get(http::header::CONTENT_TYPE)?;
+        multer::parse_boundary(content_type.to_str().ok()?).ok()
+    }
+
+    /// Read the request body as `multipart/form-data`, one field at a time, subject to
+    /// `max_body_size`
+    pub fn multipart(&mut self) -> granite::Result<multipart::Multipart> {
+        match self.multipart_boundary() {
+            Some(boundary) => Ok(multipart::Multipart::new(self.body_stream(), boundary)),
+            None => Err(granite::Error::new(ErrorKind::Validation)
+                .add_context("Request body is not multipart/form-data")),
+        }
+    }
+
+    /// Read the whole request body and parse it as `application/x-www-form-urlencoded`
+    pub async fn read_body_query_pairs(&mut self) -> granite::Result<Vec<(String, String)>> {
+        let bytes = self.read_body_as_bytes().await?;
         let rval: Vec<_> = url::form_urlencoded::parse(&bytes)
             .map(|(a, b)| (a.to_string(), b.to_string()))
             .collect();
-        rval
+        Ok(rval)
+    }
+
+    /// True if the request body is `application/json` or another `+json` type
+    pub fn is_json(&self) -> bool {
+        let content_type = match self.inner.headers().typed_get::<headers::ContentType>() {
+            Some(content_type) => mime::Mime::from(content_type),
+            None => return false,
+        };
+        content_type.subtype() == mime::JSON || content_type.suffix() == Some(mime::JSON)
+    }
+
+    /// Read the whole request body and deserialize it from JSON.  A missing or wrong
+    /// `Content-Type` and a malformed body are both validation errors.
+    pub async fn read_body_json<T>(&mut self) -> granite::Result<T>
+    where
+        T: serde::de::DeserializeOwned,
+    {
+        if !self.is_json() {
+            return Err(granite::Error::new(ErrorKind::Validation)
+                .add_context("Request body is not application/json"));
+        }
+
+        let bytes = self.read_body_as_bytes().await?;
+        serde_json::from_slice(&bytes).map_err(|error| {
+            granite::Error::new(ErrorKind::Validation)
+                .add_context(format!("invalid JSON body: {error}"))
+        })
     }
 
     pub fn has_query_string(&self) -> bool {
-        self.0.uri().query().is_some()
+        self.inner.uri().query().is_some()
     }
 
     pub fn is_post(&self) -> bool {
-        self.0.method() == http::Method::POST
+        self.inner.method() == http::Method::POST
     }
 
     pub fn is_get(&self) -> bool {
-        self.0.method() == http::Method::GET
+        self.inner.method() == http::Method::GET
     }
 }
 
+/// Hand what the app provides to the modules which keep it in statics.  Only the first call in a
+/// process has any effect.
+fn set_app_statics<APP: WebServerModule>(app: &'static APP) {
+    assets::set_manifest(app.webserver_asset_manifest());
+    rate_limit::set_store(app.webserver_rate_limit_store());
+}
+
 // We would like to have different traits that APP implements, different projects might implement
 // different sets of traits. How do we make these traits available in the application code after
 // passing through here?
@@ -221,43 +755,196 @@ where
 {
     let webserver_system = &app.webserver_system();
 
-    let addr = std::net::SocketAddr::from((webserver_system.host(), webserver_system.port()));
-
-    // Do not use client certificate authentication.
-    let tls_config = rustls::RustlsConfig::new(
-        rustls::Keycert::new()
-            .key(webserver_system.tls_key_pem().to_owned())
-            .cert(webserver_system.tls_cert_pem().to_owned()),
-    );
+    logging::init(webserver_system.log_format());
+    set_app_statics(app);
 
-    let tcp_listener = salvo_core::conn::TcpListener::new(addr).rustls(tls_config.clone());
-    let quic_listener = salvo_core::conn::QuinnListener::new(tls_config, addr);
-    let acceptor = quic_listener.join(tcp_listener).try_bind().await.unwrap();
+    let addr = std::net::SocketAddr::from((webserver_system.host(), webserver_system.port()));
+    let shutdown_timeout = webserver_system.shutdown_timeout();
 
     let handler = MyHandler {
         app,
-        port: webserver_system.port(),
+        quic_port: match webserver_system.tls() {
+            true => Some(webserver_system.port()),
+            false => None,
+        },
     };
     let router = salvo_core::Router::new().path("<**>").goal(handler);
 
-    salvo_core::server::Server::new(acceptor)
-        .try_serve(router)
+    // The redirect listener runs next to the main one and shuts down with it.  It is bound here,
+    // so a port in use stops the server from starting rather than leaving it without a redirect.
+    let redirect_server = match webserver_system.redirect_http_port() {
+        Some(redirect_port) => {
+            let redirect_addr =
+                std::net::SocketAddr::from((webserver_system.host(), redirect_port));
+            let acceptor = salvo_core::conn::TcpListener::new(redirect_addr)
+                .try_bind()
+                .await
+                .unwrap_or_else(|error| {
+                    panic!("unable to listen on {redirect_addr} for the HTTPS redirect: {error}")
+                });
+            let redirect_router = salvo_core::Router::new()
+                .path("<**>")
+                .goal(RedirectHandler {
+                    https_port: webserver_system.port(),
+                });
+            Some(tokio::spawn(serve_until_shutdown(
+                acceptor,
+                redirect_router,
+                shutdown_timeout,
+            )))
+        }
+        None => None,
+    };
+
+    let deadline = match webserver_system.tls() {
+        true => match (
+            webserver_system.tls_cert_path(),
+            webserver_system.tls_key_path(),
+        ) {
+            (Some(cert_path), Some(key_path)) => {
+                // each listener needs its own stream of configs
+                let tcp_listener = salvo_core::conn::TcpListener::new(addr)
+                    .rustls(tls::watch_files(cert_path.to_owned(), key_path.to_owned()));
+                let quic_listener = salvo_core::conn::QuinnListener::new(
+                    tls::watch_files(cert_path.to_owned(), key_path.to_owned()),
+                    addr,
+                );
+                let acceptor = quic_listener.join(tcp_listener).try_bind().await.unwrap();
+                serve_until_shutdown(acceptor, router, shutdown_timeout).await
+            }
+            (None, None) => {
+                // Do not use client certificate authentication.
+                let tls_config = rustls::RustlsConfig::new(
+                    rustls::Keycert::new()
+                        .key(webserver_system.tls_key_pem().to_owned())
+                        .cert(webserver_system.tls_cert_pem().to_owned()),
+                );
+
+                let tcp_listener =
+                    salvo_core::conn::TcpListener::new(addr).rustls(tls_config.clone());
+                let quic_listener = salvo_core::conn::QuinnListener::new(tls_config, addr);
+                let acceptor = quic_listener.join(tcp_listener).try_bind().await.unwrap();
+                serve_until_shutdown(acceptor, router, shutdown_timeout).await
+            }
+            _ => panic!("webserver.tls_cert_path and webserver.tls_key_path must be set together"),
+        },
+        false => {
+            let acceptor = salvo_core::conn::TcpListener::new(addr)
+                .try_bind()
+                .await
+                .unwrap();
+            serve_until_shutdown(acceptor, router, shutdown_timeout).await
+        }
+    };
+
+    if let Some(redirect_server) = redirect_server {
+        let _ = redirect_server.await;
+    }
+
+    // Upgraded websocket connections are not tracked by the server, so wait for them separately
+    while websocket::open_websockets() > 0 && tokio::time::Instant::now() < deadline {
+        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+    }
+
+    app.webserver_shutdown().await;
+}
+
+/// Serve until SIGTERM/SIGINT, then stop accepting connections and give everything in flight
+/// until the returned deadline to finish.
+async fn serve_until_shutdown<A>(
+    acceptor: A,
+    router: salvo_core::Router,
+    shutdown_timeout: std::time::Duration,
+) -> tokio::time::Instant
+where
+    A: salvo_core::conn::Acceptor + Send + 'static,
+{
+    let server = salvo_core::server::Server::new(acceptor);
+
+    let server_handle = server.handle();
+    let shutdown = tokio::spawn(async move {
+        shutdown_signal().await;
+        server_handle.stop_graceful(shutdown_timeout);
+        tokio::time::Instant::now() + shutdown_timeout
+    });
+
+    server.try_serve(router).await.unwrap();
+
+    shutdown
         .await
-        .unwrap();
+        .unwrap_or_else(|_| tokio::time::Instant::now())
+}
+
+/// Resolves when the process receives SIGINT (ctrl-c) or, on unix, SIGTERM.
+async fn shutdown_signal() {
+    let ctrl_c = async {
+        if tokio::signal::ctrl_c().await.is_err() {
+            std::future::pending::<()>().await;
+        }
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+            Ok(mut signal) => {
+                signal.recv().await;
+            }
+            Err(_) => std::future::pending::<()>().await,
+        }
+    };
+
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => {},
+        _ = terminate => {},
+    }
+}
+
+fn payload_too_large(max_body_size: u64) -> granite::Error {
+    granite::Error::new(ErrorKind::PayloadTooLarge).add_context(format!(
+        "Request body is larger than the maximum of {max_body_size} bytes"
+    ))
+}
+
+/// The http status that best describes an error, for use in `webserver_handle_error`
+pub fn error_status_code(error: &granite::Error) -> StatusCode {
+    match error.kind {
+        ErrorKind::Validation => StatusCode::BAD_REQUEST,
+        ErrorKind::Authentication => StatusCode::UNAUTHORIZED,
+        ErrorKind::Authorization => StatusCode::FORBIDDEN,
+        ErrorKind::PayloadTooLarge => StatusCode::PAYLOAD_TOO_LARGE,
+        ErrorKind::Timeout => StatusCode::GATEWAY_TIMEOUT,
+        _ => StatusCode::INTERNAL_SERVER_ERROR,
+    }
 }
 
 /// Basic rendering of errors.
 pub fn standard_handle_error(error: granite::Error) -> crate::server::response::Response {
-    eprintln!("{error:#?}");
+    tracing::error!("{error:#?}");
     response::Response::Empty(response::Empty {
-        status: StatusCode::INTERNAL_SERVER_ERROR,
+        status: error_status_code(&error),
         headers: HeaderMap::default(),
     })
 }
 
+/// Drop the body of the answer to a HEAD request, which was made by the GET handler, but keep the
+/// length it would have had
+fn strip_head_body(salvo_response: &mut salvo_core::Response) {
+    if let salvo_core::http::ResBody::Once(bytes) =
+        salvo_response.replace_body(salvo_core::http::ResBody::None)
+    {
+        salvo_response
+            .headers
+            .insert(http::header::CONTENT_LENGTH, bytes.len().into());
+    }
+}
+
 struct MyHandler<APP: 'static> {
     app: &'static APP,
-    port: u16,
+    /// The port QUIC is listening on, if it is
+    quic_port: Option<u16>,
 }
 
 #[async_trait::async_trait]
@@ -272,43 +959,260 @@ where
         salvo_response: &mut salvo_core::Response,
         _flow_control: &mut salvo_core::FlowCtrl,
     ) {
-        let request = Request::from(salvo_request);
-        let session_token = request.session_token();
+        use tracing::Instrument;
+
+        let start = std::time::Instant::now();
+        let request_id = logging::request_id(salvo_request.headers());
+        let span = tracing::info_span!(
+            "request",
+            request_id = %request_id,
+            method = %salvo_request.method(),
+            path = %salvo_request.uri().path(),
+        );
 
-        let port = self.port;
-        let path = request.path().to_owned();
+        // every response gets the security headers, built in ones included
+        let csp_nonce = security_headers::new_nonce();
 
-        let response = match self.app.webserver_route(request).await {
+        let builtin_response = self
+            .builtin_response(salvo_request)
+            .instrument(span.clone());
+        match builtin_response.await {
+            Some(response) => {
+                salvo_response.render(response);
+                self.add_headers(salvo_response, &request_id, &csp_nonce);
+                if salvo_request.method() == http::Method::HEAD {
+                    strip_head_body(salvo_response);
+                }
+            }
+            None => {
+                let _in_flight = metrics::InFlight::new();
+
+                // the error page is a document too, so it needs the nonce as much as the route
+                security_headers::scope(
+                    csp_nonce.clone(),
+                    self.handle_request(salvo_request, salvo_response, request_id, csp_nonce),
+                )
+                .instrument(span.clone())
+                .await;
+
+                metrics::record(
+                    metrics::route(salvo_request),
+                    salvo_request.method(),
+                    salvo_response.status_code.unwrap_or(StatusCode::OK),
+                    start.elapsed(),
+                );
+            }
+        }
+
+        let status = salvo_response.status_code.unwrap_or(StatusCode::OK);
+        let bytes = logging::body_size(salvo_response);
+        span.in_scope(|| {
+            tracing::info!(
+                status = status.as_u16(),
+                latency_ms = start.elapsed().as_secs_f64() * 1000.0,
+                bytes,
+                "request completed"
+            )
+        });
+    }
+}
+
+impl<APP> MyHandler<APP>
+where
+    APP: WebServerModule + Send + Sync + 'static,
+{
+    /// The answer to `GET /health`, `GET /ready` or `GET /metrics` (or HEAD of them), if this is
+    /// one of them and it is enabled
+    async fn builtin_response(
+        &self,
+        salvo_request: &salvo_core::Request,
+    ) -> Option<response::Response> {
+        let method = salvo_request.method();
+        if method != http::Method::GET && method != http::Method::HEAD {
+            return None;
+        }
+        let system = self.app.webserver_system();
+        match salvo_request.uri().path() {
+            health::HEALTH_PATH if system.health().enabled => Some(health::health()),
+            health::READY_PATH if system.health().enabled => {
+                Some(health::ready(self.app.webserver_readiness().await))
+            }
+            metrics::METRICS_PATH if system.metrics().enabled => {
+                let mut app_metrics = metrics::Metrics::default();
+                self.app.webserver_metrics(&mut app_metrics);
+                Some(metrics::response(app_metrics))
+            }
+            _ => None,
+        }
+    }
+
+    /// Add the headers every response gets, whether it came from a route or is built in.  This
+    /// must come after `.render()`, or they won't be included.
+    fn add_headers(
+        &self,
+        salvo_response: &mut salvo_core::Response,
+        request_id: &str,
+        csp_nonce: &str,
+    ) {
+        security_headers::apply(
+            self.app.webserver_system().security_headers(),
+            self.app.webserver_system().tls(),
+            csp_nonce,
+            salvo_response,
+        );
+
+        // additional header to let browsers know that they can use QUIC/HTTP3
+        if let Some(port) = self.quic_port {
+            salvo_response.headers.insert(
+                "alt-svc",
+                format!(r#"h3=":{port}"; ma=2592000"#).parse().unwrap(),
+            );
+        }
+
+        salvo_response.headers.insert(
+            logging::REQUEST_ID_HEADER,
+            http::HeaderValue::from_str(request_id).unwrap(),
+        );
+    }
+
+    async fn handle_request(
+        &self,
+        salvo_request: &mut salvo_core::Request,
+        salvo_response: &mut salvo_core::Response,
+        request_id: String,
+        csp_nonce: String,
+    ) {
+        let mut request = Request::from(&mut *salvo_request);
+        request.set_max_body_size(self.app.webserver_system().max_body_size());
+        request.set_timeout(self.app.webserver_system().timeout());
+        request.request_id = request_id.clone();
+        let mut session_token = request.session_token();
+        let session = session::Session::new(
+            self.app.webserver_session_store(),
+            session_token.clone(),
+            self.app.webserver_system().session_ttl(),
+        );
+        request.session = session.clone();
+        request.cookie_policy = self.app.webserver_system().cookie_policy();
+        request.trusted_proxies = self.app.webserver_system().trusted_proxies();
+
+        let is_head = request.method() == http::Method::HEAD;
+
+        let app = self.app;
+        let chain = app.webserver_middleware();
+        let next = middleware::Next::new(chain, move |request| {
+            Box::pin(app.webserver_route(request)) as futures::future::BoxFuture<_>
+        });
+
+        // Session changes are only kept if the route succeeded, and are written before anything
+        // is sent so a failure can still be reported
+        let deadline = request.deadline.clone();
+        let result = match deadline.run(next.run(request)).await {
+            Ok(response) => session.commit().await.map(|token| {
+                session_token = token;
+                response
+            }),
+            Err(error) => Err(error),
+        };
+
+        let mut response = match result {
             Ok(response) => response,
             Err(error) => self
                 .app
-                .webserver_handle_error(error)
+                .webserver_handle_error(error.set_request_id(request_id.clone()))
                 .unwrap_or_else(standard_handle_error),
         };
+        cors::apply(
+            self.app.webserver_system().cors(),
+            salvo_request.extensions().get::<cors::RouteCors>().copied(),
+            salvo_request.method(),
+            salvo_request.headers(),
+            &mut response,
+        );
+        let response = range::apply(salvo_request.method(), salvo_request.headers(), response);
         salvo_response.render(response);
+        self.add_headers(salvo_response, &request_id, &csp_nonce);
 
-        // if the request ends in .js, then add cache headers for 30 seconds
-        if path.ends_with(".js") || path.ends_with(".css") {
-            salvo_response.headers.insert(
-                "Cache-Control",
-                "public, max-age=30, immutable".parse().unwrap(),
-            );
-        }
+        // before HEAD drops the body, so the headers match what GET would send
+        compression::compress_response(
+            salvo_request.headers(),
+            salvo_response,
+            self.app.webserver_system().compression(),
+        )
+        .await;
 
-        // additional header to let browsers know that they can use QUIC/HTTP3
-        // Note: this must be added after .render() or it won't be included.
-        salvo_response.headers.insert(
-            "alt-svc",
-            format!(r#"h3=":{port}"; ma=2592000"#).parse().unwrap(),
-        );
+        // HEAD was routed to the GET handler, so drop the body
+        if is_head {
+            strip_head_body(salvo_response);
+        }
 
         // Add session_token cookie
         salvo_response.add_cookie(
-            cookie::Cookie::build(("SessionToken", session_token))
-                .http_only(true)
-                .max_age(cookie::time::Duration::days(3650))
-                .path("/")
+            self.app
+                .webserver_system()
+                .cookie_policy()
+                .cookie("SessionToken", session_token.clone())
                 .into(),
         );
+
+        // Middleware see the final response, last registered first
+        if !chain.is_empty() {
+            let mut request = Request::from(&mut *salvo_request);
+            request.request_id = request_id;
+            request.session_token = session_token;
+            request.session = session;
+            request.cookie_policy = self.app.webserver_system().cookie_policy();
+            request.trusted_proxies = self.app.webserver_system().trusted_proxies();
+            let mut response_head = middleware::ResponseHead::from(&mut *salvo_response);
+            for middleware in chain.iter().rev() {
+                middleware.after(&request, &mut response_head).await;
+            }
+        }
+    }
+}
+
+/// Answers every request on `redirect_http_port` with a 308 to the same url over HTTPS.
+struct RedirectHandler {
+    https_port: u16,
+}
+
+#[async_trait::async_trait]
+impl salvo_core::handler::Handler for RedirectHandler {
+    async fn handle(
+        &self,
+        salvo_request: &mut salvo_core::Request,
+        _depot: &mut salvo_core::Depot,
+        salvo_response: &mut salvo_core::Response,
+        _flow_control: &mut salvo_core::FlowCtrl,
+    ) {
+        let host = salvo_request
+            .headers()
+            .get(http::header::HOST)
+            .and_then(|host| host.to_str().ok())
+            .or_else(|| salvo_request.uri().host())
+            .unwrap_or("localhost");
+
+        // drop any port the client used to reach us, ipv6 literals keep their brackets
+        let host = match host.rsplit_once(':') {
+            Some((name, port)) if !name.is_empty() && port.chars().all(|c| c.is_ascii_digit()) => {
+                name
+            }
+            _ => host,
+        };
+
+        let path_and_query = salvo_request
+            .uri()
+            .path_and_query()
+            .map(|path_and_query| path_and_query.as_str())
+            .unwrap_or("/");
+
+        let location = match self.https_port {
+            443 => format!("https://{host}{path_and_query}"),
+            port => format!("https://{host}:{port}{path_and_query}"),
+        };
+
+        salvo_response.render(response::Response::Redirect(response::Redirect::permanent(
+            location,
+        )));
     }
 }
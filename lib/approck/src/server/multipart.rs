@@ -0,0 +1,163 @@
+//! `multipart/form-data` request bodies, as sent by forms with `enctype="multipart/form-data"`.
+//!
+//! Text fields are read into memory and files are streamed to a temporary file, both subject to
+//! an optional per-field size limit on top of the request's `max_body_size`.
+
+use granite::ErrorKind;
+use tokio::io::AsyncWriteExt;
+
+/// A `multipart/form-data` body being read one field at a time
+pub struct Multipart(multer::Multipart<'static>);
+
+/// A single field of a [`Multipart`] body
+pub struct MultipartField(multer::Field<'static>);
+
+/// A file which was uploaded in a `multipart/form-data` body.  The contents are kept in a
+/// temporary file which is deleted when this is dropped, unless it is [`persist`]ed first.
+///
+/// [`persist`]: UploadedFile::persist
+#[derive(Debug)]
+pub struct UploadedFile {
+    file_name: Option<String>,
+    content_type: Option<String>,
+    size: u64,
+    path: tempfile::TempPath,
+}
+
+impl Multipart {
+    pub(crate) fn new<S>(stream: S, boundary: String) -> Self
+    where
+        S: futures::Stream<Item = granite::Result<bytes::Bytes>> + Send + 'static,
+    {
+        use futures::TryStreamExt;
+        Self(multer::Multipart::new(
+            stream.map_err(granite::Error::into_std),
+            boundary,
+        ))
+    }
+
+    /// The next field in the body, or `None` once all of them have been read
+    pub async fn next_field(&mut self) -> granite::Result<Option<MultipartField>> {
+        match self.0.next_field().await {
+            Ok(field) => Ok(field.map(MultipartField)),
+            Err(error) => Err(from_multer(error)),
+        }
+    }
+}
+
+impl MultipartField {
+    /// The name of the form field
+    pub fn name(&self) -> Option<&str> {
+        self.0.name()
+    }
+
+    /// The name of the file on the client, if this field is a file
+    pub fn file_name(&self) -> Option<&str> {
+        self.0.file_name()
+    }
+
+    /// The content type the client sent for this field
+    pub fn content_type(&self) -> Option<&mime::Mime> {
+        self.0.content_type()
+    }
+
+    /// Read the field as UTF-8 text, failing if it is larger than `max_size` bytes
+    pub async fn text(self, max_size: Option<u64>) -> granite::Result<String> {
+        let name = self.name().unwrap_or_default().to_string();
+        let mut field = self.0;
+        let mut bytes = Vec::new();
+
+        while let Some(chunk) = field.chunk().await.map_err(from_multer)? {
+            bytes.extend_from_slice(&chunk);
+            check_size(&name, bytes.len() as u64, max_size)?;
+        }
+
+        String::from_utf8(bytes).map_err(|_| {
+            granite::Error::new(ErrorKind::Validation)
+                .add_context(format!("form field '{name}' is not valid UTF-8"))
+        })
+    }
+
+    /// Stream the field to a temporary file, failing if it is larger than `max_size` bytes
+    pub async fn file(self, max_size: Option<u64>) -> granite::Result<UploadedFile> {
+        let name = self.name().unwrap_or_default().to_string();
+        let file_name = self.file_name().map(str::to_string);
+        let content_type = self.content_type().map(|mime| mime.to_string());
+        let mut field = self.0;
+
+        let (file, path) = tempfile::NamedTempFile::new()?.into_parts();
+        let mut file = tokio::fs::File::from_std(file);
+        let mut size = 0;
+
+        while let Some(chunk) = field.chunk().await.map_err(from_multer)? {
+            size += chunk.len() as u64;
+            check_size(&name, size, max_size)?;
+            file.write_all(&chunk).await?;
+        }
+        file.flush().await?;
+
+        Ok(UploadedFile {
+            file_name,
+            content_type,
+            size,
+            path,
+        })
+    }
+}
+
+impl UploadedFile {
+    /// The name of the file on the client.  Never use it as a path without sanitizing it.
+    pub fn file_name(&self) -> Option<&str> {
+        self.file_name.as_deref()
+    }
+
+    /// The content type the client claimed for the file
+    pub fn content_type(&self) -> Option<&str> {
+        self.content_type.as_deref()
+    }
+
+    /// Size of the file in bytes
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    /// Where the contents are stored until this is dropped
+    pub fn path(&self) -> &std::path::Path {
+        &self.path
+    }
+
+    /// Read the whole file into memory
+    pub async fn read_bytes(&self) -> granite::Result<Vec<u8>> {
+        Ok(tokio::fs::read(&self.path).await?)
+    }
+
+    /// Keep the file by moving it to `path`, which should be on the same filesystem as the
+    /// system temp dir.
+    pub fn persist(self, path: impl AsRef<std::path::Path>) -> granite::Result<()> {
+        self.path.persist(path).map_err(|error| error.error)?;
+        Ok(())
+    }
+}
+
+fn check_size(name: &str, size: u64, max_size: Option<u64>) -> granite::Result<()> {
+    match max_size {
+        Some(max_size) if size > max_size => Err(granite::Error::new(ErrorKind::PayloadTooLarge)
+            .add_context(format!(
+                "form field '{name}' is larger than the maximum of {max_size} bytes"
+            ))),
+        _ => Ok(()),
+    }
+}
+
+/// Errors from reading the body keep their kind (e.g. PayloadTooLarge), anything else multer
+/// complains about is the client sending a malformed body.
+fn from_multer(error: multer::Error) -> granite::Error {
+    match error {
+        multer::Error::StreamReadFailed(error) => match error.downcast::<granite::StdError>() {
+            Ok(error) => error.0,
+            Err(error) => granite::Error::new(ErrorKind::Unexpected).add_context(error.to_string()),
+        },
+        error => granite::Error::new(ErrorKind::Validation)
+            .add_context(format!("invalid multipart/form-data body: {error}")),
+    }
+}
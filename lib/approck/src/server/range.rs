@@ -0,0 +1,403 @@
+//! `Range` requests, so clients can resume downloads and seek in media.
+//!
+//! The server answers them for [`Bytes`] responses and for seekable [`Stream`]s (see
+//! [`Stream::seekable`] and [`Stream::file`]) with a `206 Partial Content`, using
+//! `multipart/byteranges` when several ranges are asked for.  Ranges past the end get a
+//! `416 Range Not Satisfiable`.  `If-Range` is checked against the `ETag` or `Last-Modified`
+//! header of the response, so a resumed download never mixes two versions of the content.
+
+use super::response::{Bytes, Response, Stream};
+use futures::StreamExt;
+use std::ops::Range;
+
+/// Requests for more ranges than this are answered with the whole content
+const MAX_RANGES: usize = 16;
+
+#[derive(Debug, PartialEq)]
+enum Ranges {
+    /// No (usable) `Range` header, or `If-Range` did not match
+    Full,
+    Unsatisfiable,
+    Partial(Vec<Range<u64>>),
+}
+
+/// Cut `response` down to the ranges the client asked for, if it supports that.  Only `GET` (and
+/// `HEAD`, which is answered as `GET`) requests are ranged, as RFC 9110 says.
+pub(crate) fn apply(
+    method: &http::Method,
+    request_headers: &http::HeaderMap,
+    response: Response,
+) -> Response {
+    if method != http::Method::GET && method != http::Method::HEAD {
+        return response;
+    }
+
+    match response {
+        Response::Bytes(bytes) if bytes.status == http::StatusCode::OK => {
+            Response::Bytes(apply_bytes(request_headers, bytes))
+        }
+        Response::Stream(stream) if stream.status == http::StatusCode::OK => {
+            Response::Stream(apply_stream(request_headers, stream))
+        }
+        response => response,
+    }
+}
+
+fn apply_bytes(request_headers: &http::HeaderMap, mut bytes: Bytes) -> Bytes {
+    bytes.headers.insert(
+        http::header::ACCEPT_RANGES,
+        http::HeaderValue::from_static("bytes"),
+    );
+    let length = bytes.content.len() as u64;
+
+    match requested_ranges(request_headers, &bytes.headers, length) {
+        Ranges::Full => {}
+        Ranges::Unsatisfiable => {
+            bytes.status = http::StatusCode::RANGE_NOT_SATISFIABLE;
+            bytes.content = bytes::Bytes::new();
+            insert_content_range(&mut bytes.headers, format!("bytes */{length}"));
+        }
+        Ranges::Partial(ranges) if ranges.len() == 1 => {
+            let range = &ranges[0];
+            bytes.status = http::StatusCode::PARTIAL_CONTENT;
+            bytes.content = bytes
+                .content
+                .slice(range.start as usize..range.end as usize);
+            insert_content_range(&mut bytes.headers, content_range(range, length));
+        }
+        Ranges::Partial(ranges) => {
+            let boundary = granite::ts_random_hex(32);
+            let part_content_type = content_type(&bytes.headers);
+
+            let mut content = Vec::new();
+            for range in &ranges {
+                content.extend_from_slice(
+                    part_header(&boundary, &part_content_type, range, length).as_bytes(),
+                );
+                content.extend_from_slice(&bytes.content[range.start as usize..range.end as usize]);
+            }
+            content.extend_from_slice(part_footer(&boundary).as_bytes());
+
+            bytes.status = http::StatusCode::PARTIAL_CONTENT;
+            bytes.content = content.into();
+            insert_multipart_content_type(&mut bytes.headers, &boundary);
+        }
+    }
+
+    bytes
+}
+
+fn apply_stream(request_headers: &http::HeaderMap, mut stream: Stream) -> Stream {
+    // the whole thing was produced up front, so there is nothing to seek in
+    let length = match stream.seekable_parts() {
+        Some((length, _)) => length,
+        None => return stream,
+    };
+    stream.headers.insert(
+        http::header::ACCEPT_RANGES,
+        http::HeaderValue::from_static("bytes"),
+    );
+
+    match requested_ranges(request_headers, &stream.headers, length) {
+        Ranges::Full => {}
+        Ranges::Unsatisfiable => {
+            stream.status = http::StatusCode::RANGE_NOT_SATISFIABLE;
+            stream.set_content(futures::stream::empty().boxed());
+            insert_content_range(&mut stream.headers, format!("bytes */{length}"));
+            insert_content_length(&mut stream.headers, 0);
+        }
+        Ranges::Partial(ranges) if ranges.len() == 1 => {
+            let range = ranges[0].clone();
+            let content = open(&stream, range.clone());
+
+            stream.status = http::StatusCode::PARTIAL_CONTENT;
+            stream.set_content(content);
+            insert_content_range(&mut stream.headers, content_range(&range, length));
+            insert_content_length(&mut stream.headers, range.end - range.start);
+        }
+        Ranges::Partial(ranges) => {
+            let boundary = granite::ts_random_hex(32);
+            let part_content_type = content_type(&stream.headers);
+
+            let mut parts: Vec<futures::stream::BoxStream<'static, granite::Result<bytes::Bytes>>> =
+                Vec::new();
+            let mut content_length = 0;
+            for range in ranges {
+                let header = part_header(&boundary, &part_content_type, &range, length);
+                content_length += header.len() as u64 + (range.end - range.start);
+                parts.push(
+                    futures::stream::once(async move { Ok(bytes::Bytes::from(header)) }).boxed(),
+                );
+                parts.push(open(&stream, range));
+            }
+            let footer = part_footer(&boundary);
+            content_length += footer.len() as u64;
+            parts
+                .push(futures::stream::once(async move { Ok(bytes::Bytes::from(footer)) }).boxed());
+
+            stream.status = http::StatusCode::PARTIAL_CONTENT;
+            stream.set_content(futures::stream::iter(parts).flatten().boxed());
+            insert_multipart_content_type(&mut stream.headers, &boundary);
+            insert_content_length(&mut stream.headers, content_length);
+        }
+    }
+
+    stream
+}
+
+fn open(
+    stream: &Stream,
+    range: Range<u64>,
+) -> futures::stream::BoxStream<'static, granite::Result<bytes::Bytes>> {
+    let (_, open) = stream
+        .seekable_parts()
+        .expect("only called on seekable streams");
+    open(range)
+}
+
+/// What the client asked for, checked against the content's `length`
+fn requested_ranges(
+    request_headers: &http::HeaderMap,
+    response_headers: &http::HeaderMap,
+    length: u64,
+) -> Ranges {
+    let range = match request_headers
+        .get(http::header::RANGE)
+        .and_then(|value| value.to_str().ok())
+    {
+        Some(range) => range,
+        None => return Ranges::Full,
+    };
+
+    if !if_range_matches(request_headers, response_headers) {
+        return Ranges::Full;
+    }
+
+    // other units, and anything malformed, are ignored as if there was no header
+    let specs = match range.trim().strip_prefix("bytes=") {
+        Some(specs) => specs,
+        None => return Ranges::Full,
+    };
+
+    let mut ranges = Vec::new();
+    for spec in specs
+        .split(',')
+        .map(str::trim)
+        .filter(|spec| !spec.is_empty())
+    {
+        let (first, last) = match spec.split_once('-') {
+            Some((first, last)) => (first.trim(), last.trim()),
+            None => return Ranges::Full,
+        };
+
+        let range = match (first, last) {
+            // the last `suffix` bytes
+            ("", suffix) => match suffix.parse::<u64>() {
+                Ok(0) => continue,
+                Ok(_) if length == 0 => continue,
+                Ok(suffix) => length.saturating_sub(suffix)..length,
+                Err(_) => return Ranges::Full,
+            },
+            // from `first` to the end
+            (first, "") => match first.parse::<u64>() {
+                Ok(first) if first >= length => continue,
+                Ok(first) => first..length,
+                Err(_) => return Ranges::Full,
+            },
+            (first, last) => match (first.parse::<u64>(), last.parse::<u64>()) {
+                (Ok(first), Ok(last)) if last < first => return Ranges::Full,
+                (Ok(first), Ok(_)) if first >= length => continue,
+                (Ok(first), Ok(last)) => first..last.min(length - 1) + 1,
+                _ => return Ranges::Full,
+            },
+        };
+        ranges.push(range);
+    }
+
+    match ranges.len() {
+        0 => Ranges::Unsatisfiable,
+        n if n > MAX_RANGES => Ranges::Full,
+        _ => Ranges::Partial(ranges),
+    }
+}
+
+/// True unless `If-Range` names a different version than the response is
+fn if_range_matches(request_headers: &http::HeaderMap, response_headers: &http::HeaderMap) -> bool {
+    let if_range = match request_headers
+        .get(http::header::IF_RANGE)
+        .and_then(|value| value.to_str().ok())
+    {
+        Some(if_range) => if_range.trim(),
+        None => return true,
+    };
+
+    let response_header = |name: http::header::HeaderName| {
+        response_headers
+            .get(name)
+            .and_then(|value| value.to_str().ok())
+            .map(str::trim)
+    };
+
+    // entity tags start with a quote (weak ones never match), anything else is a date
+    if if_range.starts_with('"') {
+        response_header(http::header::ETAG) == Some(if_range)
+    } else if if_range.starts_with("W/") {
+        false
+    } else {
+        response_header(http::header::LAST_MODIFIED) == Some(if_range)
+    }
+}
+
+fn content_range(range: &Range<u64>, length: u64) -> String {
+    format!("bytes {}-{}/{}", range.start, range.end - 1, length)
+}
+
+fn content_type(headers: &http::HeaderMap) -> String {
+    headers
+        .get(http::header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or("application/octet-stream")
+        .to_string()
+}
+
+fn part_header(boundary: &str, content_type: &str, range: &Range<u64>, length: u64) -> String {
+    format!(
+        "\r\n--{boundary}\r\nContent-Type: {content_type}\r\nContent-Range: {}\r\n\r\n",
+        content_range(range, length)
+    )
+}
+
+fn part_footer(boundary: &str) -> String {
+    format!("\r\n--{boundary}--\r\n")
+}
+
+fn insert_content_range(headers: &mut http::HeaderMap, value: String) {
+    if let Ok(value) = http::HeaderValue::from_str(&value) {
+        headers.insert(http::header::CONTENT_RANGE, value);
+    }
+}
+
+fn insert_content_length(headers: &mut http::HeaderMap, length: u64) {
+    headers.insert(http::header::CONTENT_LENGTH, length.into());
+}
+
+fn insert_multipart_content_type(headers: &mut http::HeaderMap, boundary: &str) {
+    if let Ok(value) =
+        http::HeaderValue::from_str(&format!("multipart/byteranges; boundary={boundary}"))
+    {
+        headers.insert(http::header::CONTENT_TYPE, value);
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::single_range_in_vec_init)] // a `Vec` of one range is what is expected
+mod tests {
+    use super::Ranges;
+    use crate::server::response::{Bytes, Response};
+
+    fn ranges(range: &'static str, length: u64) -> Ranges {
+        let mut request_headers = http::HeaderMap::new();
+        request_headers.insert(http::header::RANGE, http::HeaderValue::from_static(range));
+        super::requested_ranges(&request_headers, &http::HeaderMap::new(), length)
+    }
+
+    #[test]
+    fn test_single_range() {
+        assert_eq!(ranges("bytes=0-499", 1000), Ranges::Partial(vec![0..500]));
+        assert_eq!(ranges("bytes=500-", 1000), Ranges::Partial(vec![500..1000]));
+
+        // the end is clamped to the content
+        assert_eq!(
+            ranges("bytes=900-1999", 1000),
+            Ranges::Partial(vec![900..1000])
+        );
+    }
+
+    #[test]
+    fn test_suffix_range() {
+        assert_eq!(ranges("bytes=-100", 1000), Ranges::Partial(vec![900..1000]));
+        assert_eq!(ranges("bytes=-5000", 1000), Ranges::Partial(vec![0..1000]));
+        assert_eq!(ranges("bytes=-0", 1000), Ranges::Unsatisfiable);
+        assert_eq!(ranges("bytes=-100", 0), Ranges::Unsatisfiable);
+    }
+
+    #[test]
+    fn test_multiple_ranges() {
+        assert_eq!(
+            ranges("bytes=0-99, 200-299 ,-100", 1000),
+            Ranges::Partial(vec![0..100, 200..300, 900..1000])
+        );
+
+        // the satisfiable ones are kept
+        assert_eq!(
+            ranges("bytes=0-99,5000-5999", 1000),
+            Ranges::Partial(vec![0..100])
+        );
+
+        let too_many = "bytes=0-0,1-1,2-2,3-3,4-4,5-5,6-6,7-7,8-8,9-9,10-10,11-11,12-12,13-13,14-14,15-15,16-16";
+        assert_eq!(ranges(too_many, 1000), Ranges::Full);
+    }
+
+    #[test]
+    fn test_unsatisfiable_ranges() {
+        assert_eq!(ranges("bytes=1000-", 1000), Ranges::Unsatisfiable);
+        assert_eq!(ranges("bytes=1000-1999", 1000), Ranges::Unsatisfiable);
+        assert_eq!(ranges("bytes=1000-,2000-2999", 1000), Ranges::Unsatisfiable);
+        assert_eq!(ranges("bytes=0-", 0), Ranges::Unsatisfiable);
+    }
+
+    #[test]
+    fn test_malformed_ranges_are_ignored() {
+        assert_eq!(ranges("items=0-99", 1000), Ranges::Full);
+        assert_eq!(ranges("bytes=99", 1000), Ranges::Full);
+        assert_eq!(ranges("bytes=a-b", 1000), Ranges::Full);
+        assert_eq!(ranges("bytes=500-100", 1000), Ranges::Full);
+    }
+
+    #[test]
+    fn test_only_get_and_head_are_ranged() {
+        let apply = |method: http::Method, range: &'static str| {
+            let mut request_headers = http::HeaderMap::new();
+            request_headers.insert(http::header::RANGE, http::HeaderValue::from_static(range));
+            let response = Response::Bytes(Bytes::new("hello"));
+            match super::apply(&method, &request_headers, response) {
+                Response::Bytes(bytes) => (bytes.status, bytes.content),
+                _ => panic!("expected bytes"),
+            }
+        };
+
+        let partial = (http::StatusCode::PARTIAL_CONTENT, bytes::Bytes::from("he"));
+        assert_eq!(apply(http::Method::GET, "bytes=0-1"), partial);
+        assert_eq!(apply(http::Method::HEAD, "bytes=0-1"), partial);
+
+        let full = (http::StatusCode::OK, bytes::Bytes::from("hello"));
+        assert_eq!(apply(http::Method::POST, "bytes=0-1"), full);
+        assert_eq!(apply(http::Method::PUT, "bytes=0-1"), full);
+        assert_eq!(apply(http::Method::POST, "bytes=100-"), full);
+    }
+
+    #[test]
+    fn test_if_range() {
+        let mut request_headers = http::HeaderMap::new();
+        request_headers.insert(
+            http::header::RANGE,
+            http::HeaderValue::from_static("bytes=0-99"),
+        );
+        let mut response_headers = http::HeaderMap::new();
+        response_headers.insert(http::header::ETAG, http::HeaderValue::from_static("\"v2\""));
+
+        let check = |if_range: &'static str| {
+            let mut request_headers = request_headers.clone();
+            request_headers.insert(
+                http::header::IF_RANGE,
+                http::HeaderValue::from_static(if_range),
+            );
+            super::requested_ranges(&request_headers, &response_headers, 1000)
+        };
+
+        assert_eq!(check("\"v2\""), Ranges::Partial(vec![0..100]));
+        assert_eq!(check("\"v1\""), Ranges::Full);
+        assert_eq!(check("W/\"v2\""), Ranges::Full);
+        assert_eq!(check("Wed, 21 Oct 2015 07:28:00 GMT"), Ranges::Full);
+    }
+}
@@ -0,0 +1,331 @@
+//! Per-route rate limits, set with `rate_limit = 10/min;` (or `rate_limit = 10/min per session;`)
+//! in `#[approck::http(...)]`.
+//!
+//! Requests are counted per client IP, or per session, over a sliding window: the count for the
+//! current period plus the count for the previous one, weighted by how much of it is still within
+//! the last `period`.  Over the limit, the route is not run and the client gets a
+//! `429 Too Many Requests` with a `Retry-After` header.  Refused requests count too, so a client
+//! which keeps retrying stays limited.
+//!
+//! Counting per session is meant for routes behind a login, since a client can always drop its
+//! session cookie.  Login and password reset forms should be limited per IP.
+//!
+//! The counters live in the [`RateLimitStore`] from
+//! [`WebServerModule::webserver_rate_limit_store`], which is the memory of this process unless
+//! the application provides a [`RedisRateLimitStore`].
+//!
+//! [`WebServerModule::webserver_rate_limit_store`]: super::WebServerModule::webserver_rate_limit_store
+
+use super::response::{Empty, Response};
+use std::collections::HashMap;
+use std::time::Duration;
+
+/// A route's limit, generated from its `rate_limit` instruction
+#[derive(Debug)]
+pub struct RateLimit {
+    /// Keeps the counters of each route apart
+    pub route: &'static str,
+    pub requests: u64,
+    pub period: Duration,
+    pub per: Per,
+}
+
+/// What requests are counted by
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Per {
+    Ip,
+    Session,
+}
+
+/// Where the request counters live
+#[async_trait::async_trait]
+pub trait RateLimitStore: Send + Sync {
+    /// Count a request under `key` in `window` (the number of `period`s since the epoch), and
+    /// return the counts for that window and the one before it
+    async fn hit(&self, key: &str, window: u64, period: Duration) -> granite::Result<(u64, u64)>;
+}
+
+/// Keeps the counters in the memory of this process, so each instance counts on its own
+#[derive(Default)]
+pub struct MemoryRateLimitStore {
+    counters: std::sync::Mutex<HashMap<String, Counter>>,
+}
+
+struct Counter {
+    window: u64,
+    current: u64,
+    previous: u64,
+    /// When neither window matters any more.  Windows of different periods can not be compared,
+    /// so this is what the counters of every route are pruned on.
+    expires: tokio::time::Instant,
+}
+
+impl MemoryRateLimitStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[async_trait::async_trait]
+impl RateLimitStore for MemoryRateLimitStore {
+    async fn hit(&self, key: &str, window: u64, period: Duration) -> granite::Result<(u64, u64)> {
+        let mut counters = self.counters.lock().unwrap();
+        let now = tokio::time::Instant::now();
+
+        if !counters.contains_key(key) {
+            counters.retain(|_, counter| counter.expires > now);
+        }
+
+        let counter = counters.entry(key.to_string()).or_insert(Counter {
+            window,
+            current: 0,
+            previous: 0,
+            expires: now,
+        });
+        // this window is current until at most `period` from now, and previous for one more
+        counter.expires = now + period * 2;
+        if counter.window != window {
+            counter.previous = match counter.window + 1 == window {
+                true => counter.current,
+                false => 0,
+            };
+            counter.current = 0;
+            counter.window = window;
+        }
+        counter.current += 1;
+
+        Ok((counter.current, counter.previous))
+    }
+}
+
+/// How long a request waits on Redis before it is counted in memory instead
+const REDIS_TIMEOUT: Duration = Duration::from_millis(250);
+
+/// How long Redis is skipped after it fails or times out
+const REDIS_RETRY_AFTER: Duration = Duration::from_secs(5);
+
+/// Keeps the counters in Redis under `rate_limit:<route>:<client>:<window>`, so all instances
+/// share them.  While Redis cannot be reached, requests are counted in memory instead.
+pub struct RedisRateLimitStore {
+    pool: granite_redis::RedisPool,
+    fallback: MemoryRateLimitStore,
+    /// Set when Redis fails, so the requests after it go straight to the fallback
+    retry_at: std::sync::Mutex<Option<std::time::Instant>>,
+}
+
+impl RedisRateLimitStore {
+    pub fn new(pool: granite_redis::RedisPool) -> Self {
+        Self {
+            pool,
+            fallback: MemoryRateLimitStore::new(),
+            retry_at: std::sync::Mutex::new(None),
+        }
+    }
+
+    fn key(key: &str, window: u64) -> String {
+        format!("rate_limit:{key}:{window}")
+    }
+
+    async fn hit_redis(
+        &self,
+        key: &str,
+        window: u64,
+        period: Duration,
+    ) -> granite::Result<(u64, u64)> {
+        let mut redis_cx = self.pool.get().await?;
+        // kept through the next window, where it is the previous one
+        let current = redis_cx
+            .incr_expire(&Self::key(key, window), period.as_secs().max(1) * 2)
+            .await?;
+        let previous: Option<u64> = redis_cx
+            .get_val(&Self::key(key, window.saturating_sub(1)))
+            .await?;
+        Ok((current, previous.unwrap_or(0)))
+    }
+}
+
+#[async_trait::async_trait]
+impl RateLimitStore for RedisRateLimitStore {
+    async fn hit(&self, key: &str, window: u64, period: Duration) -> granite::Result<(u64, u64)> {
+        let retry_at = *self.retry_at.lock().unwrap();
+        if retry_at.is_some_and(|retry_at| retry_at > std::time::Instant::now()) {
+            return self.fallback.hit(key, window, period).await;
+        }
+
+        match tokio::time::timeout(REDIS_TIMEOUT, self.hit_redis(key, window, period)).await {
+            Ok(Ok(counts)) => return Ok(counts),
+            Ok(Err(error)) => {
+                tracing::warn!("rate limit counted in memory, redis failed: {error}");
+            }
+            Err(_) => {
+                tracing::warn!("rate limit counted in memory, redis timed out");
+            }
+        }
+
+        *self.retry_at.lock().unwrap() = Some(std::time::Instant::now() + REDIS_RETRY_AFTER);
+        self.fallback.hit(key, window, period).await
+    }
+}
+
+/// Used when the application does not provide a store
+pub(crate) fn default_store() -> &'static MemoryRateLimitStore {
+    static DEFAULT: std::sync::OnceLock<MemoryRateLimitStore> = std::sync::OnceLock::new();
+    DEFAULT.get_or_init(MemoryRateLimitStore::new)
+}
+
+static STORE: std::sync::OnceLock<&'static dyn RateLimitStore> = std::sync::OnceLock::new();
+
+/// Called once by [`serve`](super::serve)
+pub(crate) fn set_store(store: &'static dyn RateLimitStore) {
+    let _ = STORE.set(store);
+}
+
+fn store() -> &'static dyn RateLimitStore {
+    match STORE.get() {
+        Some(store) => *store,
+        None => default_store(),
+    }
+}
+
+/// Count a request from `client` against `rate_limit`, returning the `429` to send if it is over
+pub(crate) async fn check(
+    rate_limit: &RateLimit,
+    client: &str,
+) -> granite::Result<Option<Response>> {
+    let period = rate_limit.period.as_millis().max(1) as u64;
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as u64;
+    let window = now / period;
+    // how far into the current window we are, from 0 to 1
+    let elapsed = (now % period) as f64 / period as f64;
+
+    let key = format!("{}:{}", rate_limit.route, client);
+    let (current, previous) = store().hit(&key, window, rate_limit.period).await?;
+
+    let retry_after = match retry_after(rate_limit.requests, period, elapsed, current, previous) {
+        Some(retry_after) => retry_after,
+        None => return Ok(None),
+    };
+
+    let mut headers = http::HeaderMap::new();
+    headers.insert(http::header::RETRY_AFTER, retry_after.into());
+    Ok(Some(Response::Empty(Empty {
+        status: http::StatusCode::TOO_MANY_REQUESTS,
+        headers,
+    })))
+}
+
+/// The seconds until a request would fit within `limit`, or `None` if this one does.  `period`
+/// is in milliseconds, and `elapsed` is how far into the current window we are, from 0 to 1.
+fn retry_after(limit: u64, period: u64, elapsed: f64, current: u64, previous: u64) -> Option<u64> {
+    let limit = limit as f64;
+    if previous as f64 * (1.0 - elapsed) + current as f64 <= limit {
+        return None;
+    }
+
+    // How far from the start of this window the next request would fit, given that it will
+    // count too.  If this window is full on its own, that is in the next one, once this one
+    // has faded enough.
+    let room = limit - current as f64 - 1.0;
+    let fits_at = match room >= 0.0 && previous > 0 {
+        true => 1.0 - room / previous as f64,
+        false => 2.0 - (limit - 1.0) / current as f64,
+    };
+    let retry_after = ((fits_at - elapsed).max(0.0) * period as f64 / 1000.0).ceil() as u64;
+    Some(retry_after.max(1))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{MemoryRateLimitStore, RateLimitStore};
+    use std::time::Duration;
+
+    const MINUTE: u64 = 60 * 1000;
+
+    #[test]
+    fn test_under_limit() {
+        assert_eq!(super::retry_after(10, MINUTE, 0.0, 1, 0), None);
+        assert_eq!(super::retry_after(10, MINUTE, 0.0, 10, 0), None);
+    }
+
+    #[test]
+    fn test_previous_window_is_weighted() {
+        // half of the previous window is still within the last minute: 10 * 0.5 + 5
+        assert_eq!(super::retry_after(10, MINUTE, 0.5, 5, 10), None);
+
+        // 10 * 0.5 + 6 is over, and the next request fits once 10 * 0.3 + 6 + 1 <= 10
+        assert_eq!(super::retry_after(10, MINUTE, 0.5, 6, 10), Some(12));
+    }
+
+    #[test]
+    fn test_full_window_waits_for_the_next() {
+        // 11 in this window fades to 9 at 2/11 into the next one, leaving room for 1 more
+        assert_eq!(super::retry_after(10, MINUTE, 0.5, 11, 0), Some(41));
+        assert_eq!(super::retry_after(10, MINUTE, 0.5, 11, 10), Some(41));
+    }
+
+    #[test]
+    fn test_retry_after_is_at_least_a_second() {
+        assert_eq!(super::retry_after(10, 1000, 0.99, 11, 0), Some(1));
+    }
+
+    #[tokio::test]
+    async fn test_memory_store_windows() {
+        let store = MemoryRateLimitStore::new();
+        let period = Duration::from_secs(60);
+
+        assert_eq!(store.hit("a", 5, period).await.unwrap(), (1, 0));
+        assert_eq!(store.hit("a", 5, period).await.unwrap(), (2, 0));
+        assert_eq!(store.hit("b", 5, period).await.unwrap(), (1, 0));
+
+        // the current window becomes the previous one
+        assert_eq!(store.hit("a", 6, period).await.unwrap(), (1, 2));
+        assert_eq!(store.hit("a", 6, period).await.unwrap(), (2, 2));
+
+        // a skipped window leaves nothing in the previous one
+        assert_eq!(store.hit("a", 8, period).await.unwrap(), (1, 0));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_memory_store_mixed_periods() {
+        let store = MemoryRateLimitStore::new();
+        let hour = Duration::from_secs(60 * 60);
+        let second = Duration::from_secs(1);
+
+        // a new key with a far larger window number does not prune the hourly counter
+        assert_eq!(store.hit("login", 470_000, hour).await.unwrap(), (1, 0));
+        assert_eq!(
+            store.hit("api:1", 1_700_000_000, second).await.unwrap(),
+            (1, 0)
+        );
+        assert_eq!(
+            store.hit("api:2", 1_700_000_000, second).await.unwrap(),
+            (1, 0)
+        );
+        assert_eq!(store.hit("login", 470_000, hour).await.unwrap(), (2, 0));
+
+        // the per second counters are pruned once they no longer matter
+        tokio::time::advance(Duration::from_secs(3)).await;
+        assert_eq!(
+            store.hit("api:3", 1_700_000_003, second).await.unwrap(),
+            (1, 0)
+        );
+        let keys = {
+            let counters = store.counters.lock().unwrap();
+            let mut keys: Vec<String> = counters.keys().cloned().collect();
+            keys.sort();
+            keys
+        };
+        assert_eq!(keys, vec!["api:3", "login"]);
+
+        // and the hourly one two hours after its last request
+        tokio::time::advance(hour * 2).await;
+        assert_eq!(
+            store.hit("api:4", 1_700_007_203, second).await.unwrap(),
+            (1, 0)
+        );
+        assert_eq!(store.counters.lock().unwrap().len(), 1);
+    }
+}
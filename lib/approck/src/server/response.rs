@@ -50,16 +50,36 @@ impl salvo_core::Scribe for Bytes {
 pub struct Stream {
     /// NOTE: This field is intentonally private to hide the implementation details of
     /// [`BytesContent::Stream`].
-    content: sync_wrapper::SyncWrapper<
-        futures::stream::BoxStream<
-            'static,
-            std::result::Result<salvo_core::http::body::BytesFrame, salvo_core::BoxedError>,
-        >,
-    >,
+    content: StreamContent,
     pub status: http::StatusCode,
     pub headers: http::HeaderMap,
 }
 
+type FrameStream = sync_wrapper::SyncWrapper<
+    futures::stream::BoxStream<
+        'static,
+        std::result::Result<salvo_core::http::body::BytesFrame, salvo_core::BoxedError>,
+    >,
+>;
+
+/// Opens the part of a seekable source covering the given byte range
+pub(crate) type OpenRange = Box<
+    dyn Fn(
+            std::ops::Range<u64>,
+        ) -> futures::stream::BoxStream<'static, granite::Result<bytes::Bytes>>
+        + Send
+        + Sync,
+>;
+
+enum StreamContent {
+    Frames(FrameStream),
+    /// Can start anywhere, so the server can answer `Range` requests.  See [`super::range`].
+    Seekable {
+        length: u64,
+        open: OpenRange,
+    },
+}
+
 impl Stream {
     pub fn new<S>(stream: S) -> Self
     where
@@ -75,13 +95,78 @@ impl Stream {
                 })
                 .map_err::<salvo_core::BoxedError, _>(|error| Box::new(error.into_std())),
         );
-        let content = sync_wrapper::SyncWrapper::new(mapped);
+        let content = StreamContent::Frames(sync_wrapper::SyncWrapper::new(mapped));
         Self {
             content,
             status: http::StatusCode::default(),
             headers: http::HeaderMap::default(),
         }
     }
+
+    /// A stream of `length` bytes which `open` can produce starting at any offset, so clients
+    /// can resume downloads and seek with `Range` requests.  `open` is called with the range of
+    /// bytes wanted (once per range) and must yield exactly those.
+    pub fn seekable<F, S, B>(length: u64, open: F) -> Self
+    where
+        F: Fn(std::ops::Range<u64>) -> S + Send + Sync + 'static,
+        S: futures::Stream<Item = granite::Result<B>> + Send + 'static,
+        B: Into<bytes::Bytes> + 'static,
+    {
+        let open: OpenRange = Box::new(move |range| Box::pin(open(range).map_ok(Into::into)));
+        Self {
+            content: StreamContent::Seekable { length, open },
+            status: http::StatusCode::default(),
+            headers: http::HeaderMap::default(),
+        }
+    }
+
+    /// Stream the file at `path`, with `Range` support
+    pub async fn file(path: impl Into<std::path::PathBuf>) -> granite::Result<Self> {
+        let path = path.into();
+        let length = tokio::fs::metadata(&path).await?.len();
+
+        Ok(Self::seekable(length, move |range| {
+            let path = path.clone();
+            futures::stream::once(async move {
+                use tokio::io::{AsyncReadExt, AsyncSeekExt};
+
+                let mut file = tokio::fs::File::open(&path).await?;
+                file.seek(std::io::SeekFrom::Start(range.start)).await?;
+                let reader = file.take(range.end - range.start);
+                Ok::<_, granite::Error>(
+                    tokio_util::io::ReaderStream::new(reader).map_err(granite::Error::from),
+                )
+            })
+            .try_flatten()
+        }))
+    }
+
+    /// The total length and opener, if this stream is seekable
+    pub(crate) fn seekable_parts(&self) -> Option<(u64, &OpenRange)> {
+        match &self.content {
+            StreamContent::Seekable { length, open } => Some((*length, open)),
+            StreamContent::Frames(_) => None,
+        }
+    }
+
+    /// Replace the content with `stream`, e.g. the part of a seekable source that was asked for
+    pub(crate) fn set_content(
+        &mut self,
+        stream: futures::stream::BoxStream<'static, granite::Result<bytes::Bytes>>,
+    ) {
+        self.content = StreamContent::Frames(frames(stream));
+    }
+}
+
+fn frames(
+    stream: futures::stream::BoxStream<'static, granite::Result<bytes::Bytes>>,
+) -> FrameStream {
+    let mapped: futures::stream::BoxStream<_> = Box::pin(
+        stream
+            .map_ok(salvo_core::http::body::BytesFrame::from)
+            .map_err::<salvo_core::BoxedError, _>(|error| Box::new(error.into_std())),
+    );
+    sync_wrapper::SyncWrapper::new(mapped)
 }
 
 impl<S, F> From<S> for Stream
@@ -98,9 +183,181 @@ impl salvo_core::Scribe for Stream {
     fn render(self, res: &mut salvo_core::prelude::Response) {
         res.headers
             .typed_insert(headers::ContentType::from(mime::APPLICATION_OCTET_STREAM));
+        let content = match self.content {
+            StreamContent::Frames(content) => content,
+            // nobody asked for a range, so send all of it
+            StreamContent::Seekable { length, open } => {
+                res.headers.typed_insert(headers::ContentLength(length));
+                frames(open(0..length))
+            }
+        };
+        res.headers.extend(self.headers);
+        res.status_code = Some(self.status);
+        res.body(salvo_core::http::ResBody::Stream(content));
+    }
+}
+
+/// One event of a [`SSE`] stream.  Only `data` is required; a browser `EventSource` dispatches
+/// it as a `message` event unless [`event`](Self::event) names another type.
+#[derive(Debug, Default, Clone)]
+pub struct SseEvent {
+    id: Option<String>,
+    event: Option<String>,
+    data: String,
+    retry: Option<std::time::Duration>,
+}
+
+impl SseEvent {
+    pub fn new(data: impl Into<String>) -> Self {
+        Self {
+            data: data.into(),
+            ..Self::default()
+        }
+    }
+
+    /// An event with `value` serialized as JSON for its data
+    pub fn json<T: serde::Serialize>(value: &T) -> granite::Result<Self> {
+        Ok(Self::new(serde_json::to_string(value)?))
+    }
+
+    /// Sent back by the client as `Last-Event-ID` when it reconnects, see
+    /// [`Request::last_event_id`](crate::server::Request::last_event_id)
+    pub fn id(mut self, id: impl Into<String>) -> Self {
+        self.id = Some(id.into());
+        self
+    }
+
+    /// The event type, for `addEventListener` on the client
+    pub fn event(mut self, event: impl Into<String>) -> Self {
+        self.event = Some(event.into());
+        self
+    }
+
+    /// How long the client should wait before reconnecting if the stream is lost
+    pub fn retry(mut self, retry: std::time::Duration) -> Self {
+        self.retry = Some(retry);
+        self
+    }
+
+    /// The event in the `text/event-stream` format.  Line breaks in `data` become several `data`
+    /// lines; line breaks in `id` and `event` would end the field early, so they are dropped.
+    fn encode(&self) -> bytes::Bytes {
+        let mut out = String::new();
+        if let Some(event) = &self.event {
+            out.push_str("event: ");
+            out.extend(event.chars().filter(|c| *c != '\r' && *c != '\n'));
+            out.push('\n');
+        }
+        if let Some(id) = &self.id {
+            out.push_str("id: ");
+            out.extend(
+                id.chars()
+                    .filter(|c| *c != '\r' && *c != '\n' && *c != '\0'),
+            );
+            out.push('\n');
+        }
+        if let Some(retry) = self.retry {
+            out.push_str(&format!("retry: {}\n", retry.as_millis()));
+        }
+        // `\r\n`, `\r` and `\n` all end a line in the format
+        for line in self.data.replace("\r\n", "\n").split(['\r', '\n']) {
+            out.push_str("data: ");
+            out.push_str(line);
+            out.push('\n');
+        }
+        out.push('\n');
+        out.into()
+    }
+}
+
+/// A `text/event-stream` of [`SseEvent`]s, for a browser `EventSource`.  A comment is sent
+/// whenever the stream has been quiet for the keep-alive interval, so proxies do not close the
+/// connection.  Ending the stream ends the response; yielding an error aborts it.
+pub struct SSE {
+    /// NOTE: private for the same reason as on [`Stream`].
+    events:
+        sync_wrapper::SyncWrapper<futures::stream::BoxStream<'static, granite::Result<SseEvent>>>,
+    keep_alive: Option<std::time::Duration>,
+    pub status: http::StatusCode,
+    pub headers: http::HeaderMap,
+}
+
+impl SSE {
+    pub const DEFAULT_KEEP_ALIVE: std::time::Duration = std::time::Duration::from_secs(15);
+
+    pub fn new<S>(events: S) -> Self
+    where
+        S: futures::Stream<Item = granite::Result<SseEvent>> + Send + 'static,
+    {
+        Self {
+            events: sync_wrapper::SyncWrapper::new(Box::pin(events)),
+            keep_alive: Some(Self::DEFAULT_KEEP_ALIVE),
+            status: http::StatusCode::default(),
+            headers: http::HeaderMap::default(),
+        }
+    }
+
+    /// Change how long the stream may be quiet before a keep-alive comment is sent, or turn
+    /// keep-alive comments off with `None`
+    pub fn keep_alive(mut self, keep_alive: Option<std::time::Duration>) -> Self {
+        self.keep_alive = keep_alive;
+        self
+    }
+}
+
+impl<S> From<S> for SSE
+where
+    S: futures::Stream<Item = granite::Result<SseEvent>> + Send + 'static,
+{
+    fn from(events: S) -> Self {
+        Self::new(events)
+    }
+}
+
+impl salvo_core::Scribe for SSE {
+    fn render(self, res: &mut salvo_core::prelude::Response) {
+        use futures::StreamExt;
+
+        enum Next {
+            Event(Option<granite::Result<SseEvent>>),
+            KeepAlive,
+        }
+
+        let keep_alive = self.keep_alive;
+        let content =
+            futures::stream::unfold(self.events.into_inner(), move |mut events| async move {
+                // `next` is cancel safe, so an event is never lost to the keep-alive timer
+                let next = match keep_alive {
+                    Some(keep_alive) => tokio::select! {
+                        event = events.next() => Next::Event(event),
+                        _ = tokio::time::sleep(keep_alive) => Next::KeepAlive,
+                    },
+                    None => Next::Event(events.next().await),
+                };
+                match next {
+                    Next::Event(Some(Ok(event))) => Some((Ok(event.encode()), events)),
+                    Next::Event(Some(Err(error))) => Some((Err(error), events)),
+                    Next::Event(None) => None,
+                    Next::KeepAlive => {
+                        Some((Ok(bytes::Bytes::from_static(b": keep-alive\n\n")), events))
+                    }
+                }
+            });
+
+        res.headers.insert(
+            http::header::CONTENT_TYPE,
+            http::HeaderValue::from_static("text/event-stream"),
+        );
+        res.headers.insert(
+            http::header::CACHE_CONTROL,
+            http::HeaderValue::from_static("no-cache"),
+        );
+        // nginx buffers responses by default, which would hold events back
+        res.headers
+            .insert("x-accel-buffering", http::HeaderValue::from_static("no"));
         res.headers.extend(self.headers);
         res.status_code = Some(self.status);
-        res.body(salvo_core::http::ResBody::Stream(self.content));
+        res.body(salvo_core::http::ResBody::Stream(frames(content.boxed())));
     }
 }
 
@@ -359,10 +616,62 @@ impl salvo_core::Scribe for NotFound {
     }
 }
 
+/// Returned by the router when a path exists but no handler accepts the request method.
+pub struct MethodNotAllowed {
+    pub allow: Vec<http::Method>,
+    pub headers: http::HeaderMap,
+}
+
+impl MethodNotAllowed {
+    pub fn new(allow: Vec<http::Method>) -> Self {
+        Self {
+            allow,
+            headers: http::HeaderMap::new(),
+        }
+    }
+}
+
+impl salvo_core::Scribe for MethodNotAllowed {
+    fn render(self, res: &mut salvo_core::prelude::Response) {
+        res.headers
+            .typed_insert(headers::ContentType::from(mime::TEXT_PLAIN_UTF_8));
+        res.headers
+            .typed_insert(self.allow.into_iter().collect::<headers::Allow>());
+        res.headers.extend(self.headers);
+        res.status_code = Some(http::StatusCode::METHOD_NOT_ALLOWED);
+        res.body("Method Not Allowed".into());
+    }
+}
+
+/// Answer to an OPTIONS request, listing the methods the path accepts.
+pub struct Options {
+    pub allow: Vec<http::Method>,
+    pub headers: http::HeaderMap,
+}
+
+impl Options {
+    pub fn new(allow: Vec<http::Method>) -> Self {
+        Self {
+            allow,
+            headers: http::HeaderMap::new(),
+        }
+    }
+}
+
+impl salvo_core::Scribe for Options {
+    fn render(self, res: &mut salvo_core::prelude::Response) {
+        res.headers
+            .typed_insert(self.allow.into_iter().collect::<headers::Allow>());
+        res.headers.extend(self.headers);
+        res.status_code = Some(http::StatusCode::NO_CONTENT);
+    }
+}
+
 #[derive(Debug)]
 pub struct Redirect {
     pub location: String,
     pub status: http::StatusCode,
+    pub headers: http::HeaderMap,
 }
 
 impl Redirect {
@@ -370,6 +679,7 @@ impl Redirect {
         Self {
             location,
             status: http::StatusCode::SEE_OTHER,
+            headers: http::HeaderMap::new(),
         }
     }
 
@@ -377,6 +687,7 @@ impl Redirect {
         Self {
             location,
             status: http::StatusCode::TEMPORARY_REDIRECT,
+            headers: http::HeaderMap::new(),
         }
     }
 
@@ -384,12 +695,14 @@ impl Redirect {
         Self {
             location,
             status: http::StatusCode::PERMANENT_REDIRECT,
+            headers: http::HeaderMap::new(),
         }
     }
 }
 
 impl salvo_core::Scribe for Redirect {
     fn render(self, res: &mut salvo_core::prelude::Response) {
+        res.headers.extend(self.headers);
         res.headers.insert(
             http::header::LOCATION,
             http::header::HeaderValue::from_str(&self.location).unwrap(),
@@ -415,6 +728,7 @@ impl salvo_core::Scribe for WebSocketUpgrade {
 pub enum Response {
     Bytes(Bytes),
     Stream(Stream),
+    SSE(SSE),
     Text(Text),
     Empty(Empty),
     HTML(HTML),
@@ -423,6 +737,8 @@ pub enum Response {
     JSON(JSON),
     SVG(SVG),
     NotFound(NotFound),
+    MethodNotAllowed(MethodNotAllowed),
+    Options(Options),
     Redirect(Redirect),
     WebSocketUpgrade(WebSocketUpgrade),
 }
@@ -432,6 +748,7 @@ impl salvo_core::Scribe for Response {
         match self {
             Response::Bytes(bytes) => res.render(bytes),
             Response::Stream(stream) => res.render(stream),
+            Response::SSE(sse) => res.render(sse),
             Response::Text(text) => res.render(text),
             Response::Empty(empty) => res.render(empty),
             Response::HTML(html) => res.render(html),
@@ -440,8 +757,180 @@ impl salvo_core::Scribe for Response {
             Response::JSON(json) => res.render(json),
             Response::SVG(svg) => res.render(svg),
             Response::NotFound(not_found) => res.render(not_found),
+            Response::MethodNotAllowed(method_not_allowed) => res.render(method_not_allowed),
+            Response::Options(options) => res.render(options),
             Response::Redirect(redirect) => res.render(redirect),
             Response::WebSocketUpgrade(websocket_upgrade) => res.render(websocket_upgrade),
         }
     }
 }
+
+/// Access to the headers of any response, which the cookie methods build on
+pub(crate) trait HeadersMut {
+    fn headers_mut(&mut self) -> &mut http::HeaderMap;
+}
+
+macro_rules! impl_headers_mut {
+    ($($type:ident),*) => {
+        $(
+            impl HeadersMut for $type {
+                fn headers_mut(&mut self) -> &mut http::HeaderMap {
+                    &mut self.headers
+                }
+            }
+        )*
+    };
+}
+
+impl_headers_mut!(
+    Bytes,
+    Stream,
+    SSE,
+    Text,
+    Empty,
+    HTML,
+    JavaScript,
+    CSS,
+    JSON,
+    SVG,
+    MethodNotAllowed,
+    Options,
+    Redirect
+);
+
+impl HeadersMut for WebSocketUpgrade {
+    fn headers_mut(&mut self) -> &mut http::HeaderMap {
+        &mut self.0.headers
+    }
+}
+
+impl HeadersMut for Response {
+    fn headers_mut(&mut self) -> &mut http::HeaderMap {
+        match self {
+            Response::Bytes(bytes) => bytes.headers_mut(),
+            Response::Stream(stream) => stream.headers_mut(),
+            Response::SSE(sse) => sse.headers_mut(),
+            Response::Text(text) => text.headers_mut(),
+            Response::Empty(empty) => empty.headers_mut(),
+            Response::HTML(html) => html.headers_mut(),
+            Response::JavaScript(javascript) => javascript.headers_mut(),
+            Response::CSS(css) => css.headers_mut(),
+            Response::JSON(json) => json.headers_mut(),
+            Response::SVG(svg) => svg.headers_mut(),
+            // `NotFound` has nowhere to keep headers, so it becomes the `Text` it renders as
+            Response::NotFound(_) => {
+                *self = Response::Text(Text {
+                    content: "Not Found".to_string(),
+                    status: http::StatusCode::NOT_FOUND,
+                    headers: http::HeaderMap::new(),
+                });
+                self.headers_mut()
+            }
+            Response::MethodNotAllowed(method_not_allowed) => method_not_allowed.headers_mut(),
+            Response::Options(options) => options.headers_mut(),
+            Response::Redirect(redirect) => redirect.headers_mut(),
+            Response::WebSocketUpgrade(websocket_upgrade) => websocket_upgrade.headers_mut(),
+        }
+    }
+}
+
+macro_rules! impl_cookies {
+    ($($type:ident),*) => {
+        $(
+            impl $type {
+                /// Add a `Set-Cookie` header.  See [`Request::new_cookie`] for one which follows the
+                /// configured cookie policy.
+                ///
+                /// [`Request::new_cookie`]: crate::server::Request::new_cookie
+                pub fn add_cookie(&mut self, cookie: cookie::Cookie<'_>) {
+                    crate::server::cookies::append(self.headers_mut(), &cookie);
+                }
+
+                /// Add a cookie the client can read but not change, see
+                /// [`Request::signed_cookie`](crate::server::Request::signed_cookie)
+                pub fn add_signed_cookie(&mut self, key: &cookie::Key, cookie: cookie::Cookie<'static>) {
+                    self.add_cookie(crate::server::cookies::sign(key, cookie));
+                }
+
+                /// Add a cookie the client can neither read nor change, see
+                /// [`Request::private_cookie`](crate::server::Request::private_cookie)
+                pub fn add_private_cookie(&mut self, key: &cookie::Key, cookie: cookie::Cookie<'static>) {
+                    self.add_cookie(crate::server::cookies::encrypt(key, cookie));
+                }
+
+                /// Tell the client to delete `cookie`, which must have the same path and domain it
+                /// was set with
+                pub fn remove_cookie(&mut self, mut cookie: cookie::Cookie<'static>) {
+                    cookie.make_removal();
+                    self.add_cookie(cookie);
+                }
+            }
+        )*
+    };
+}
+
+impl_cookies!(
+    Bytes,
+    Stream,
+    SSE,
+    Text,
+    Empty,
+    HTML,
+    JavaScript,
+    CSS,
+    JSON,
+    SVG,
+    MethodNotAllowed,
+    Options,
+    Redirect,
+    WebSocketUpgrade,
+    Response
+);
+
+#[cfg(test)]
+mod tests {
+    use super::SseEvent;
+
+    fn encode(event: SseEvent) -> String {
+        String::from_utf8(event.encode().to_vec()).unwrap()
+    }
+
+    #[test]
+    fn test_sse_data() {
+        assert_eq!(encode(SseEvent::new("hello")), "data: hello\n\n");
+        assert_eq!(encode(SseEvent::new("")), "data: \n\n");
+    }
+
+    #[test]
+    fn test_sse_multi_line_data() {
+        assert_eq!(
+            encode(SseEvent::new("one\ntwo\r\nthree\rfour")),
+            "data: one\ndata: two\ndata: three\ndata: four\n\n"
+        );
+
+        // a trailing line break is an empty last line, which the client joins back on
+        assert_eq!(encode(SseEvent::new("one\n")), "data: one\ndata: \n\n");
+        assert_eq!(
+            encode(SseEvent::new("one\n\nthree")),
+            "data: one\ndata: \ndata: three\n\n"
+        );
+    }
+
+    #[test]
+    fn test_sse_fields() {
+        let event = SseEvent::new("{}")
+            .event("update")
+            .id("42")
+            .retry(std::time::Duration::from_secs(3));
+        assert_eq!(
+            encode(event),
+            "event: update\nid: 42\nretry: 3000\ndata: {}\n\n"
+        );
+    }
+
+    #[test]
+    fn test_sse_fields_drop_line_breaks() {
+        let event = SseEvent::new("x").event("up\ndate").id("4\r\n2\0");
+        assert_eq!(encode(event), "event: update\nid: 42\ndata: x\n\n");
+    }
+}
@@ -0,0 +1,109 @@
+//! Security headers sent with every response: `Content-Security-Policy`,
+//! `Strict-Transport-Security`, `X-Content-Type-Options`, `Referrer-Policy` and
+//! `Permissions-Policy`.
+//!
+//! Each request gets a fresh CSP nonce, which replaces `{nonce}` in the policy.  `Document`
+//! implementations put it on the inline `<script>` and `<style>` elements they render (see
+//! [`csp_nonce`]), so the policy can refuse every other inline script.
+//!
+//! `[webserver.security_headers]` sets the value of each header, and an empty value leaves that
+//! header out.  A route which sets one of these headers itself keeps its own value.
+//!
+//! `Content-Security-Policy` and `Strict-Transport-Security` depend on what the app loads and
+//! where it is deployed, so they are empty (off) until configured, e.g.
+//!
+//! ```toml
+//! [webserver.security_headers]
+//! content_security_policy = "default-src 'self'; script-src 'self' 'nonce-{nonce}'"
+//! strict_transport_security = "max-age=31536000"
+//! ```
+
+/// The `[webserver.security_headers]` section of the config
+#[derive(Debug, serde::Deserialize)]
+#[serde(default)]
+pub struct SecurityHeadersConfig {
+    /// `Content-Security-Policy`, with `{nonce}` replaced by the request's nonce.  Off by default.
+    pub content_security_policy: String,
+
+    /// `Strict-Transport-Security`, only sent when `tls` is on.  Off by default.
+    pub strict_transport_security: String,
+
+    /// `X-Content-Type-Options`
+    pub content_type_options: String,
+
+    /// `Referrer-Policy`
+    pub referrer_policy: String,
+
+    /// `Permissions-Policy`
+    pub permissions_policy: String,
+}
+
+impl Default for SecurityHeadersConfig {
+    fn default() -> Self {
+        Self {
+            content_security_policy: String::new(),
+            strict_transport_security: String::new(),
+            content_type_options: "nosniff".to_string(),
+            referrer_policy: "strict-origin-when-cross-origin".to_string(),
+            permissions_policy: "camera=(), microphone=(), geolocation=()".to_string(),
+        }
+    }
+}
+
+tokio::task_local! {
+    static CSP_NONCE: String;
+}
+
+/// A new nonce for a request
+pub(crate) fn new_nonce() -> String {
+    granite::random_hex(32)
+}
+
+/// Run `future` (the route) with `nonce` available from [`csp_nonce`]
+pub(crate) async fn scope<F: std::future::Future>(nonce: String, future: F) -> F::Output {
+    CSP_NONCE.scope(nonce, future).await
+}
+
+/// The CSP nonce of the request being handled, to render as `nonce="..."` on inline `<script>`
+/// and `<style>` elements.  `None` outside of a request.
+pub fn csp_nonce() -> Option<String> {
+    CSP_NONCE.try_with(Clone::clone).ok()
+}
+
+/// Add the configured headers which `response` does not already have
+pub(crate) fn apply(
+    config: &SecurityHeadersConfig,
+    tls: bool,
+    nonce: &str,
+    response: &mut salvo_core::Response,
+) {
+    let csp = config.content_security_policy.replace("{nonce}", nonce);
+    let hsts = match tls {
+        true => config.strict_transport_security.as_str(),
+        false => "",
+    };
+
+    for (name, value) in [
+        (http::header::CONTENT_SECURITY_POLICY, csp.as_str()),
+        (http::header::STRICT_TRANSPORT_SECURITY, hsts),
+        (
+            http::header::X_CONTENT_TYPE_OPTIONS,
+            config.content_type_options.as_str(),
+        ),
+        (
+            http::header::REFERRER_POLICY,
+            config.referrer_policy.as_str(),
+        ),
+        (
+            http::header::HeaderName::from_static("permissions-policy"),
+            config.permissions_policy.as_str(),
+        ),
+    ] {
+        if value.is_empty() {
+            continue;
+        }
+        if let Ok(value) = http::HeaderValue::from_str(value) {
+            response.headers.entry(name).or_insert(value);
+        }
+    }
+}
@@ -0,0 +1,429 @@
+//! Server-side sessions, keyed by the `SessionToken` cookie.
+//!
+//! A [`Session`] is handed to every request (see [`Request::session`]), but nothing is read from
+//! the store until it is first used.  Changes are written back once the route returns, before the
+//! response is sent.  Entries expire `webserver.session_ttl` seconds after the last request that
+//! used them.
+//!
+//! [`Request::session`]: super::Request::session
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::time::Instant;
+
+/// The values stored in a session, by key
+pub type SessionData = HashMap<String, serde_json::Value>;
+
+/// Where session data lives between requests
+#[async_trait::async_trait]
+pub trait SessionStore: Send + Sync {
+    /// The data stored under `token`, or `None` if there is none or it expired
+    async fn load(&self, token: &str) -> granite::Result<Option<SessionData>>;
+
+    /// Store `data` under `token` for `ttl`, replacing whatever was there
+    async fn save(&self, token: &str, data: &SessionData, ttl: Duration) -> granite::Result<()>;
+
+    /// Push the expiry of `token` out to `ttl` from now
+    async fn touch(&self, token: &str, ttl: Duration) -> granite::Result<()>;
+
+    async fn delete(&self, token: &str) -> granite::Result<()>;
+}
+
+/// A handle to the current request's session.  Clones share the same data.
+#[derive(Clone)]
+pub struct Session {
+    inner: Arc<tokio::sync::Mutex<SessionInner>>,
+}
+
+// the data sits behind an async lock and the token is a credential, so neither is shown
+impl std::fmt::Debug for Session {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Session").finish_non_exhaustive()
+    }
+}
+
+struct SessionInner {
+    store: &'static dyn SessionStore,
+    token: String,
+    ttl: Duration,
+    /// None until the first access
+    data: Option<SessionData>,
+    changed: bool,
+    /// Tokens which were rotated away from and must be deleted
+    retired_tokens: Vec<String>,
+}
+
+impl Session {
+    pub(crate) fn new(store: &'static dyn SessionStore, token: String, ttl: Duration) -> Self {
+        Self {
+            inner: Arc::new(tokio::sync::Mutex::new(SessionInner {
+                store,
+                token,
+                ttl,
+                data: None,
+                changed: false,
+                retired_tokens: Vec::new(),
+            })),
+        }
+    }
+
+    /// The value stored under `key`, or `None` if there is none
+    pub async fn get<T>(&self, key: &str) -> granite::Result<Option<T>>
+    where
+        T: serde::de::DeserializeOwned,
+    {
+        let mut inner = self.inner.lock().await;
+        match inner.data().await?.get(key) {
+            Some(value) => Ok(Some(serde_json::from_value(value.clone())?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Store `value` under `key`
+    pub async fn set<T>(&self, key: &str, value: &T) -> granite::Result<()>
+    where
+        T: serde::Serialize,
+    {
+        let value = serde_json::to_value(value)?;
+        let mut inner = self.inner.lock().await;
+        inner.data().await?.insert(key.to_string(), value);
+        inner.changed = true;
+        Ok(())
+    }
+
+    /// Remove `key`, returning true if it was there
+    pub async fn remove(&self, key: &str) -> granite::Result<bool> {
+        let mut inner = self.inner.lock().await;
+        let removed = inner.data().await?.remove(key).is_some();
+        inner.changed |= removed;
+        Ok(removed)
+    }
+
+    /// Move the data to a new token, and send that to the client instead.  Call this whenever the
+    /// privilege level changes (e.g. on login) so a token planted before then is useless.
+    pub async fn rotate(&self) -> granite::Result<()> {
+        let mut inner = self.inner.lock().await;
+        inner.data().await?;
+        let old_token = std::mem::replace(&mut inner.token, granite::ts_random_hex(64));
+        inner.retired_tokens.push(old_token);
+        inner.changed = true;
+        Ok(())
+    }
+
+    /// Remove all data and rotate the token, e.g. on logout
+    pub async fn clear(&self) -> granite::Result<()> {
+        {
+            let mut inner = self.inner.lock().await;
+            inner.data = Some(SessionData::new());
+        }
+        self.rotate().await
+    }
+
+    /// Write any changes back to the store, returning the token to send to the client
+    pub(crate) async fn commit(&self) -> granite::Result<String> {
+        let mut inner = self.inner.lock().await;
+        let store = inner.store;
+
+        for token in std::mem::take(&mut inner.retired_tokens) {
+            store.delete(&token).await?;
+        }
+
+        match (&inner.data, inner.changed) {
+            // never used, so there is nothing to keep alive
+            (None, _) => {}
+            (Some(data), true) if data.is_empty() => store.delete(&inner.token).await?,
+            (Some(data), true) => store.save(&inner.token, data, inner.ttl).await?,
+            (Some(_), false) => store.touch(&inner.token, inner.ttl).await?,
+        }
+        inner.changed = false;
+
+        Ok(inner.token.clone())
+    }
+}
+
+impl SessionInner {
+    async fn data(&mut self) -> granite::Result<&mut SessionData> {
+        if self.data.is_none() {
+            self.data = Some(self.store.load(&self.token).await?.unwrap_or_default());
+        }
+        Ok(self.data.get_or_insert_with(SessionData::new))
+    }
+}
+
+/// Keeps sessions in the memory of this process.  They are lost on restart and not shared between
+/// instances, so this is meant for development and tests.
+#[derive(Default)]
+pub struct MemorySessionStore {
+    sessions: std::sync::Mutex<HashMap<String, (Instant, SessionData)>>,
+}
+
+impl MemorySessionStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[async_trait::async_trait]
+impl SessionStore for MemorySessionStore {
+    async fn load(&self, token: &str) -> granite::Result<Option<SessionData>> {
+        let sessions = self.sessions.lock().unwrap();
+        Ok(match sessions.get(token) {
+            Some((expires, data)) if *expires > Instant::now() => Some(data.clone()),
+            _ => None,
+        })
+    }
+
+    async fn save(&self, token: &str, data: &SessionData, ttl: Duration) -> granite::Result<()> {
+        let now = Instant::now();
+        let mut sessions = self.sessions.lock().unwrap();
+        sessions.retain(|_, (expires, _)| *expires > now);
+        sessions.insert(token.to_string(), (now + ttl, data.clone()));
+        Ok(())
+    }
+
+    async fn touch(&self, token: &str, ttl: Duration) -> granite::Result<()> {
+        if let Some((expires, _)) = self.sessions.lock().unwrap().get_mut(token) {
+            *expires = Instant::now() + ttl;
+        }
+        Ok(())
+    }
+
+    async fn delete(&self, token: &str) -> granite::Result<()> {
+        self.sessions.lock().unwrap().remove(token);
+        Ok(())
+    }
+}
+
+/// Keeps sessions in Redis as JSON under `session:<token>`, expired by Redis itself
+pub struct RedisSessionStore {
+    pool: granite_redis::RedisPool,
+}
+
+impl RedisSessionStore {
+    pub fn new(pool: granite_redis::RedisPool) -> Self {
+        Self { pool }
+    }
+
+    fn key(token: &str) -> String {
+        format!("session:{token}")
+    }
+}
+
+#[async_trait::async_trait]
+impl SessionStore for RedisSessionStore {
+    async fn load(&self, token: &str) -> granite::Result<Option<SessionData>> {
+        let mut redis_cx = self.pool.get().await?;
+        let value: Option<String> = redis_cx.get_val(&Self::key(token)).await?;
+        match value {
+            Some(value) => Ok(Some(serde_json::from_str(&value)?)),
+            None => Ok(None),
+        }
+    }
+
+    async fn save(&self, token: &str, data: &SessionData, ttl: Duration) -> granite::Result<()> {
+        let mut redis_cx = self.pool.get().await?;
+        redis_cx
+            .set_json_ex(&Self::key(token), data, ttl.as_secs())
+            .await
+    }
+
+    async fn touch(&self, token: &str, ttl: Duration) -> granite::Result<()> {
+        let mut redis_cx = self.pool.get().await?;
+        redis_cx.expire(&Self::key(token), ttl.as_secs()).await
+    }
+
+    async fn delete(&self, token: &str) -> granite::Result<()> {
+        let mut redis_cx = self.pool.get().await?;
+        redis_cx.del(&Self::key(token)).await
+    }
+}
+
+/// Used by [`Request`](super::Request)s which were not set up by the server, and by the server
+/// when the application does not provide a store
+pub(crate) fn default_store() -> &'static MemorySessionStore {
+    static STORE: std::sync::OnceLock<MemorySessionStore> = std::sync::OnceLock::new();
+    STORE.get_or_init(MemorySessionStore::new)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{MemorySessionStore, Session, SessionData, SessionStore};
+    use std::time::Duration;
+
+    const TTL: Duration = Duration::from_secs(60);
+
+    fn memory_store() -> &'static MemorySessionStore {
+        Box::leak(Box::new(MemorySessionStore::new()))
+    }
+
+    fn token() -> String {
+        granite::ts_random_hex(64)
+    }
+
+    /// Records which store methods are called, in order
+    #[derive(Default)]
+    struct RecordingStore {
+        store: MemorySessionStore,
+        calls: std::sync::Mutex<Vec<&'static str>>,
+    }
+
+    impl RecordingStore {
+        fn take_calls(&self) -> Vec<&'static str> {
+            std::mem::take(&mut *self.calls.lock().unwrap())
+        }
+    }
+
+    #[async_trait::async_trait]
+    impl SessionStore for RecordingStore {
+        async fn load(&self, token: &str) -> granite::Result<Option<SessionData>> {
+            self.calls.lock().unwrap().push("load");
+            self.store.load(token).await
+        }
+
+        async fn save(
+            &self,
+            token: &str,
+            data: &SessionData,
+            ttl: Duration,
+        ) -> granite::Result<()> {
+            self.calls.lock().unwrap().push("save");
+            self.store.save(token, data, ttl).await
+        }
+
+        async fn touch(&self, token: &str, ttl: Duration) -> granite::Result<()> {
+            self.calls.lock().unwrap().push("touch");
+            self.store.touch(token, ttl).await
+        }
+
+        async fn delete(&self, token: &str) -> granite::Result<()> {
+            self.calls.lock().unwrap().push("delete");
+            self.store.delete(token).await
+        }
+    }
+
+    #[derive(Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+    struct User {
+        id: i32,
+        name: String,
+    }
+
+    #[tokio::test]
+    async fn test_get_set_round_trip() {
+        let store = memory_store();
+        let user = User {
+            id: 1,
+            name: "Jane".to_string(),
+        };
+
+        let session = Session::new(store, token(), TTL);
+        session.set("user", &user).await.unwrap();
+        session.set("visits", &3).await.unwrap();
+        let token = session.commit().await.unwrap();
+
+        let session = Session::new(store, token, TTL);
+        assert_eq!(session.get::<User>("user").await.unwrap(), Some(user));
+        assert_eq!(session.get::<i32>("visits").await.unwrap(), Some(3));
+        assert_eq!(session.get::<i32>("missing").await.unwrap(), None);
+        assert!(session.get::<i32>("user").await.is_err());
+
+        assert!(session.remove("visits").await.unwrap());
+        assert!(!session.remove("visits").await.unwrap());
+        assert_eq!(session.get::<i32>("visits").await.unwrap(), None);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_expiry() {
+        let store = memory_store();
+
+        let session = Session::new(store, token(), TTL);
+        session.set("visits", &1).await.unwrap();
+        let token = session.commit().await.unwrap();
+
+        // each use pushes the expiry out again
+        tokio::time::advance(TTL - Duration::from_secs(1)).await;
+        let session = Session::new(store, token.clone(), TTL);
+        assert_eq!(session.get::<i32>("visits").await.unwrap(), Some(1));
+        session.commit().await.unwrap();
+
+        tokio::time::advance(TTL - Duration::from_secs(1)).await;
+        assert!(store.load(&token).await.unwrap().is_some());
+
+        tokio::time::advance(Duration::from_secs(2)).await;
+        assert!(store.load(&token).await.unwrap().is_none());
+        let session = Session::new(store, token, TTL);
+        assert_eq!(session.get::<i32>("visits").await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn test_rotate() {
+        let store = memory_store();
+
+        let session = Session::new(store, token(), TTL);
+        session.set("visits", &1).await.unwrap();
+        let old_token = session.commit().await.unwrap();
+
+        let session = Session::new(store, old_token.clone(), TTL);
+        session.rotate().await.unwrap();
+        let new_token = session.commit().await.unwrap();
+
+        assert_ne!(new_token, old_token);
+        assert_eq!(new_token.len(), 64);
+        assert!(store.load(&old_token).await.unwrap().is_none());
+
+        let session = Session::new(store, new_token, TTL);
+        assert_eq!(session.get::<i32>("visits").await.unwrap(), Some(1));
+    }
+
+    #[tokio::test]
+    async fn test_clear() {
+        let store = memory_store();
+
+        let session = Session::new(store, token(), TTL);
+        session.set("visits", &1).await.unwrap();
+        let old_token = session.commit().await.unwrap();
+
+        let session = Session::new(store, old_token.clone(), TTL);
+        session.clear().await.unwrap();
+        assert_eq!(session.get::<i32>("visits").await.unwrap(), None);
+        let new_token = session.commit().await.unwrap();
+
+        // nothing is left to store under either token
+        assert_ne!(new_token, old_token);
+        assert!(store.load(&old_token).await.unwrap().is_none());
+        assert!(store.load(&new_token).await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_commit_only_writes_changes() {
+        let store: &'static RecordingStore = Box::leak(Box::default());
+        let token = token();
+
+        // an unused session is never loaded or written
+        let session = Session::new(store, token.clone(), TTL);
+        assert_eq!(session.commit().await.unwrap(), token);
+        assert_eq!(store.take_calls(), Vec::<&str>::new());
+
+        let session = Session::new(store, token.clone(), TTL);
+        session.set("visits", &1).await.unwrap();
+        session.commit().await.unwrap();
+        assert_eq!(store.take_calls(), vec!["load", "save"]);
+
+        // committing again with nothing changed only keeps it alive
+        session.commit().await.unwrap();
+        assert_eq!(store.take_calls(), vec!["touch"]);
+
+        // reading alone does not write it back
+        let session = Session::new(store, token.clone(), TTL);
+        session.get::<i32>("visits").await.unwrap();
+        session.remove("missing").await.unwrap();
+        session.commit().await.unwrap();
+        assert_eq!(store.take_calls(), vec!["load", "touch"]);
+
+        // removing the last value deletes it
+        let session = Session::new(store, token, TTL);
+        session.remove("visits").await.unwrap();
+        session.commit().await.unwrap();
+        assert_eq!(store.take_calls(), vec!["load", "delete"]);
+    }
+}
@@ -0,0 +1,476 @@
+//! Run requests through an app in-process, for tests.
+//!
+//! A [`TestClient`] sends requests through the same handler as [`serve`](super::serve): the
+//! middleware, `webserver_route` (and so the generated router), the session store,
+//! `webserver_handle_error`, and the headers and cookies added to every response.  No socket is
+//! bound.  Like a browser, it keeps the cookies it is sent, so the requests of one client share a
+//! session.
+//!
+//! ```ignore
+//! let client = TestClient::new(app);
+//! let response = client.get("/user/1").send().await;
+//! assert_eq!(response.status(), StatusCode::OK);
+//! let user: User = response.json().await?;
+//!
+//! let mut socket = client.get("/example/websocket1").websocket().await?;
+//! socket.send("hello")?;
+//! let reply = socket.recv().await;
+//! ```
+//!
+//! The app's asset manifest and rate limit store are set by the first client, or by `serve`, and
+//! kept for the rest of the process.
+
+use super::websocket::{self, TestWebSocket};
+use super::{MyHandler, WebServerModule};
+use salvo_core::handler::Handler;
+use std::collections::BTreeMap;
+
+pub struct TestClient<APP: 'static> {
+    handler: MyHandler<APP>,
+    /// Cookies kept from responses, by name
+    cookies: std::sync::Mutex<BTreeMap<String, String>>,
+}
+
+impl<APP> TestClient<APP>
+where
+    APP: WebServerModule + Send + Sync + 'static,
+{
+    pub fn new(app: &'static APP) -> Self {
+        super::set_app_statics(app);
+        Self {
+            handler: MyHandler {
+                app,
+                quic_port: None,
+            },
+            cookies: Default::default(),
+        }
+    }
+
+    /// Start a request for `path`, which may include a query string
+    pub fn request(&self, method: http::Method, path: &str) -> TestRequest<'_, APP> {
+        TestRequest {
+            client: self,
+            method,
+            path: path.to_string(),
+            headers: http::HeaderMap::new(),
+            cookies: BTreeMap::new(),
+            body: bytes::Bytes::new(),
+        }
+    }
+
+    pub fn get(&self, path: &str) -> TestRequest<'_, APP> {
+        self.request(http::Method::GET, path)
+    }
+
+    pub fn post(&self, path: &str) -> TestRequest<'_, APP> {
+        self.request(http::Method::POST, path)
+    }
+
+    pub fn put(&self, path: &str) -> TestRequest<'_, APP> {
+        self.request(http::Method::PUT, path)
+    }
+
+    pub fn patch(&self, path: &str) -> TestRequest<'_, APP> {
+        self.request(http::Method::PATCH, path)
+    }
+
+    pub fn delete(&self, path: &str) -> TestRequest<'_, APP> {
+        self.request(http::Method::DELETE, path)
+    }
+
+    /// The value of a cookie this client was sent and still has
+    pub fn cookie(&self, name: &str) -> Option<String> {
+        self.cookies.lock().unwrap().get(name).cloned()
+    }
+
+    /// Forget every cookie, e.g. to start a new session
+    pub fn clear_cookies(&self) {
+        self.cookies.lock().unwrap().clear();
+    }
+}
+
+/// A request being built, sent with [`TestRequest::send`]
+pub struct TestRequest<'c, APP: 'static> {
+    client: &'c TestClient<APP>,
+    method: http::Method,
+    path: String,
+    headers: http::HeaderMap,
+    /// Sent along with the client's own cookies, replacing any of the same name
+    cookies: BTreeMap<String, String>,
+    body: bytes::Bytes,
+}
+
+impl<'c, APP> TestRequest<'c, APP>
+where
+    APP: WebServerModule + Send + Sync + 'static,
+{
+    /// Add `pairs` to the query string
+    pub fn query(mut self, pairs: &[(&str, &str)]) -> Self {
+        let query = url::form_urlencoded::Serializer::new(String::new())
+            .extend_pairs(pairs)
+            .finish();
+        let separator = match self.path.contains('?') {
+            true => '&',
+            false => '?',
+        };
+        self.path.push(separator);
+        self.path.push_str(&query);
+        self
+    }
+
+    /// Add a header.  Panics if `name` or `value` is not valid in a header.
+    pub fn header(mut self, name: &str, value: &str) -> Self {
+        let name = http::header::HeaderName::try_from(name).expect("invalid header name");
+        let value = http::HeaderValue::try_from(value).expect("invalid header value");
+        self.headers.append(name, value);
+        self
+    }
+
+    /// Send a cookie with this request only
+    pub fn cookie(mut self, name: &str, value: &str) -> Self {
+        self.cookies.insert(name.to_string(), value.to_string());
+        self
+    }
+
+    pub fn body(mut self, body: impl Into<bytes::Bytes>) -> Self {
+        self.body = body.into();
+        self
+    }
+
+    /// Send `form` url-encoded, as a browser submits a form.  `PostForm` handlers also need the
+    /// session's CSRF token, in the form or in an `X-CSRF-Token` header.
+    pub fn form<T: serde::Serialize>(self, form: &T) -> Self {
+        let body = serde_qs::to_string(form).expect("unable to encode the form");
+        self.header("content-type", "application/x-www-form-urlencoded")
+            .body(body)
+    }
+
+    pub fn json<T: serde::Serialize>(self, json: &T) -> Self {
+        let body = serde_json::to_vec(json).expect("unable to encode the json");
+        self.header("content-type", "application/json").body(body)
+    }
+
+    /// Run the request through the app
+    pub async fn send(self) -> TestResponse {
+        self.send_with(|_| {}).await
+    }
+
+    /// Ask to open a websocket, returning the client end of it once the route has accepted it.
+    /// The route's handler runs on its own task, as it does when serving.
+    pub async fn websocket(self) -> granite::Result<TestWebSocket> {
+        let (server, client) = websocket::test_pair();
+        let upgrade = websocket::TestUpgrade::new(server);
+
+        let response = self
+            .header("connection", "upgrade")
+            .header("upgrade", "websocket")
+            .header("sec-websocket-version", "13")
+            .header("sec-websocket-key", "dGhlIHNhbXBsZSBub25jZQ==")
+            .send_with(move |request| {
+                request.extensions_mut().insert(upgrade);
+            })
+            .await;
+
+        match response.status() {
+            http::StatusCode::SWITCHING_PROTOCOLS => Ok(client),
+            status => Err(granite::Error::new(granite::ErrorKind::WebsocketUpgrade)
+                .add_context(format!("the route answered {status} instead of upgrading"))),
+        }
+    }
+
+    async fn send_with(self, prepare: impl FnOnce(&mut salvo_core::Request)) -> TestResponse {
+        let mut cookies = self.client.cookies.lock().unwrap().clone();
+        cookies.extend(self.cookies);
+
+        let mut request = salvo_core::Request::new();
+        *request.method_mut() = self.method;
+        *request.uri_mut() = self.path.parse().expect("invalid path");
+        *request.headers_mut() = self.headers;
+        request.headers_mut().insert(
+            http::header::HOST,
+            http::HeaderValue::from_static("localhost"),
+        );
+        if !cookies.is_empty() {
+            let header = cookies
+                .iter()
+                .map(|(name, value)| format!("{name}={value}"))
+                .collect::<Vec<_>>()
+                .join("; ");
+            request.headers_mut().insert(
+                http::header::COOKIE,
+                http::HeaderValue::try_from(header).expect("invalid cookie"),
+            );
+        }
+        for (name, value) in cookies {
+            request
+                .cookies_mut()
+                .add_original(cookie::Cookie::new(name, value));
+        }
+        if !self.body.is_empty() {
+            request
+                .headers_mut()
+                .insert(http::header::CONTENT_LENGTH, self.body.len().into());
+        }
+        request.replace_body(self.body.into());
+        prepare(&mut request);
+
+        let mut response = salvo_core::Response::new();
+        let mut depot = salvo_core::Depot::new();
+        let mut flow_control = salvo_core::FlowCtrl::new(Vec::new());
+        self.client
+            .handler
+            .handle(&mut request, &mut depot, &mut response, &mut flow_control)
+            .await;
+
+        // salvo adds the cookies of its jar (e.g. the session's) to the headers as it writes the
+        // response, which has not happened here
+        let jar_cookies: Vec<cookie::Cookie<'static>> =
+            response.cookies().delta().cloned().collect();
+        for cookie in &jar_cookies {
+            super::cookies::append(&mut response.headers, cookie);
+        }
+
+        // keep the cookies the response sets and drop the ones it removes, as a browser would.
+        // Values are kept as they were sent, since a browser does not decode them either.
+        let set_cookies: Vec<cookie::Cookie<'static>> = response
+            .headers
+            .get_all(http::header::SET_COOKIE)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .filter_map(|value| cookie::Cookie::parse(value.to_owned()).ok())
+            .collect();
+        {
+            let mut client_cookies = self.client.cookies.lock().unwrap();
+            for cookie in &set_cookies {
+                match is_removal(cookie) {
+                    true => client_cookies.remove(cookie.name()),
+                    false => client_cookies.insert(cookie.name().into(), cookie.value().into()),
+                };
+            }
+        }
+
+        TestResponse {
+            status: response.status_code.unwrap_or(http::StatusCode::OK),
+            headers: std::mem::take(&mut response.headers),
+            cookies: set_cookies,
+            body: response.replace_body(salvo_core::http::ResBody::None),
+        }
+    }
+}
+
+/// Whether `cookie` tells the client to delete it: a `Max-Age` of zero or less, or (without a
+/// `Max-Age`) an `Expires` in the past
+fn is_removal(cookie: &cookie::Cookie<'_>) -> bool {
+    match cookie.max_age() {
+        Some(max_age) => max_age <= cookie::time::Duration::ZERO,
+        None => cookie
+            .expires_datetime()
+            .is_some_and(|expires| expires <= cookie::time::OffsetDateTime::now_utc()),
+    }
+}
+
+/// What the app answered.  The body is read by [`bytes`](Self::bytes), [`text`](Self::text) or
+/// [`json`](Self::json), which wait for the end of a streamed body.
+pub struct TestResponse {
+    status: http::StatusCode,
+    headers: http::HeaderMap,
+    cookies: Vec<cookie::Cookie<'static>>,
+    body: salvo_core::http::ResBody,
+}
+
+impl TestResponse {
+    pub fn status(&self) -> http::StatusCode {
+        self.status
+    }
+
+    pub fn headers(&self) -> &http::HeaderMap {
+        &self.headers
+    }
+
+    /// The first value of the header `name`, if it is there and is text
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers.get(name).and_then(|value| value.to_str().ok())
+    }
+
+    /// A cookie set (or removed) by this response, the last one if it was set more than once
+    pub fn cookie(&self, name: &str) -> Option<&cookie::Cookie<'static>> {
+        self.cookies.iter().rfind(|cookie| cookie.name() == name)
+    }
+
+    pub async fn bytes(self) -> granite::Result<bytes::Bytes> {
+        use http_body_util::BodyExt;
+
+        match self.body.collect().await {
+            Ok(collected) => Ok(collected.to_bytes()),
+            Err(error) => Err(granite::Error::new(granite::ErrorKind::Unexpected)
+                .add_context(format!("Unable to read response body: {error}"))),
+        }
+    }
+
+    pub async fn text(self) -> granite::Result<String> {
+        let bytes = self.bytes().await?;
+        Ok(String::from_utf8(bytes.to_vec())?)
+    }
+
+    pub async fn json<T: serde::de::DeserializeOwned>(self) -> granite::Result<T> {
+        let bytes = self.bytes().await?;
+        Ok(serde_json::from_slice(&bytes)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::TestClient;
+    use crate::server::response::{NotFound, Response, Text};
+    use crate::server::websocket::{Message, MessageData, WebSocket};
+    use crate::server::{Request, WebServerModule, WebServerSystem};
+
+    struct TestApp {
+        webserver_system: WebServerSystem,
+    }
+
+    impl WebServerModule for TestApp {
+        fn webserver_system(&self) -> &WebServerSystem {
+            &self.webserver_system
+        }
+
+        async fn webserver_route<'a>(
+            &'static self,
+            mut req: Request<'a>,
+        ) -> crate::server::response::Result {
+            let text = |content: &str| Response::Text(content.into());
+
+            let path = req.path().to_string();
+            Ok(match path.as_str() {
+                "/hello" => text("hello"),
+                "/cookie" => match req.cookie("flavor") {
+                    Some(cookie) => text(cookie.value()),
+                    None => text("none"),
+                },
+                "/cookie/set" => {
+                    let mut response = Text::from("set");
+                    response.add_cookie(cookie::Cookie::new("flavor", "oatmeal"));
+                    Response::Text(response)
+                }
+                "/cookie/remove" => {
+                    let mut response = Response::Text("removed".into());
+                    response.remove_cookie(cookie::Cookie::build("flavor").path("/").into());
+                    response
+                }
+                "/session" => text(&req.session_token()),
+                "/echo" => match req.upgrade_to_websocket(echo).await? {
+                    Some(upgrade) => Response::WebSocketUpgrade(upgrade),
+                    None => text("not a websocket"),
+                },
+                _ => Response::NotFound(NotFound),
+            })
+        }
+    }
+
+    async fn echo(mut socket: WebSocket) {
+        while let Some(Ok(message)) = socket.recv().await {
+            match message.into_data() {
+                MessageData::Text(text) => {
+                    if socket.send(format!("echo: {text}").into()).await.is_err() {
+                        break;
+                    }
+                }
+                MessageData::Close => break,
+                _ => {}
+            }
+        }
+    }
+
+    fn client() -> TestClient<TestApp> {
+        let config: crate::server::WebServerConfig =
+            serde_json::from_value(serde_json::json!({ "port": 0, "tls": false })).unwrap();
+        let app = Box::leak(Box::new(TestApp {
+            webserver_system: config.into_system(),
+        }));
+        TestClient::new(app)
+    }
+
+    #[tokio::test]
+    async fn test_request() {
+        let client = client();
+
+        let response = client.get("/hello").send().await;
+        assert_eq!(response.status(), http::StatusCode::OK);
+        assert_eq!(
+            response.header("content-type"),
+            Some("text/plain; charset=utf-8")
+        );
+        assert_eq!(response.text().await.unwrap(), "hello");
+
+        let response = client.get("/missing").send().await;
+        assert_eq!(response.status(), http::StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_cookie_round_trip() {
+        let client = client();
+        assert_eq!(
+            client.get("/cookie").send().await.text().await.unwrap(),
+            "none"
+        );
+
+        let response = client.get("/cookie/set").send().await;
+        assert_eq!(response.cookie("flavor").unwrap().value(), "oatmeal");
+        assert_eq!(client.cookie("flavor").as_deref(), Some("oatmeal"));
+        assert_eq!(
+            client.get("/cookie").send().await.text().await.unwrap(),
+            "oatmeal"
+        );
+
+        // a cookie sent with one request goes over the client's own
+        let response = client
+            .get("/cookie")
+            .cookie("flavor", "ginger")
+            .send()
+            .await;
+        assert_eq!(response.text().await.unwrap(), "ginger");
+
+        let response = client.get("/cookie/remove").send().await;
+        assert!(super::is_removal(response.cookie("flavor").unwrap()));
+        assert_eq!(client.cookie("flavor"), None);
+        assert_eq!(
+            client.get("/cookie").send().await.text().await.unwrap(),
+            "none"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_session_cookie() {
+        let client = client();
+
+        let session_token = client.get("/session").send().await.text().await.unwrap();
+        assert_eq!(client.cookie("SessionToken"), Some(session_token.clone()));
+
+        let response = client.get("/session").send().await;
+        assert_eq!(response.text().await.unwrap(), session_token);
+
+        client.clear_cookies();
+        let response = client.get("/session").send().await;
+        assert_ne!(response.text().await.unwrap(), session_token);
+    }
+
+    #[tokio::test]
+    async fn test_websocket_echo() {
+        let client = client();
+        let mut socket = client.get("/echo").websocket().await.unwrap();
+
+        for text in ["hello", "again"] {
+            socket.send(text).unwrap();
+            match socket.recv().await.map(|message| message.into_data()) {
+                Some(MessageData::Text(reply)) => assert_eq!(reply, format!("echo: {text}")),
+                _ => panic!("expected a text reply"),
+            }
+        }
+
+        // the handler returns on close, which ends the socket
+        socket.send(Message::close_with(1000u16, "done")).unwrap();
+        assert!(socket.recv().await.is_none());
+
+        assert!(client.get("/hello").websocket().await.is_err());
+    }
+}
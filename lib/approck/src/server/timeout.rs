@@ -0,0 +1,207 @@
+//! Request deadlines, from `webserver.timeout` and the `timeout` instruction of routes.
+//!
+//! The middleware and the route run against a deadline counted from the start of the request.
+//! If it passes first, they are cancelled (dropping whatever they were waiting on, such as a
+//! database query) and the request fails with [`ErrorKind::Timeout`], which
+//! [`error_status_code`] answers with `504 Gateway Timeout`.
+//!
+//! A route's `timeout = 2min;` or `timeout = off;` replaces the server wide timeout, even though
+//! the route only sets it once it is running.  Only producing the response is timed: a streamed
+//! body, or a websocket session after the upgrade, may go on for as long as it needs.
+//!
+//! [`ErrorKind::Timeout`]: granite::ErrorKind::Timeout
+//! [`error_status_code`]: super::error_status_code
+
+use std::time::Duration;
+use tokio::sync::watch;
+use tokio::time::Instant;
+
+/// When the request being answered has to be answered by, shared between the [`Request`] (which
+/// routes use to move it) and the server (which enforces it)
+///
+/// [`Request`]: super::Request
+#[derive(Debug)]
+pub(crate) struct Deadline {
+    start: Instant,
+    timeout: watch::Sender<Option<Duration>>,
+}
+
+impl Deadline {
+    pub(crate) fn new() -> Self {
+        Self {
+            start: Instant::now(),
+            timeout: watch::Sender::new(None),
+        }
+    }
+
+    pub(crate) fn timeout(&self) -> Option<Duration> {
+        *self.timeout.borrow()
+    }
+
+    pub(crate) fn set_timeout(&self, timeout: Option<Duration>) {
+        self.timeout.send_replace(timeout);
+    }
+
+    /// Run `future` until it finishes, or fail with [`ErrorKind::Timeout`] if the deadline passes
+    /// first
+    ///
+    /// [`ErrorKind::Timeout`]: granite::ErrorKind::Timeout
+    pub(crate) async fn run<T>(
+        &self,
+        future: impl std::future::Future<Output = granite::Result<T>>,
+    ) -> granite::Result<T> {
+        let mut timeout = self.timeout.subscribe();
+        tokio::pin!(future);
+
+        loop {
+            let expires = timeout
+                .borrow_and_update()
+                .map(|timeout| tokio::time::sleep_until(self.start + timeout));
+            let expired = async {
+                match expires {
+                    Some(sleep) => sleep.await,
+                    None => std::future::pending().await,
+                }
+            };
+
+            tokio::select! {
+                output = &mut future => return output,
+                _ = expired => return Err(error(self.timeout())),
+                // the route moved the deadline, so wait for the new one
+                _ = timeout.changed() => {}
+            }
+        }
+    }
+}
+
+fn error(timeout: Option<Duration>) -> granite::Error {
+    let timeout = timeout.unwrap_or_default().as_secs_f64();
+    granite::Error::new(granite::ErrorKind::Timeout)
+        .add_context(format!("Request was not answered within {timeout} seconds"))
+        .set_transient(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Deadline;
+    use std::time::Duration;
+    use tokio::time::{sleep, Instant};
+
+    const SECOND: Duration = Duration::from_secs(1);
+
+    fn deadline(timeout: Option<Duration>) -> Deadline {
+        let deadline = Deadline::new();
+        deadline.set_timeout(timeout);
+        deadline
+    }
+
+    /// A route which takes `duration` to answer
+    async fn route(duration: Duration) -> granite::Result<&'static str> {
+        sleep(duration).await;
+        Ok("done")
+    }
+
+    fn is_timeout<T>(result: granite::Result<T>) -> bool {
+        matches!(result, Err(error) if matches!(error.kind, granite::ErrorKind::Timeout))
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_in_time() {
+        let deadline = deadline(Some(SECOND * 2));
+        assert_eq!(deadline.run(route(SECOND)).await.unwrap(), "done");
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_timeout() {
+        let deadline = deadline(Some(SECOND * 2));
+        let start = Instant::now();
+        assert!(is_timeout(deadline.run(route(SECOND * 10)).await));
+        assert_eq!(start.elapsed(), SECOND * 2);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_errors_pass_through() {
+        let deadline = deadline(Some(SECOND * 2));
+        let result = deadline
+            .run(async { Err::<(), _>(granite::Error::new(granite::ErrorKind::Validation)) })
+            .await;
+        assert!(matches!(
+            result,
+            Err(error) if matches!(error.kind, granite::ErrorKind::Validation)
+        ));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_no_timeout() {
+        let deadline = deadline(None);
+        let hour = SECOND * 60 * 60;
+        assert_eq!(deadline.run(route(hour)).await.unwrap(), "done");
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_counted_from_start() {
+        let deadline = deadline(Some(SECOND * 2));
+        sleep(SECOND).await;
+
+        let start = Instant::now();
+        assert!(is_timeout(deadline.run(route(SECOND * 10)).await));
+        assert_eq!(start.elapsed(), SECOND);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_route_extends() {
+        let deadline = deadline(Some(SECOND * 2));
+        let result = deadline
+            .run(async {
+                sleep(SECOND).await;
+                deadline.set_timeout(Some(SECOND * 10));
+                route(SECOND * 5).await
+            })
+            .await;
+        assert_eq!(result.unwrap(), "done");
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_route_turns_off() {
+        let deadline = deadline(Some(SECOND * 2));
+        let result = deadline
+            .run(async {
+                sleep(SECOND).await;
+                deadline.set_timeout(None);
+                route(SECOND * 60 * 60).await
+            })
+            .await;
+        assert_eq!(result.unwrap(), "done");
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_route_shortens() {
+        let deadline = deadline(Some(SECOND * 10));
+        let start = Instant::now();
+        let result = deadline
+            .run(async {
+                sleep(SECOND).await;
+                deadline.set_timeout(Some(SECOND * 2));
+                route(SECOND * 5).await
+            })
+            .await;
+        assert!(is_timeout(result));
+        assert_eq!(start.elapsed(), SECOND * 2);
+        assert_eq!(deadline.timeout(), Some(SECOND * 2));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_route_shortens_into_the_past() {
+        let deadline = deadline(Some(SECOND * 10));
+        let start = Instant::now();
+        let result = deadline
+            .run(async {
+                sleep(SECOND * 3).await;
+                deadline.set_timeout(Some(SECOND));
+                route(SECOND * 5).await
+            })
+            .await;
+        assert!(is_timeout(result));
+        assert_eq!(start.elapsed(), SECOND * 3);
+    }
+}
@@ -1,4 +1,5 @@
 use std::borrow::Cow;
+use std::sync::atomic::{AtomicUsize, Ordering};
 
 use granite::ResultExt;
 use salvo_core::{Request, Response};
@@ -110,31 +111,145 @@ impl Message {
 }
 
 /// A websocket connection
-pub struct WebSocket(SalvoWebsocket);
+pub struct WebSocket(Socket);
+
+enum Socket {
+    Salvo(SalvoWebsocket),
+    /// Connected to a [`TestWebSocket`] instead of a client
+    Test(Channel),
+}
 
 impl WebSocket {
     pub async fn send(&mut self, message: Message) -> granite::Result<()> {
-        self.0.send(message.0).await.amend(|mut e| {
-            e.kind = granite::ErrorKind::WebsocketCommunication;
-            e
-        })
+        match &mut self.0 {
+            Socket::Salvo(socket) => socket.send(message.0).await.amend(|mut e| {
+                e.kind = granite::ErrorKind::WebsocketCommunication;
+                e
+            }),
+            Socket::Test(channel) => channel.send(message),
+        }
     }
 
     pub async fn recv(&mut self) -> Option<granite::Result<Message>> {
-        self.0.recv().await.map(|r| {
-            r.map(Message).amend(|mut e| {
+        match &mut self.0 {
+            Socket::Salvo(socket) => socket.recv().await.map(|r| {
+                r.map(Message).amend(|mut e| {
+                    e.kind = granite::ErrorKind::WebsocketCommunication;
+                    e
+                })
+            }),
+            Socket::Test(channel) => channel.recv().await.map(Ok),
+        }
+    }
+
+    pub async fn close(self) -> granite::Result<()> {
+        match self.0 {
+            Socket::Salvo(socket) => socket.close().await.amend(|mut e| {
                 e.kind = granite::ErrorKind::WebsocketCommunication;
                 e
-            })
-        })
+            }),
+            Socket::Test(channel) => channel.close(),
+        }
     }
+}
 
-    pub async fn close(self) -> granite::Result<()> {
-        self.0.close().await.amend(|mut e| {
-            e.kind = granite::ErrorKind::WebsocketCommunication;
-            e
+/// The client end of a websocket opened by [`TestClient`], whose other end is given to the
+/// route's handler
+///
+/// [`TestClient`]: super::test_client::TestClient
+pub struct TestWebSocket(Channel);
+
+impl TestWebSocket {
+    pub fn send(&mut self, message: impl Into<Message>) -> granite::Result<()> {
+        self.0.send(message.into())
+    }
+
+    /// The next message from the handler, or `None` once it has closed the socket or returned
+    pub async fn recv(&mut self) -> Option<Message> {
+        self.0.recv().await
+    }
+
+    pub fn close(self) -> granite::Result<()> {
+        self.0.close()
+    }
+}
+
+/// One end of an in-memory websocket
+struct Channel {
+    sender: tokio::sync::mpsc::UnboundedSender<SalvoMessage>,
+    receiver: tokio::sync::mpsc::UnboundedReceiver<SalvoMessage>,
+}
+
+impl Channel {
+    fn send(&mut self, message: Message) -> granite::Result<()> {
+        self.sender.send(message.0).map_err(|_| {
+            granite::Error::new(granite::ErrorKind::WebsocketCommunication)
+                .add_context("the other end of the websocket is closed")
         })
     }
+
+    async fn recv(&mut self) -> Option<Message> {
+        self.receiver.recv().await.map(Message)
+    }
+
+    fn close(mut self) -> granite::Result<()> {
+        self.send(Message(SalvoMessage::close()))
+    }
+}
+
+/// A connected pair of in-memory websockets, for the route and for the test
+pub(crate) fn test_pair() -> (WebSocket, TestWebSocket) {
+    let (server_sender, client_receiver) = tokio::sync::mpsc::unbounded_channel();
+    let (client_sender, server_receiver) = tokio::sync::mpsc::unbounded_channel();
+    let server = Channel {
+        sender: server_sender,
+        receiver: server_receiver,
+    };
+    let client = Channel {
+        sender: client_sender,
+        receiver: client_receiver,
+    };
+    (WebSocket(Socket::Test(server)), TestWebSocket(client))
+}
+
+/// Put in the extensions of a request by [`TestClient`], so [`upgrade`] gives the handler this
+/// socket rather than upgrading the connection, which a test does not have
+///
+/// [`TestClient`]: super::test_client::TestClient
+#[derive(Clone)]
+pub(crate) struct TestUpgrade(std::sync::Arc<std::sync::Mutex<Option<WebSocket>>>);
+
+impl TestUpgrade {
+    pub(crate) fn new(socket: WebSocket) -> Self {
+        Self(std::sync::Arc::new(std::sync::Mutex::new(Some(socket))))
+    }
+
+    fn take(&self) -> Option<WebSocket> {
+        self.0.lock().unwrap().take()
+    }
+}
+
+/// Number of websocket sessions whose handler is still running.  Used to drain them on shutdown.
+static OPEN_WEBSOCKETS: AtomicUsize = AtomicUsize::new(0);
+
+pub(crate) fn open_websockets() -> usize {
+    OPEN_WEBSOCKETS.load(Ordering::SeqCst)
+}
+
+/// Keeps [`OPEN_WEBSOCKETS`] accurate even if a handler panics.
+struct OpenWebSocketGuard;
+
+impl OpenWebSocketGuard {
+    fn new() -> Self {
+        OPEN_WEBSOCKETS.fetch_add(1, Ordering::SeqCst);
+        Self
+    }
+}
+
+impl Drop for OpenWebSocketGuard {
+    fn drop(&mut self) {
+        OPEN_WEBSOCKETS.fetch_sub(1, Ordering::SeqCst);
+    }
 }
 
 pub async fn upgrade<H, F>(
@@ -146,9 +261,25 @@ where
     F: std::future::Future<Output = ()> + Send + 'static,
 {
     let mut response = Response::new();
+
+    // the test client has no connection to upgrade, but hands over a socket of its own
+    if let Some(socket) = req
+        .extensions()
+        .get::<TestUpgrade>()
+        .and_then(TestUpgrade::take)
+    {
+        tokio::spawn(async move {
+            let _guard = OpenWebSocketGuard::new();
+            handler(socket).await;
+        });
+        response.status_code = Some(http::StatusCode::SWITCHING_PROTOCOLS);
+        return Ok(crate::server::response::WebSocketUpgrade(response));
+    }
+
     SalvoWebSocketUpgrade::new()
         .upgrade(req, &mut response, |ws| async move {
-            handler(WebSocket(ws)).await;
+            let _guard = OpenWebSocketGuard::new();
+            handler(WebSocket(Socket::Salvo(ws))).await;
         })
         .await
         .amend(|mut e| {
@@ -20,8 +20,8 @@ impl Default for Document {
             head: Vec::new(),
             body: Vec::new(),
             tail: Vec::new(),
-            js_list: vec!["/app.js".to_string()],
-            css_list: vec!["/app.css".to_string()],
+            js_list: vec![approck::server::assets::resolve("/app.js")],
+            css_list: vec![approck::server::assets::resolve("/app.css")],
             status: approck::server::StatusCode::OK,
             script_list: Vec::new(),
             style_list: Vec::new(),
@@ -54,12 +54,13 @@ impl approck::traits::Document for Document {
         self.title = Some(title.to_string());
     }
 
+    // link to the hashed paths of compiled-in assets so they can be cached forever
     fn add_js(&mut self, module: &str) {
-        self.js_list.push(module.to_string());
+        self.js_list.push(approck::server::assets::resolve(module));
     }
 
     fn add_css(&mut self, path: &str) {
-        self.css_list.push(path.to_string());
+        self.css_list.push(approck::server::assets::resolve(path));
     }
 
     fn set_status(&mut self, status: approck::server::StatusCode) {
@@ -78,7 +79,9 @@ impl approck::traits::Document for Document {
 #[rustfmt::skip]
 impl maud::Render for Document {
     fn render(&self) -> maud::Markup {
-        
+        // lets the inline scripts and styles run under the Content-Security-Policy
+        let nonce = approck::server::security_headers::csp_nonce();
+
         html! {
             (maud::DOCTYPE)
             html {
@@ -102,7 +105,7 @@ impl maud::Render for Document {
                     }
 
                     @for style in &self.style_list {
-                        style { (style) }
+                        style nonce=[&nonce] { (style) }
                     }
                 }
                 body {
@@ -205,7 +208,7 @@ impl maud::Render for Document {
                 }
 
                 @for script in &self.script_list {
-                    script type="module" { (script) }
+                    script type="module" nonce=[&nonce] { (script) }
                 }
             }
         }
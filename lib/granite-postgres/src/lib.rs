@@ -1,3 +1,4 @@
+pub use granite::PoolStatus;
 pub use tokio_postgres::error::Error as PgError;
 pub use tokio_postgres::types::ToSql;
 pub use tokio_postgres::types::Type as PgType;
@@ -34,6 +35,10 @@ impl PostgresSystem {
     pub async fn get_dbcx(&self) -> granite::Result<DBCX> {
         self.pool.get().await
     }
+
+    pub fn pool_status(&self) -> PoolStatus {
+        self.pool.status()
+    }
 }
 
 pub trait PostgresModule {
@@ -74,6 +79,14 @@ impl PostgresPool {
 
         Ok(DBCX::wrap(conn))
     }
+
+    pub fn status(&self) -> PoolStatus {
+        let state = self.pool.state();
+        PoolStatus {
+            connections: state.connections,
+            idle_connections: state.idle_connections,
+        }
+    }
 }
 
 pub struct DBTX<'a> {
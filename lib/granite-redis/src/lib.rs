@@ -1,3 +1,4 @@
+pub use granite::PoolStatus;
 pub use redis::AsyncCommands;
 use redis::{FromRedisValue, ToRedisArgs};
 
@@ -44,6 +45,14 @@ impl RedisSystem {
     pub async fn get_dbcx(&self) -> granite::Result<RedisCX> {
         self.pool.get().await
     }
+
+    pub fn pool(&self) -> &RedisPool {
+        &self.pool
+    }
+
+    pub fn pool_status(&self) -> PoolStatus {
+        self.pool.status()
+    }
 }
 
 pub trait RedisModule {
@@ -81,6 +90,14 @@ impl RedisPool {
             cx: self.pool.get().await?,
         })
     }
+
+    pub fn status(&self) -> PoolStatus {
+        let state = self.pool.state();
+        PoolStatus {
+            connections: state.connections,
+            idle_connections: state.idle_connections,
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -113,6 +130,16 @@ impl<'a> RedisCX<'a> {
         Ok(())
     }
 
+    pub async fn del(&mut self, key: &str) -> granite::Result<()> {
+        self.cx.del(key).await?;
+        Ok(())
+    }
+
+    pub async fn expire(&mut self, key: &str, seconds: u64) -> granite::Result<()> {
+        self.cx.expire(key, seconds as i64).await?;
+        Ok(())
+    }
+
     pub async fn keys_str(&mut self, pattern: &str) -> granite::Result<Vec<String>> {
         let keys: Vec<String> = self.cx.keys(pattern).await?;
         Ok(keys)
@@ -127,6 +154,21 @@ impl<'a> RedisCX<'a> {
         Ok(())
     }
 
+    /// Like [`set_json`](Self::set_json), but the key expires after `seconds`
+    pub async fn set_json_ex<T>(
+        &mut self,
+        key: &str,
+        value: &T,
+        seconds: u64,
+    ) -> granite::Result<()>
+    where
+        T: serde::Serialize,
+    {
+        let value = serde_json::to_string(value)?;
+        self.cx.set_ex(key, value, seconds).await?;
+        Ok(())
+    }
+
     pub async fn get_json<T>(&mut self, key: &str) -> granite::Result<T>
     where
         T: serde::de::DeserializeOwned,
@@ -347,6 +389,19 @@ impl<'a> RedisCX<'a> {
         Ok(value)
     }
 
+    /// Increment `key` and set it to expire in `seconds`, both in one transaction so the key can
+    /// never be left without an expiry
+    pub async fn incr_expire(&mut self, key: &str, seconds: u64) -> granite::Result<u64> {
+        let (value,): (u64,) = redis::pipe()
+            .atomic()
+            .incr(key, 1)
+            .expire(key, seconds as i64)
+            .ignore()
+            .query_async(&mut *self.cx)
+            .await?;
+        Ok(value)
+    }
+
     pub async fn decr<T>(&mut self, key: &str, delta: T) -> granite::Result<T>
     where
         T: ToRedisArgs + FromRedisValue + Send + Sync,
@@ -354,4 +409,11 @@ impl<'a> RedisCX<'a> {
         let value: T = self.cx.decr(key, delta).await?;
         Ok(value)
     }
+
+    pub async fn ping(&mut self) -> granite::Result<()> {
+        redis::cmd("PING")
+            .query_async::<_, String>(&mut *self.cx)
+            .await?;
+        Ok(())
+    }
 }
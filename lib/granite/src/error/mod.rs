@@ -17,7 +17,9 @@ pub enum ErrorKind {
     Authorization,
     DataNotFound,
     InvalidOperation,
+    PayloadTooLarge,
     ProcessError,
+    Timeout,
     Unexpected,
     Validation,
     WebsocketCommunication,
@@ -32,6 +34,7 @@ pub struct Error {
     is_transient: Option<bool>,
     pub code: Option<String>,
     uri: Option<String>,
+    request_id: Option<String>,
 }
 
 // LUKE: how do we automatically convert from a variety of types, like PgError, or IntOverflowError, type things?
@@ -46,6 +49,7 @@ impl Error {
             is_transient: None,
             code: None,
             uri: None,
+            request_id: None,
         }
     }
 
@@ -67,6 +71,12 @@ impl Error {
         self
     }
 
+    /// The id of the http request this error occurred in, as logged and sent in `X-Request-Id`
+    pub fn set_request_id(mut self, request_id: String) -> Self {
+        self.request_id = Some(request_id);
+        self
+    }
+
     /// Convert this into a [`StdError`]
     pub fn into_std(self) -> StdError {
         self.into()
@@ -97,6 +107,7 @@ impl std::fmt::Debug for Error {
             .field("kind", &self.kind)
             .field("source", &self.source)
             .field("uri", &self.uri)
+            .field("request_id", &self.request_id)
             .field("location", &self.location)
             .field(
                 "context",
@@ -126,6 +137,7 @@ where
             is_transient: None,
             code: None,
             uri: None,
+            request_id: None,
         }
     }
 }
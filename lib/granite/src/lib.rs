@@ -2,8 +2,12 @@ pub use granite_macros::{pg_execute, pg_row, pg_row_vec, pg_value, pg_value_vec}
 
 pub use crate::error::{Error, ErrorKind, Result, ResultExt, StdError};
 
+pub use crate::pool::PoolStatus;
+
 pub use crate::rand::{random_hex, ts_random_hex};
 
 mod error;
 
+mod pool;
+
 mod rand;
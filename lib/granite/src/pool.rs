@@ -0,0 +1,7 @@
+/// How many connections a pool holds, and how many of them are waiting to be used.  Shared by
+/// the postgres and redis pools, so their status can be reported the same way.
+#[derive(Debug, Clone, Copy)]
+pub struct PoolStatus {
+    pub connections: u32,
+    pub idle_connections: u32,
+}
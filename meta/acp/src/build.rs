@@ -243,6 +243,9 @@ pub fn codegen(workspace: &'static Workspace) {
 
         bundle_css(&csspaths);
 
+        // after bundling, so the compressed copies match what is served uncompressed
+        precompress(&allpaths);
+
         for (abs_path, web_path) in allpaths.iter() {
             println!("esbuild output: {} -> {}", web_path, abs_path);
         }
@@ -351,3 +354,38 @@ fn bundle_css(paths: &[PathBuf]) {
         std::fs::write(path, css.code).unwrap();
     }
 }
+
+/// Write `.br`, `.zst` and `.gz` copies next to each asset, compressed as hard as possible since
+/// this only happens once.  The router serves them instead of compressing on every request.
+fn precompress(paths: &[(String, String)]) {
+    for (abs_path, _) in paths {
+        let content = std::fs::read(abs_path).expect("Error reading esbuild output");
+
+        let brotli = {
+            let mut writer = brotli::CompressorWriter::new(Vec::new(), 4096, 11, 22);
+            writer
+                .write_all(&content)
+                .expect("Error compressing with brotli");
+            writer.into_inner()
+        };
+
+        let zstd = zstd::encode_all(&content[..], 19).expect("Error compressing with zstd");
+
+        let gzip = {
+            let mut encoder =
+                flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::best());
+            encoder
+                .write_all(&content)
+                .expect("Error compressing with gzip");
+            encoder.finish().expect("Error compressing with gzip")
+        };
+
+        for (suffix, compressed) in [("br", brotli), ("zst", zstd), ("gz", gzip)] {
+            // tiny files can grow, in which case the original is served to everyone
+            if compressed.len() < content.len() {
+                std::fs::write(format!("{}.{}", abs_path, suffix), compressed)
+                    .expect("Error writing compressed esbuild output");
+            }
+        }
+    }
+}